@@ -0,0 +1,350 @@
+// The async client. Requires the `async` feature.
+//
+// The URLs and query parameters are built exactly as in the blocking client,
+// through the Urls, ApiUrl and SearchUrl traits. Only sending the request
+// differs.
+use crate::client::{flavors, product_url, ApiUrl, Error, SearchUrl, Urls};
+use crate::flavor::Flavor;
+use crate::locale::Locale;
+use crate::output::Output;
+use crate::pages::AsyncPaginator;
+use crate::product::{Product, ProductResponse};
+use crate::response::{api_status, decode_async};
+use crate::retry::RequestPolicy;
+use crate::search::{SearchQueryV0, SearchQueryV2};
+use crate::server::{join, with_params, Server};
+use crate::taxonomy::Taxonomy;
+use crate::types::{Params, Version, V0, V2};
+
+pub use reqwest::{Client as AsyncHttpClient, Response as AsyncHttpResponse};
+use url::{ParseError, Url};
+
+/// The return type of all AsyncOffClient methods.
+pub type AsyncResult = std::result::Result<AsyncHttpResponse, Error>;
+
+/// The async OFF API client.
+///
+/// Same as [crate::OffClient] but built on the non-blocking [reqwest::Client].
+/// Obtain it with [crate::OffBuilder::build_async].
+///
+/// ```no_run
+/// use openfoodfacts as off;
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), off::Error> {
+/// let client = off::v2().build_async()?;
+/// let response = client.product("3017620422003", None).await?;
+/// assert!(response.status().is_success());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct AsyncOffClient<V> {
+    // The version marker.
+    v: V,
+    // The default locale to use when no locale is given in a method call.
+    locale: Locale,
+    // The server to send the requests to.
+    server: Server,
+    // The rate limits, shared by all the tasks using the client, and the
+    // retries of the GET requests.
+    policy: RequestPolicy,
+    // The underlying reqwest client.
+    client: AsyncHttpClient,
+}
+
+impl<V> Version for AsyncOffClient<V>
+where
+    V: Version,
+{
+    fn version(&self) -> &str {
+        self.v.version()
+    }
+}
+
+impl<V> Urls for AsyncOffClient<V>
+where
+    V: Version,
+{
+    fn host_with_locale(&self, locale: Option<&Locale>) -> std::result::Result<Url, ParseError> {
+        self.server.host_url(locale.unwrap_or(&self.locale))
+    }
+}
+
+impl<V> ApiUrl for AsyncOffClient<V> where V: Version {}
+
+impl<V> AsyncOffClient<V>
+where
+    V: Version + Copy,
+{
+    /// Async version of [crate::OffClient::taxonomy].
+    pub async fn taxonomy(&self, taxonomy: &str) -> AsyncResult {
+        let base_url = self.base_url_world()?; // force world locale.
+        let url = join(&base_url, &format!("data/taxonomies/{}.json", taxonomy))?;
+        self.get(url, None).await
+    }
+
+    /// Async version of [crate::OffClient::taxonomy_nocache].
+    pub async fn taxonomy_nocache(&self, taxonomy: &str) -> AsyncResult {
+        let base_url = self.base_url_world()?; // force world locale.
+        let url = join(&base_url, &format!("data/taxonomies/{}.json", taxonomy))?;
+        let params = Output::new().nocache(true).params(&["nocache"]);
+        self.get(url, Some(&params)).await
+    }
+
+    /// Async version of [crate::OffClient::taxonomy_typed].
+    pub async fn taxonomy_typed(&self, taxonomy: &str) -> std::result::Result<Taxonomy, Error> {
+        decode_async(self.taxonomy(taxonomy).await?).await
+    }
+
+    /// Async version of [crate::OffClient::facet].
+    pub async fn facet(&self, facet: &str, output: Option<Output>) -> AsyncResult {
+        let base_url = self.base_url(output.as_ref().and_then(|o| o.locale.as_ref()))?;
+        let url = join(&base_url, &format!("{}.json", facet))?;
+        let params = output.map(|o| o.params(&["page", "page_size", "fields", "nocache"]));
+        self.get(url, params.as_ref()).await
+    }
+
+    /// Async version of [crate::OffClient::categories].
+    pub async fn categories(&self, output: Option<Output>) -> AsyncResult {
+        let base_url = self.base_url(output.as_ref().and_then(|o| o.locale.as_ref()))?;
+        let url = join(&base_url, "categories.json")?;
+        let params = output.map(|o| o.params(&["nocache"]));
+        self.get(url, params.as_ref()).await
+    }
+
+    /// Async version of [crate::OffClient::nutrients].
+    pub async fn nutrients(&self, output: Option<Output>) -> AsyncResult {
+        let cgi_url = self.cgi_url(output.as_ref().and_then(|o| o.locale.as_ref()))?;
+        let url = join(&cgi_url, "nutrients.pl")?;
+        let params = output.map(|o| o.params(&["nocache"]));
+        self.get(url, params.as_ref()).await
+    }
+
+    /// Async version of [crate::OffClient::products_by].
+    pub async fn products_by(&self, what: &str, id: &str, output: Option<Output>) -> AsyncResult {
+        let base_url = self.base_url(output.as_ref().and_then(|o| o.locale.as_ref()))?;
+        let url = join(&base_url, &format!("{}/{}.json", what, id))?;
+        let params = output.map(|o| o.params(&["page", "page_size", "fields", "nocache"]));
+        self.get(url, params.as_ref()).await
+    }
+
+    /// Async version of [crate::OffClient::products_by_iter].
+    pub fn products_by_stream(
+        &self,
+        what: &str,
+        id: &str,
+        output: Option<Output>,
+    ) -> std::result::Result<AsyncPaginator<'_, V>, Error>
+    where
+        V: Sync,
+    {
+        let base_url = self.base_url(output.as_ref().and_then(|o| o.locale.as_ref()))?;
+        let url = join(&base_url, &format!("{}/{}.json", what, id))?;
+        Ok(AsyncPaginator::new(self, url, Params::new(), output))
+    }
+
+    /// Async version of [crate::OffClient::product].
+    pub async fn product(&self, barcode: impl AsRef<str>, output: Option<Output>) -> AsyncResult {
+        let api_url = self.api_url(output.as_ref().and_then(|o| o.locale.as_ref()))?;
+        let url = join(&api_url, &format!("product/{}", barcode.as_ref()))?;
+        let params = output.map(|o| o.params(&["fields", "nocache"]));
+        self.get(url, params.as_ref()).await
+    }
+
+    /// Async version of [crate::OffClient::product_typed].
+    pub async fn product_typed(
+        &self,
+        barcode: impl AsRef<str>,
+        output: Option<Output>,
+    ) -> std::result::Result<ProductResponse, Error> {
+        let response =
+            decode_async::<ProductResponse>(self.product(barcode, output).await?).await?;
+        if self.policy.error_for_status {
+            api_status(response.status, &response.status_verbose)?;
+        }
+        Ok(response)
+    }
+
+    /// Async version of [crate::OffClient::product_any_flavor].
+    pub async fn product_any_flavor(
+        &self,
+        barcode: impl AsRef<str>,
+        output: Option<Output>,
+    ) -> std::result::Result<Option<(Flavor, Product)>, Error> {
+        let locale = output
+            .as_ref()
+            .and_then(|o| o.locale.as_ref())
+            .unwrap_or(&self.locale);
+        let params = output.as_ref().map(|o| o.params(&["fields", "nocache"]));
+        for flavor in flavors(&self.server) {
+            let server = self.server.with_flavor(flavor);
+            let url = product_url(&server, self.version(), locale, barcode.as_ref())?;
+            let response = match self.get(url, params.as_ref()).await {
+                Err(Error::NotFound(_)) => continue,
+                response => response?,
+            };
+            if let Some(product) = decode_async::<ProductResponse>(response).await?.product {
+                return Ok(Some((flavor, product)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the client flavor.
+    pub fn flavor(&self) -> Flavor {
+        self.server.flavor
+    }
+
+    pub(crate) fn new(
+        v: V,
+        locale: Locale,
+        server: Server,
+        policy: RequestPolicy,
+        client: AsyncHttpClient,
+    ) -> Self {
+        Self {
+            v,
+            locale,
+            server,
+            policy,
+            client,
+        }
+    }
+
+    // Builds and sends a GET request. Goes through the cache, waits for the
+    // rate limiter of the endpoint and retries transient failures.
+    pub(crate) async fn get(&self, url: Url, params: Option<&Params<'_>>) -> AsyncResult {
+        let url = with_params(url, params);
+        self.policy
+            .get_async(&url, |headers| {
+                self.client.get(url.clone()).headers(headers).send()
+            })
+            .await
+    }
+}
+
+impl AsyncOffClient<V0> {
+    /// Returns the query builder for API V0.
+    pub fn query(&self) -> SearchQueryV0 {
+        SearchQueryV0::new()
+    }
+
+    /// Async version of [crate::OffClient::search].
+    pub async fn search(&self, query: SearchQueryV0, output: Option<Output>) -> AsyncResult {
+        let (url, params) = SearchQueryV0::request(&query, self, output)?;
+        self.get(url, Some(&params)).await
+    }
+
+    /// Async version of [crate::OffClient::search_iter].
+    pub fn search_stream(
+        &self,
+        query: SearchQueryV0,
+        output: Option<Output>,
+    ) -> std::result::Result<AsyncPaginator<'_, V0>, Error> {
+        let (url, params) = SearchQueryV0::request(&query, self, output.clone())?;
+        Ok(AsyncPaginator::new(self, url, params, output))
+    }
+}
+
+impl SearchUrl for AsyncOffClient<V0> {
+    /// Returns the API V0 search URL.
+    ///
+    /// `https://{locale}.openfoodfacts.org/cgi/search.pl`
+    fn search_url(&self, locale: Option<&Locale>) -> std::result::Result<Url, ParseError> {
+        let cgi_url = self.cgi_url(locale)?;
+        join(&cgi_url, "search.pl")
+    }
+}
+
+impl AsyncOffClient<V2> {
+    /// Returns the query builder for API V2.
+    pub fn query(&self) -> SearchQueryV2 {
+        SearchQueryV2::new()
+    }
+
+    /// Async version of [crate::OffClient::search].
+    pub async fn search(&self, query: SearchQueryV2, output: Option<Output>) -> AsyncResult {
+        let (url, params) = SearchQueryV2::request(&query, self, output)?;
+        self.get(url, Some(&params)).await
+    }
+
+    /// Async version of [crate::OffClient::search_iter].
+    pub fn search_stream(
+        &self,
+        query: SearchQueryV2,
+        output: Option<Output>,
+    ) -> std::result::Result<AsyncPaginator<'_, V2>, Error> {
+        let (url, params) = SearchQueryV2::request(&query, self, output.clone())?;
+        Ok(AsyncPaginator::new(self, url, params, output))
+    }
+
+    /// Async version of [crate::OffClient::products].
+    pub async fn products(&self, barcodes: &str, output: Option<Output>) -> AsyncResult {
+        let url = self.search_url(output.as_ref().and_then(|o| o.locale.as_ref()))?;
+        let mut params = Params::new();
+        params.push(("code", String::from(barcodes)));
+        if let Some(output_params) = output.map(|o| o.params(&["fields", "nocache"])) {
+            params.extend(output_params);
+        }
+        self.get(url, Some(&params)).await
+    }
+}
+
+impl SearchUrl for AsyncOffClient<V2> {
+    /// Returns the API V2 search URL.
+    ///
+    /// `https://{locale}.openfoodfacts.org/api/v2/search`
+    fn search_url(&self, locale: Option<&Locale>) -> std::result::Result<Url, ParseError> {
+        let api_url = self.api_url(locale)?;
+        join(&api_url, "search")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version() {
+        let client_v0 = crate::v0().build_async().unwrap();
+        assert_eq!(client_v0.version(), "v0");
+
+        let client_v2 = crate::v2().build_async().unwrap();
+        assert_eq!(client_v2.version(), "v2");
+    }
+
+    #[test]
+    fn base_url_locale() {
+        let client = crate::v0()
+            .locale(Locale::new("fr", None))
+            .build_async()
+            .unwrap();
+        assert_eq!(
+            client.base_url(None).unwrap().as_str(),
+            "https://fr.openfoodfacts.org/"
+        );
+        assert_eq!(
+            client.base_url_world().unwrap().as_str(),
+            "https://world.openfoodfacts.org/"
+        );
+    }
+
+    #[test]
+    fn search_url() {
+        let client_v0 = crate::v0().build_async().unwrap();
+        assert_eq!(
+            client_v0
+                .search_url(Some(&Locale::new("gr", None)))
+                .unwrap()
+                .as_str(),
+            "https://gr.openfoodfacts.org/cgi/search.pl"
+        );
+
+        let client_v2 = crate::v2().build_async().unwrap();
+        assert_eq!(
+            client_v2.search_url(None).unwrap().as_str(),
+            "https://world.openfoodfacts.org/api/v2/search"
+        );
+    }
+}
@@ -0,0 +1,343 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// The barcode formats.
+///
+/// # Variants:
+///
+/// * Ean8 - 8 digits EAN-8.
+/// * Ean13 - 13 digits EAN-13.
+/// * UpcA - 12 digits UPC-A.
+/// * UpcE - 8 digits zero-suppressed UPC-E: number system 0 or 1, 6 digits
+///   and the check digit of the equivalent UPC-A.
+/// * Gtin14 - 14 digits GTIN-14.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BarcodeKind {
+    Ean8,
+    Ean13,
+    UpcA,
+    UpcE,
+    Gtin14,
+}
+
+/// The restricted circulation barcodes, not unique worldwide.
+///
+/// # Variants:
+///
+/// * InStore - Codes assigned by a retailer for its own stores: EAN-13
+///   prefixes 20 to 29 and EAN-8 prefixes 0 and 2.
+/// * VariableWeight - Codes embedding a weight or a price: UPC-A number
+///   system 2 (EAN-13 prefix 02) and GTIN-14 indicator digit 9. They are also
+///   assigned in store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Restriction {
+    InStore,
+    VariableWeight,
+}
+
+/// The barcode parsing errors.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum BarcodeError {
+    #[error("invalid character '{0}' in barcode")]
+    InvalidCharacter(char),
+
+    #[error("invalid barcode length {0}")]
+    InvalidLength(usize),
+
+    #[error("invalid check digit {found}, expected {expected}")]
+    InvalidCheckDigit { expected: u8, found: u8 },
+
+    #[error("invalid UPC-E number system {0}")]
+    InvalidNumberSystem(u8),
+}
+
+/// A validated product barcode.
+///
+/// Barcodes are parsed from EAN-8, EAN-13, UPC-A, UPC-E or GTIN-14 codes and
+/// their check digit is verified. The code is normalized the way OFF stores
+/// it: UPC-E codes are expanded to UPC-A, leading zeros are removed, then
+/// codes are padded with zeros to 8 digits, or to 13 digits if longer than 8
+/// digits. GTIN-14 codes with a non-zero indicator digit keep their 14
+/// digits.
+///
+/// The client methods taking a barcode accept a [Barcode] as well as a string.
+///
+/// ```
+/// use openfoodfacts::{Barcode, BarcodeKind};
+///
+/// let barcode: Barcode = "036000291452".parse().unwrap();
+/// assert_eq!(barcode.kind(), BarcodeKind::UpcA);
+/// assert_eq!(barcode.as_str(), "0036000291452");
+///
+/// let upc_e = Barcode::upc_e("04252614").unwrap();
+/// assert_eq!(upc_e.as_str(), "0042100005264");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Barcode {
+    kind: BarcodeKind,
+    code: String,
+}
+
+impl Barcode {
+    /// Parses the given code. Surrounding whitespace is ignored.
+    ///
+    /// 12, 13 and 14 digits codes are read as UPC-A, EAN-13 and GTIN-14. 8
+    /// digits codes are read as EAN-8, or as UPC-E if their check digit is
+    /// only valid for UPC-E. Use [Barcode::upc_e] to read an ambiguous code as
+    /// UPC-E.
+    pub fn parse(code: &str) -> Result<Self, BarcodeError> {
+        let digits = digits(code)?;
+        let kind = match digits.len() {
+            8 => match check(&digits) {
+                Ok(()) => BarcodeKind::Ean8,
+                Err(err) => return Self::upc_e(code).map_err(|_| err),
+            },
+            12 => BarcodeKind::UpcA,
+            13 => BarcodeKind::Ean13,
+            14 => BarcodeKind::Gtin14,
+            len => return Err(BarcodeError::InvalidLength(len)),
+        };
+        check(&digits)?;
+        Ok(Self {
+            kind,
+            code: normalize(&digits),
+        })
+    }
+
+    /// Parses the given 8 digits UPC-E code and expands it to UPC-A.
+    pub fn upc_e(code: &str) -> Result<Self, BarcodeError> {
+        let digits = digits(code)?;
+        if digits.len() != 8 {
+            return Err(BarcodeError::InvalidLength(digits.len()));
+        }
+        let upc_a = upc_e_to_upc_a(&digits)?;
+        check(&upc_a)?;
+        Ok(Self {
+            kind: BarcodeKind::UpcE,
+            code: normalize(&upc_a),
+        })
+    }
+
+    /// Returns the format the barcode was parsed from.
+    pub fn kind(&self) -> BarcodeKind {
+        self.kind
+    }
+
+    /// Returns the normalized code.
+    pub fn as_str(&self) -> &str {
+        &self.code
+    }
+
+    /// Returns the restriction of the barcode, None for the barcodes unique
+    /// worldwide.
+    pub fn restriction(&self) -> Option<Restriction> {
+        let code = self.code.as_bytes();
+        match (code.len(), code[0], code[1]) {
+            (14, b'9', _) => Some(Restriction::VariableWeight),
+            (13, b'0', b'2') => Some(Restriction::VariableWeight),
+            (13, b'2', _) => Some(Restriction::InStore),
+            (8, b'0', _) | (8, b'2', _) => Some(Restriction::InStore),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the barcode is assigned in store or embeds a weight or
+    /// a price. See [Restriction].
+    pub fn is_restricted(&self) -> bool {
+        self.restriction().is_some()
+    }
+}
+
+impl FromStr for Barcode {
+    type Err = BarcodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl Display for Barcode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.code)
+    }
+}
+
+impl AsRef<str> for Barcode {
+    fn as_ref(&self) -> &str {
+        &self.code
+    }
+}
+
+// Returns the digits of the code.
+fn digits(code: &str) -> Result<Vec<u8>, BarcodeError> {
+    code.trim()
+        .chars()
+        .map(|c| match c.to_digit(10) {
+            Some(d) => Ok(d as u8),
+            None => Err(BarcodeError::InvalidCharacter(c)),
+        })
+        .collect()
+}
+
+// Checks the GS1 check digit, the last digit.
+fn check(digits: &[u8]) -> Result<(), BarcodeError> {
+    let (found, digits) = digits.split_last().expect("non empty barcode");
+    // Weights 3 and 1 alternate from the rightmost digit.
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| u32::from(*d) * if i % 2 == 0 { 3 } else { 1 })
+        .sum();
+    let expected = ((10 - sum % 10) % 10) as u8;
+    if expected == *found {
+        Ok(())
+    } else {
+        Err(BarcodeError::InvalidCheckDigit {
+            expected,
+            found: *found,
+        })
+    }
+}
+
+// Expands the 8 digits UPC-E code to the 12 digits UPC-A code.
+fn upc_e_to_upc_a(digits: &[u8]) -> Result<Vec<u8>, BarcodeError> {
+    let system = digits[0];
+    if system > 1 {
+        return Err(BarcodeError::InvalidNumberSystem(system));
+    }
+    let d = &digits[1..7];
+    let manufacturer_product: [u8; 10] = match d[5] {
+        0..=2 => [d[0], d[1], d[5], 0, 0, 0, 0, d[2], d[3], d[4]],
+        3 => [d[0], d[1], d[2], 0, 0, 0, 0, 0, d[3], d[4]],
+        4 => [d[0], d[1], d[2], d[3], 0, 0, 0, 0, 0, d[4]],
+        _ => [d[0], d[1], d[2], d[3], d[4], 0, 0, 0, 0, d[5]],
+    };
+    let mut upc_a = vec![system];
+    upc_a.extend_from_slice(&manufacturer_product);
+    upc_a.push(digits[7]);
+    Ok(upc_a)
+}
+
+// Normalizes the code as OFF does: leading zeros are removed, then the code
+// is padded to 8 digits, or to 13 digits if longer than 8 digits.
+fn normalize(digits: &[u8]) -> String {
+    let code: String = digits
+        .iter()
+        .skip_while(|d| **d == 0)
+        .map(|d| char::from(b'0' + d))
+        .collect();
+    let width = if code.len() <= 8 { 8 } else { 13 };
+    format!("{:0>width$}", code, width = width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(code: &str) -> (BarcodeKind, String) {
+        let barcode = Barcode::parse(code).unwrap();
+        (barcode.kind, barcode.code)
+    }
+
+    #[test]
+    fn kinds() {
+        assert_eq!(
+            parse("3017620422003"),
+            (BarcodeKind::Ean13, String::from("3017620422003"))
+        );
+        assert_eq!(
+            parse(" 96385074 "),
+            (BarcodeKind::Ean8, String::from("96385074"))
+        );
+        assert_eq!(
+            parse("036000291452"),
+            (BarcodeKind::UpcA, String::from("0036000291452"))
+        );
+        assert_eq!(
+            parse("00012345600012"),
+            (BarcodeKind::Gtin14, String::from("0012345600012"))
+        );
+        assert_eq!(
+            parse("10012345600019"),
+            (BarcodeKind::Gtin14, String::from("10012345600019"))
+        );
+        // Valid as UPC-E only.
+        assert_eq!(
+            parse("04252614"),
+            (BarcodeKind::UpcE, String::from("0042100005264"))
+        );
+    }
+
+    #[test]
+    fn upc_e() {
+        let expand = |code: &str| Barcode::upc_e(code).unwrap().code;
+        assert_eq!(expand("04252614"), "0042100005264");
+        assert_eq!(expand("01234505"), "0012000003455");
+        assert_eq!(expand("01234531"), "0012300000451");
+        assert_eq!(expand("01234543"), "0012340000053");
+        assert_eq!(expand("01234558"), "0012345000058");
+        assert_eq!(
+            Barcode::upc_e("21234558"),
+            Err(BarcodeError::InvalidNumberSystem(2))
+        );
+        assert_eq!(
+            Barcode::upc_e("3017620422003"),
+            Err(BarcodeError::InvalidLength(13))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Barcode::parse("3017620422004"),
+            Err(BarcodeError::InvalidCheckDigit {
+                expected: 3,
+                found: 4
+            })
+        );
+        assert_eq!(
+            Barcode::parse("96385075"),
+            Err(BarcodeError::InvalidCheckDigit {
+                expected: 4,
+                found: 5
+            })
+        );
+        assert_eq!(
+            Barcode::parse("3017-620422003"),
+            Err(BarcodeError::InvalidCharacter('-'))
+        );
+        assert_eq!(Barcode::parse("12345"), Err(BarcodeError::InvalidLength(5)));
+        assert_eq!(Barcode::parse(""), Err(BarcodeError::InvalidLength(0)));
+    }
+
+    #[test]
+    fn normalization() {
+        assert_eq!(
+            normalize(&[0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9]),
+            "0000123456789"
+        );
+        assert_eq!(normalize(&[0, 0, 0, 0, 0, 1, 2, 3]), "00000123");
+        assert_eq!(
+            normalize(&[0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8]),
+            "12345678"
+        );
+    }
+
+    #[test]
+    fn restrictions() {
+        let restriction = |code: &str| Barcode::parse(code).unwrap().restriction();
+        assert_eq!(restriction("3017620422003"), None);
+        assert_eq!(restriction("2001234567893"), Some(Restriction::InStore));
+        assert_eq!(
+            restriction("212345678909"),
+            Some(Restriction::VariableWeight)
+        );
+        assert_eq!(
+            restriction("98712345678909"),
+            Some(Restriction::VariableWeight)
+        );
+        assert_eq!(restriction("20123451"), Some(Restriction::InStore));
+        assert_eq!(restriction("96385074"), None);
+    }
+}
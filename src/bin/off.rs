@@ -0,0 +1,630 @@
+//! The `off` command-line tool: looks up products, searches, facets and
+//! taxonomies from the shell. Requires the `cli` feature.
+//!
+//! ```text
+//! off product 3017620422003 --format table
+//! off search --criteria categories=en:cheeses --nutrient "salt_100g<1" --sort-by popularity
+//! off facet brands --locale fr --page-size 10 --format csv
+//! ```
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use openfoodfacts::search::SortBy;
+use openfoodfacts::{self as off, Error, HttpClient, Locale, OffClient, Output, Transport, V0, V2};
+use serde_json::{Map, Value};
+use std::io::{self, Write};
+use std::process::ExitCode;
+
+// The maximum width of the table columns, in characters.
+const MAX_WIDTH: usize = 40;
+
+/// Looks up Open Food Facts products.
+#[derive(Debug, Parser)]
+#[command(name = "off", version)]
+struct Cli {
+    /// The locale, as `{cc}` or `{cc}-{lc}`, i.e. `fr` or `be-nl`.
+    #[arg(long, global = true, default_value = "world", value_parser = parse_locale)]
+    locale: Locale,
+
+    /// The comma-separated fields to return, i.e. `code,product_name`. Also
+    /// the columns of the table and CSV formats.
+    #[arg(long, global = true)]
+    fields: Option<String>,
+
+    /// The page of results.
+    #[arg(long, global = true)]
+    page: Option<usize>,
+
+    /// The number of results per page.
+    #[arg(long, global = true)]
+    page_size: Option<usize>,
+
+    /// The API version.
+    #[arg(long, global = true, value_enum, default_value_t = Api::V2)]
+    api: Api,
+
+    /// The output format.
+    #[arg(long, global = true, value_enum, default_value_t = Format::Json)]
+    format: Format,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Gets a product by barcode.
+    Product { barcode: String },
+
+    /// Searches products. Requires `--api v2`.
+    Search {
+        /// A criterion `NAME[:LC]=VALUE`, i.e. `categories=en:cheeses` or
+        /// `brands:fr=nestlé`. Repeat for several criteria.
+        #[arg(long = "criteria", value_name = "NAME[:LC]=VALUE", value_parser = parse_criterion)]
+        criteria: Vec<Criterion>,
+
+        /// A nutrient condition `NUTRIENT_UNIT{=,<,>,<=,>=}VALUE`, where UNIT
+        /// is `100g` or `serving`, i.e. `sugars_100g<5`.
+        #[arg(long = "nutrient", value_name = "CONDITION", value_parser = parse_nutrient)]
+        nutrients: Vec<Nutrient>,
+
+        /// The sorting order.
+        #[arg(long, value_enum)]
+        sort_by: Option<Sort>,
+    },
+
+    /// Gets a facet, i.e. `brands` or `labels`.
+    Facet { name: String },
+
+    /// Gets a taxonomy, i.e. `nova_groups` or `additives`.
+    Taxonomy { name: String },
+
+    /// Gets the categories.
+    Categories,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum Api {
+    V0,
+    V2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    Json,
+    Table,
+    Csv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum Sort {
+    Popularity,
+    ProductName,
+    CreatedDate,
+    LastModifiedDate,
+    EcoScore,
+}
+
+impl From<Sort> for SortBy {
+    fn from(sort: Sort) -> Self {
+        match sort {
+            Sort::Popularity => Self::Popularity,
+            Sort::ProductName => Self::ProductName,
+            Sort::CreatedDate => Self::CreatedDate,
+            Sort::LastModifiedDate => Self::LastModifiedDate,
+            Sort::EcoScore => Self::EcoScore,
+        }
+    }
+}
+
+// A parameter of SearchQueryV2::criteria.
+#[derive(Debug, Clone, PartialEq)]
+struct Criterion {
+    name: String,
+    lc: Option<String>,
+    value: String,
+}
+
+// The parameters of SearchQueryV2::nutrient.
+#[derive(Debug, Clone, PartialEq)]
+struct Nutrient {
+    nutrient: String,
+    unit: String,
+    op: String,
+    value: u32,
+}
+
+// The kind of JSON response, giving its rows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    // A product response: one row.
+    Product,
+    // A search response: a row per product.
+    Products,
+    // A facet: a row per tag.
+    Tags,
+    // A taxonomy: a row per node.
+    Taxonomy,
+}
+
+impl Kind {
+    // The columns of the table and CSV formats without `--fields`.
+    fn columns(self) -> &'static [&'static str] {
+        match self {
+            Self::Product | Self::Products => &[
+                "code",
+                "product_name",
+                "brands",
+                "quantity",
+                "nutriscore_grade",
+                "nova_group",
+            ],
+            Self::Tags => &["id", "name", "products"],
+            Self::Taxonomy => &["id", "name", "parents"],
+        }
+    }
+}
+
+// A client of either API version.
+enum Client<T = HttpClient> {
+    V0(OffClient<V0, T>),
+    V2(OffClient<V2, T>),
+}
+
+// Calls the same method on the client of either version.
+macro_rules! dispatch {
+    ($client:expr, $c:ident => $call:expr) => {
+        match $client {
+            Client::V0($c) => $call,
+            Client::V2($c) => $call,
+        }
+    };
+}
+
+impl Cli {
+    // Checks the arguments clap cannot check.
+    fn check(&self) -> Result<(), clap::Error> {
+        if matches!(self.command, Command::Search { .. }) && self.api == Api::V0 {
+            let message = "search requires --api v2";
+            return Err(Cli::command().error(ErrorKind::ArgumentConflict, message));
+        }
+        Ok(())
+    }
+
+    fn client(&self) -> Result<Client, Error> {
+        let client = match self.api {
+            Api::V0 => Client::V0(
+                off::v0()
+                    .locale(self.locale.clone())
+                    .error_for_status(true)
+                    .build()?,
+            ),
+            Api::V2 => Client::V2(
+                off::v2()
+                    .locale(self.locale.clone())
+                    .error_for_status(true)
+                    .build()?,
+            ),
+        };
+        Ok(client)
+    }
+
+    fn output(&self) -> Output {
+        let mut output = Output::new().locale(self.locale.clone());
+        if let Some(page) = self.page {
+            output = output.page(page);
+        }
+        if let Some(page_size) = self.page_size {
+            output = output.page_size(page_size);
+        }
+        if let Some(ref fields) = self.fields {
+            // Output::fields takes a static str. The tool sends a single
+            // request.
+            output = output.fields(Box::leak(fields.clone().into_boxed_str()));
+        }
+        output
+    }
+
+    fn columns(&self, kind: Kind) -> Vec<String> {
+        match self.fields {
+            Some(ref fields) => fields.split(',').map(String::from).collect(),
+            None => kind.columns().iter().map(|c| c.to_string()).collect(),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    if let Err(err) = cli.check() {
+        err.exit();
+    }
+    let result = cli
+        .client()
+        .and_then(|client| run(&client, &cli, &mut io::stdout().lock()));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Sends the request of the command and writes the response to `out`.
+fn run<T: Transport>(client: &Client<T>, cli: &Cli, out: &mut dyn Write) -> Result<(), Error> {
+    let output = cli.output();
+    let (response, kind) = match cli.command {
+        Command::Product { ref barcode } => (
+            dispatch!(client, c => c.product(barcode, Some(output)))?,
+            Kind::Product,
+        ),
+        Command::Search {
+            ref criteria,
+            ref nutrients,
+            sort_by,
+        } => {
+            let client = match client {
+                Client::V2(client) => client,
+                Client::V0(_) => unreachable!("rejected by Cli::check"),
+            };
+            let mut query = client.query();
+            for c in criteria {
+                query = query.criteria(&c.name, &c.value, c.lc.as_deref());
+            }
+            for n in nutrients {
+                query = query.nutrient(&n.nutrient, &n.unit, &n.op, n.value);
+            }
+            if let Some(sort_by) = sort_by {
+                query = query.sort_by(sort_by.into());
+            }
+            (client.search(query, Some(output))?, Kind::Products)
+        }
+        Command::Facet { ref name } => (
+            dispatch!(client, c => c.facet(name, Some(output)))?,
+            Kind::Tags,
+        ),
+        Command::Taxonomy { ref name } => {
+            (dispatch!(client, c => c.taxonomy(name))?, Kind::Taxonomy)
+        }
+        Command::Categories => (
+            dispatch!(client, c => c.categories(Some(output)))?,
+            Kind::Tags,
+        ),
+    };
+    let json: Value = serde_json::from_slice(&response.bytes()?).map_err(|err| Error::Decode {
+        path: String::new(),
+        source: err,
+    })?;
+    match cli.format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, &json).map_err(io::Error::from)?;
+            writeln!(out)?;
+        }
+        Format::Table => {
+            let columns = cli.columns(kind);
+            write_table(out, &columns, &rows(kind, &json, &cli.locale, &columns))?;
+        }
+        Format::Csv => {
+            let columns = cli.columns(kind);
+            write_csv(out, &columns, &rows(kind, &json, &cli.locale, &columns))?;
+        }
+    }
+    Ok(())
+}
+
+// Returns the cells of the rows of the response.
+fn rows(kind: Kind, json: &Value, locale: &Locale, columns: &[String]) -> Vec<Vec<String>> {
+    let cells = |object: &Map<String, Value>| -> Vec<String> {
+        columns.iter().map(|c| cell(object.get(c))).collect()
+    };
+    match kind {
+        Kind::Product => json["product"].as_object().map(cells).into_iter().collect(),
+        Kind::Products => items(&json["products"]).map(cells).collect(),
+        Kind::Tags => items(&json["tags"]).map(cells).collect(),
+        Kind::Taxonomy => {
+            let nodes = json.as_object().into_iter().flatten();
+            nodes
+                .map(|(id, node)| {
+                    // The name in the language of the locale, in English
+                    // otherwise.
+                    let name = locale
+                        .lc
+                        .as_deref()
+                        .and_then(|lc| node["name"].get(lc))
+                        .or_else(|| node["name"].get("en"))
+                        .cloned()
+                        .unwrap_or(Value::Null);
+                    let mut row = node.as_object().cloned().unwrap_or_default();
+                    row.insert(String::from("id"), Value::String(id.clone()));
+                    row.insert(String::from("name"), name);
+                    cells(&row)
+                })
+                .collect()
+        }
+    }
+}
+
+fn items(json: &Value) -> impl Iterator<Item = &Map<String, Value>> {
+    json.as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object)
+}
+
+// Formats a JSON value as a cell. Lists are comma-separated.
+fn cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(values)) => values
+            .iter()
+            .map(|v| cell(Some(v)))
+            .collect::<Vec<_>>()
+            .join(","),
+        Some(value) => value.to_string(),
+    }
+}
+
+// Writes the rows as a table with aligned columns. Long cells are truncated.
+fn write_table(out: &mut dyn Write, columns: &[String], rows: &[Vec<String>]) -> io::Result<()> {
+    let truncate = |s: &str| -> String {
+        if s.chars().count() > MAX_WIDTH {
+            let mut s: String = s.chars().take(MAX_WIDTH - 1).collect();
+            s.push('…');
+            s
+        } else {
+            s.to_string()
+        }
+    };
+    let header: Vec<String> = columns.iter().map(|c| truncate(c)).collect();
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().map(|c| truncate(c)).collect())
+        .collect();
+    let widths: Vec<usize> = (0..columns.len())
+        .map(|i| {
+            std::iter::once(&header)
+                .chain(&rows)
+                .map(|row| row[i].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    for row in std::iter::once(&header).chain(&rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        writeln!(out, "{}", line.join("  ").trim_end())?;
+    }
+    Ok(())
+}
+
+fn write_csv(out: &mut dyn Write, columns: &[String], rows: &[Vec<String>]) -> io::Result<()> {
+    let mut writer = csv::Writer::from_writer(out);
+    writer.write_record(columns)?;
+    for row in rows {
+        writer.write_record(row)?;
+    }
+    writer.flush()
+}
+
+fn parse_locale(s: &str) -> Result<Locale, String> {
+    if s.is_empty() || s.starts_with('-') {
+        return Err(String::from("expected {cc} or {cc}-{lc}"));
+    }
+    Ok(Locale::from(s))
+}
+
+fn parse_criterion(s: &str) -> Result<Criterion, String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| String::from("expected NAME[:LC]=VALUE"))?;
+    let (name, lc) = match name.split_once(':') {
+        Some((name, lc)) => (name, Some(lc.to_string())),
+        None => (name, None),
+    };
+    if name.is_empty() || value.is_empty() {
+        return Err(String::from("expected NAME[:LC]=VALUE"));
+    }
+    Ok(Criterion {
+        name: name.to_string(),
+        lc,
+        value: value.to_string(),
+    })
+}
+
+fn parse_nutrient(s: &str) -> Result<Nutrient, String> {
+    let expected = || String::from("expected NUTRIENT_UNIT{=,<,>,<=,>=}VALUE, i.e. sugars_100g<5");
+    let start = s.find(['<', '>', '=']).ok_or_else(expected)?;
+    let end = s[start..]
+        .find(|c| !matches!(c, '<' | '>' | '='))
+        .map_or(s.len(), |i| start + i);
+    let (name, op, value) = (&s[..start], &s[start..end], &s[end..]);
+    let (nutrient, unit) = name.rsplit_once('_').ok_or_else(expected)?;
+    if nutrient.is_empty()
+        || !matches!(unit, "100g" | "serving")
+        || !matches!(op, "=" | "<" | ">" | "<=" | ">=")
+    {
+        return Err(expected());
+    }
+    Ok(Nutrient {
+        nutrient: nutrient.to_string(),
+        unit: unit.to_string(),
+        op: op.to_string(),
+        value: value.parse().map_err(|_| expected())?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use off::{FakeResponse, FakeTransport};
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::try_parse_from(std::iter::once("off").chain(args.iter().copied())).unwrap()
+    }
+
+    fn run_v2(args: &[&str], fake: FakeTransport) -> (String, Vec<off::RecordedRequest>) {
+        let client = Client::V2(off::v2().error_for_status(true).build_with(fake));
+        let mut out = Vec::new();
+        run(&client, &cli(args), &mut out).unwrap();
+        let requests = dispatch!(&client, c => c.transport().requests());
+        (String::from_utf8(out).unwrap(), requests)
+    }
+
+    #[test]
+    fn args() {
+        Cli::command().debug_assert();
+        let cli = cli(&["facet", "brands", "--locale", "be-nl", "--format", "csv"]);
+        assert_eq!(cli.locale, Locale::new("be", Some("nl")));
+        assert_eq!(cli.format, Format::Csv);
+        assert!(cli.check().is_ok());
+        assert!(Cli::try_parse_from(["off", "--api", "v1", "categories"]).is_err());
+
+        let search = self::cli(&["--api", "v0", "search"]);
+        assert_eq!(
+            search.check().unwrap_err().kind(),
+            ErrorKind::ArgumentConflict
+        );
+    }
+
+    #[test]
+    fn criteria_and_nutrients() {
+        assert_eq!(
+            parse_criterion("brands:fr=nestlé").unwrap(),
+            Criterion {
+                name: String::from("brands"),
+                lc: Some(String::from("fr")),
+                value: String::from("nestlé"),
+            }
+        );
+        assert_eq!(parse_criterion("categories=-en:cheeses").unwrap().lc, None);
+        assert!(parse_criterion("categories").is_err());
+
+        assert_eq!(
+            parse_nutrient("saturated-fat_serving<=10").unwrap(),
+            Nutrient {
+                nutrient: String::from("saturated-fat"),
+                unit: String::from("serving"),
+                op: String::from("<="),
+                value: 10,
+            }
+        );
+        assert_eq!(parse_nutrient("sugars_100g=5").unwrap().op, "=");
+        assert_eq!(parse_nutrient("sugars_100g>=5").unwrap().op, ">=");
+        assert!(parse_nutrient("sugars_100g=>5").is_err());
+        assert!(parse_nutrient("sugars<5").is_err());
+        assert!(parse_nutrient("sugars_100g<<5").is_err());
+        assert!(parse_nutrient("sugars_100g<five").is_err());
+    }
+
+    #[test]
+    fn product_table() {
+        let fake = FakeTransport::new().route(
+            "*/api/v2/product/3017620422003*",
+            FakeResponse::json(
+                r#"{"code": "3017620422003", "status": 1, "product": {
+                    "code": "3017620422003", "product_name": "Nutella",
+                    "brands": "Ferrero", "nutriscore_grade": "e", "nova_group": 4}}"#,
+            ),
+        );
+        let (out, requests) = run_v2(&["product", "3017620422003", "--format", "table"], fake);
+        assert_eq!(
+            out,
+            "code           product_name  brands   quantity  nutriscore_grade  nova_group\n\
+             3017620422003  Nutella       Ferrero            e                 4\n"
+        );
+        assert_eq!(requests[0].query("fields"), None);
+    }
+
+    #[test]
+    fn search_csv() {
+        let fake = FakeTransport::new().route(
+            "*/api/v2/search*",
+            FakeResponse::json(
+                r#"{"count": 2, "products": [
+                    {"code": "1", "categories_tags": ["en:cheeses", "en:blue-cheeses"]},
+                    {"code": "2", "product_name": "Brie, \"de Meaux\""}]}"#,
+            ),
+        );
+        let args = [
+            "search",
+            "--criteria",
+            "categories=en:cheeses",
+            "--nutrient",
+            "salt_100g<1",
+            "--sort-by",
+            "popularity",
+            "--fields",
+            "code,product_name,categories_tags",
+            "--page-size",
+            "2",
+            "--format",
+            "csv",
+        ];
+        let (out, requests) = run_v2(&args, fake);
+        assert_eq!(
+            out,
+            "code,product_name,categories_tags\n\
+             1,,\"en:cheeses,en:blue-cheeses\"\n\
+             2,\"Brie, \"\"de Meaux\"\"\",\n"
+        );
+        let request = &requests[0];
+        assert_eq!(
+            request.query("categories_tags"),
+            Some(String::from("en:cheeses"))
+        );
+        assert!(request.url.query().unwrap().contains("salt_100g%3C1"));
+        assert_eq!(
+            request.query("sort_by"),
+            Some(String::from("unique_scans_n"))
+        );
+        assert_eq!(request.query("page_size"), Some(String::from("2")));
+    }
+
+    #[test]
+    fn taxonomy_rows() {
+        let json: Value = serde_json::json!({
+            "en:4": {"name": {"en": "Ultra processed", "fr": "Ultra-transformés"}},
+            "en:1": {"name": {"en": "Unprocessed"}, "parents": []},
+        });
+        let columns = cli(&["taxonomy", "nova_groups"]).columns(Kind::Taxonomy);
+        let rows = rows(
+            Kind::Taxonomy,
+            &json,
+            &Locale::new("fr", Some("fr")),
+            &columns,
+        );
+        assert_eq!(
+            rows,
+            [
+                ["en:1", "Unprocessed", ""],
+                ["en:4", "Ultra-transformés", ""]
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let fake = FakeTransport::new().route("*", FakeResponse::status(404));
+        let client = Client::V0(off::v0().error_for_status(true).build_with(fake));
+        let result = run(&client, &cli(&["product", "1"]), &mut Vec::new());
+        assert!(matches!(result, Err(Error::NotFound(_))));
+
+        let fake = FakeTransport::new().route("*", FakeResponse::json("<html>"));
+        let client = Client::V2(off::v2().build_with(fake));
+        let result = run(&client, &cli(&["categories"]), &mut Vec::new());
+        assert!(matches!(result, Err(Error::Decode { .. })));
+    }
+
+    #[test]
+    fn long_cells() {
+        let mut out = Vec::new();
+        let long = "x".repeat(MAX_WIDTH + 10);
+        write_table(&mut out, &[String::from("a")], &[vec![long]]).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().nth(1).unwrap().chars().count(), MAX_WIDTH);
+        assert!(out.ends_with("…\n"));
+    }
+}
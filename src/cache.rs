@@ -0,0 +1,759 @@
+use crate::client::Error;
+use crate::rate_limit::EndpointClass;
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, ETAG,
+    IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, TRANSFER_ENCODING,
+};
+use reqwest::{ResponseBuilderExt, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Debug, Formatter};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use url::Url;
+
+/// The endpoints a cache TTL can be set for.
+///
+/// # Variants:
+///
+/// * Taxonomy - `GET /data/taxonomies/{taxonomy}.json`.
+/// * Facet - Facets, categories and `products_by`.
+/// * Product - Product reads.
+/// * Search - Search queries.
+/// * Other - All other GET requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CacheEndpoint {
+    Taxonomy,
+    Facet,
+    Product,
+    Search,
+    Other,
+}
+
+impl CacheEndpoint {
+    /// Returns the endpoint of the given URL.
+    pub fn of(url: &Url) -> Self {
+        if url.path().starts_with("/data/taxonomies/") {
+            return Self::Taxonomy;
+        }
+        match EndpointClass::of(url) {
+            Some(EndpointClass::Product) => Self::Product,
+            Some(EndpointClass::Search) => Self::Search,
+            Some(EndpointClass::Facet) => Self::Facet,
+            None => Self::Other,
+        }
+    }
+}
+
+/// A response stored in a cache.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    #[serde(skip)]
+    pub body: Vec<u8>,
+    /// When the response was received or last revalidated.
+    pub stored_at: SystemTime,
+}
+
+impl CachedResponse {
+    fn new(status: StatusCode, headers: &HeaderMap, body: Vec<u8>, now: SystemTime) -> Self {
+        Self {
+            status: status.as_u16(),
+            headers: headers
+                .iter()
+                .filter_map(|(n, v)| Some((n.to_string(), v.to_str().ok()?.to_string())))
+                .collect(),
+            body,
+            stored_at: now,
+        }
+    }
+
+    fn header(&self, name: &HeaderName) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name.as_str()))
+            .map(|(_, v)| v.as_str())
+    }
+
+    // Returns the headers of a conditional GET revalidating this response.
+    fn validators(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let validators = [(ETAG, IF_NONE_MATCH), (LAST_MODIFIED, IF_MODIFIED_SINCE)];
+        for (name, conditional) in validators {
+            if let Some(value) = self.header(&name).and_then(|v| v.parse().ok()) {
+                headers.insert(conditional, value);
+            }
+        }
+        headers
+    }
+
+    // Updates the response with the headers of a `304 Not Modified`. The
+    // headers describing the 304 body, i.e. its length and encoding, are not
+    // merged: they would not match the stored body.
+    fn revalidated(mut self, headers: &HeaderMap, now: SystemTime) -> Self {
+        const BODY_HEADERS: [HeaderName; 3] = [CONTENT_LENGTH, TRANSFER_ENCODING, CONTENT_ENCODING];
+        for name in headers.keys() {
+            if BODY_HEADERS.contains(name) {
+                continue;
+            }
+            self.headers
+                .retain(|(n, _)| !n.eq_ignore_ascii_case(name.as_str()));
+            for value in headers.get_all(name) {
+                if let Ok(value) = value.to_str() {
+                    self.headers.push((name.to_string(), value.to_string()));
+                }
+            }
+        }
+        self.stored_at = now;
+        self
+    }
+
+    fn to_http(&self, url: &Url) -> http::Response<Vec<u8>> {
+        let mut builder = http::Response::builder()
+            .status(self.status)
+            .url(url.clone());
+        for (name, value) in &self.headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                builder = builder.header(name, value);
+            }
+        }
+        builder
+            .body(self.body.clone())
+            .unwrap_or_else(|_| http::Response::new(self.body.clone()))
+    }
+}
+
+/// A cache backend. Keys are request URLs.
+///
+/// The cache is best effort: backends ignore the storage errors.
+pub trait CacheStore: Send + Sync {
+    fn get(&self, key: &str) -> Option<CachedResponse>;
+    fn put(&self, key: &str, response: &CachedResponse);
+    fn remove(&self, key: &str);
+}
+
+/// An in-memory cache keeping the `capacity` least recently used responses.
+#[derive(Debug)]
+pub struct MemoryCache {
+    capacity: usize,
+    entries: Mutex<Lru>,
+}
+
+// The entries with their last use, the keys by last use and the use counter.
+#[derive(Debug, Default)]
+struct Lru {
+    entries: HashMap<String, (u64, CachedResponse)>,
+    uses: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+impl Lru {
+    // Marks the given entry as the most recently used.
+    fn touch(&mut self, key: &str, used: u64) -> u64 {
+        self.clock += 1;
+        self.uses.remove(&used);
+        self.uses.insert(self.clock, key.to_string());
+        self.clock
+    }
+}
+
+impl MemoryCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(Lru::default()),
+        }
+    }
+}
+
+impl CacheStore for MemoryCache {
+    fn get(&self, key: &str) -> Option<CachedResponse> {
+        let mut lru = self.entries.lock().unwrap();
+        let used = lru.entries.get(key)?.0;
+        let clock = lru.touch(key, used);
+        let (used, response) = lru.entries.get_mut(key)?;
+        *used = clock;
+        Some(response.clone())
+    }
+
+    fn put(&self, key: &str, response: &CachedResponse) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut lru = self.entries.lock().unwrap();
+        let used = match lru.entries.get(key) {
+            Some((used, _)) => *used,
+            None if lru.entries.len() >= self.capacity => {
+                if let Some((_, oldest)) = lru.uses.pop_first() {
+                    lru.entries.remove(&oldest);
+                }
+                0
+            }
+            None => 0,
+        };
+        let clock = lru.touch(key, used);
+        lru.entries
+            .insert(key.to_string(), (clock, response.clone()));
+    }
+
+    fn remove(&self, key: &str) {
+        let mut lru = self.entries.lock().unwrap();
+        if let Some((used, _)) = lru.entries.remove(key) {
+            lru.uses.remove(&used);
+        }
+    }
+}
+
+/// An on-disk cache storing each response in a file of a directory,
+/// `{hash}.entry`: a line of JSON with the status and headers, then the body.
+///
+/// Entries are replaced at once, so concurrent writes of the same key never
+/// publish a partial or mixed entry.
+#[derive(Debug)]
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+// The metadata line of an entry.
+#[derive(Serialize, Deserialize)]
+struct DiskEntry {
+    key: String,
+    response: CachedResponse,
+}
+
+impl DiskCache {
+    /// Creates a disk cache in the given directory, creating it if needed.
+    pub fn new(dir: impl AsRef<Path>) -> io::Result<Self> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    // Returns the entry path of a key.
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:016x}.entry", fnv1a(key.as_bytes())))
+    }
+
+    fn write(&self, key: &str, response: &CachedResponse) -> io::Result<()> {
+        let entry = DiskEntry {
+            key: key.to_string(),
+            response: response.clone(),
+        };
+        // Compact JSON has no raw newline.
+        let mut content = serde_json::to_vec(&entry)?;
+        content.push(b'\n');
+        content.extend_from_slice(&response.body);
+        write_atomic(&self.path(key), &content)
+    }
+}
+
+impl CacheStore for DiskCache {
+    fn get(&self, key: &str) -> Option<CachedResponse> {
+        let content = fs::read(self.path(key)).ok()?;
+        let split = content.iter().position(|b| *b == b'\n')?;
+        let entry: DiskEntry = serde_json::from_slice(&content[..split]).ok()?;
+        // Hash collision.
+        if entry.key != key {
+            return None;
+        }
+        let mut response = entry.response;
+        response.body = content[split + 1..].to_vec();
+        Some(response)
+    }
+
+    fn put(&self, key: &str, response: &CachedResponse) {
+        let _ = self.write(key, response);
+    }
+
+    fn remove(&self, key: &str) {
+        let _ = fs::remove_file(self.path(key));
+    }
+}
+
+// Writes the file through a temporary file unique to the call, so that
+// concurrent writes never publish each other's partial content.
+fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+    let write = WRITES.fetch_add(1, Ordering::Relaxed);
+    let tmp = path.with_extension(format!("tmp{}-{}", std::process::id(), write));
+    fs::write(&tmp, content)?;
+    fs::rename(tmp, path)
+}
+
+// The 64 bits FNV-1a hash. Stable across runs and platforms, unlike the std
+// hashers.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The HTTP cache of a client, set with [crate::OffBuilder::cache].
+///
+/// Successful GET responses are stored unless the server sends
+/// `Cache-Control: no-store`. A stored response is fresh for the TTL of its
+/// endpoint, if set with [Cache::ttl], or else for the `max-age` of its
+/// `Cache-Control` header. Stale responses with an `ETag` or a
+/// `Last-Modified` header are revalidated with a conditional GET, other
+/// stale responses are fetched again.
+///
+/// Requests with [crate::Output::nocache] set bypass the cache.
+///
+/// ```no_run
+/// use openfoodfacts::{self as off, Cache, CacheEndpoint};
+/// use std::time::Duration;
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let cache = Cache::disk("/tmp/off-cache")?
+///     .ttl(CacheEndpoint::Taxonomy, Duration::from_secs(24 * 3600));
+/// let client = off::v2().cache(cache).build()?;
+/// // Downloaded once a day at most.
+/// let response = client.taxonomy("categories")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Cache {
+    store: Arc<dyn CacheStore>,
+    ttls: HashMap<CacheEndpoint, Duration>,
+}
+
+impl Debug for Cache {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache").field("ttls", &self.ttls).finish()
+    }
+}
+
+// What to do with the response of a request to a cached URL.
+enum Lookup {
+    // The cached response is fresh.
+    Fresh(CachedResponse),
+    // Send the request with the given headers. The cached response is kept
+    // for revalidation.
+    Send(HeaderMap, Option<CachedResponse>),
+}
+
+impl Cache {
+    /// Creates a cache with the given backend.
+    pub fn new(store: impl CacheStore + 'static) -> Self {
+        Self {
+            store: Arc::new(store),
+            ttls: HashMap::new(),
+        }
+    }
+
+    /// Creates an in-memory cache of `capacity` responses.
+    pub fn memory(capacity: usize) -> Self {
+        Self::new(MemoryCache::new(capacity))
+    }
+
+    /// Creates an on-disk cache in the given directory.
+    pub fn disk(dir: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(DiskCache::new(dir)?))
+    }
+
+    /// Sets the TTL of the responses of the given endpoint, overriding the
+    /// server `max-age`.
+    pub fn ttl(mut self, endpoint: CacheEndpoint, ttl: Duration) -> Self {
+        self.ttls.insert(endpoint, ttl);
+        self
+    }
+
+    // Returns how long the given response of the given URL is fresh.
+    fn lifetime(&self, url: &Url, response: &CachedResponse) -> Duration {
+        if let Some(ttl) = self.ttls.get(&CacheEndpoint::of(url)) {
+            return *ttl;
+        }
+        let directives = response.header(&CACHE_CONTROL).unwrap_or_default();
+        if has_directive(directives, "no-cache") {
+            return Duration::ZERO;
+        }
+        directives
+            .split(',')
+            .filter_map(|d| d.trim().strip_prefix("max-age="))
+            .find_map(|secs| secs.trim_matches('"').parse().ok())
+            .map_or(Duration::ZERO, Duration::from_secs)
+    }
+
+    fn lookup(&self, url: &Url, now: SystemTime) -> Lookup {
+        let cached = match self.store.get(url.as_str()) {
+            Some(cached) => cached,
+            None => return Lookup::Send(HeaderMap::new(), None),
+        };
+        let age = now.duration_since(cached.stored_at).unwrap_or_default();
+        if age < self.lifetime(url, &cached) {
+            return Lookup::Fresh(cached);
+        }
+        let validators = cached.validators();
+        if validators.is_empty() {
+            Lookup::Send(validators, None)
+        } else {
+            Lookup::Send(validators, Some(cached))
+        }
+    }
+
+    // Handles a `304 Not Modified` answer to a conditional GET.
+    fn revalidate(&self, url: &Url, cached: CachedResponse, headers: &HeaderMap) -> CachedResponse {
+        let cached = cached.revalidated(headers, SystemTime::now());
+        self.store.put(url.as_str(), &cached);
+        cached
+    }
+
+    // Stores a new response.
+    fn store(
+        &self,
+        url: &Url,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: Vec<u8>,
+    ) -> CachedResponse {
+        let response = CachedResponse::new(status, headers, body, SystemTime::now());
+        self.store.put(url.as_str(), &response);
+        response
+    }
+
+    /// Sends a GET request to `url` through the cache. `send` sends the
+    /// request with the given extra headers.
+    pub(crate) fn get(
+        &self,
+        url: &Url,
+        send: impl Fn(HeaderMap) -> Result<reqwest::blocking::Response, Error>,
+    ) -> Result<reqwest::blocking::Response, Error> {
+        let (headers, cached) = match self.lookup(url, SystemTime::now()) {
+            Lookup::Fresh(cached) => return Ok(cached.to_http(url).into()),
+            Lookup::Send(headers, cached) => (headers, cached),
+        };
+        let response = send(headers)?;
+        let status = response.status();
+        match cached {
+            Some(cached) if status == StatusCode::NOT_MODIFIED => {
+                let cached = self.revalidate(url, cached, response.headers());
+                Ok(cached.to_http(url).into())
+            }
+            _ if is_storable(status, response.headers()) => {
+                let headers = response.headers().clone();
+                let body = response.bytes()?.to_vec();
+                Ok(self.store(url, status, &headers, body).to_http(url).into())
+            }
+            _ => Ok(response),
+        }
+    }
+
+    /// Async version of [Cache::get].
+    #[cfg(feature = "async")]
+    pub(crate) async fn get_async<F, Fut>(
+        &self,
+        url: &Url,
+        send: F,
+    ) -> Result<reqwest::Response, Error>
+    where
+        F: Fn(HeaderMap) -> Fut,
+        Fut: std::future::Future<Output = Result<reqwest::Response, Error>>,
+    {
+        let (headers, cached) = match self.lookup(url, SystemTime::now()) {
+            Lookup::Fresh(cached) => return Ok(cached.to_http(url).into()),
+            Lookup::Send(headers, cached) => (headers, cached),
+        };
+        let response = send(headers).await?;
+        let status = response.status();
+        match cached {
+            Some(cached) if status == StatusCode::NOT_MODIFIED => {
+                let cached = self.revalidate(url, cached, response.headers());
+                Ok(cached.to_http(url).into())
+            }
+            _ if is_storable(status, response.headers()) => {
+                let headers = response.headers().clone();
+                let body = response.bytes().await?.to_vec();
+                Ok(self.store(url, status, &headers, body).to_http(url).into())
+            }
+            _ => Ok(response),
+        }
+    }
+}
+
+fn has_directive(cache_control: &str, directive: &str) -> bool {
+    cache_control
+        .split(',')
+        .any(|d| d.trim().eq_ignore_ascii_case(directive))
+}
+
+fn is_storable(status: StatusCode, headers: &HeaderMap) -> bool {
+    let no_store = headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .any(|v| has_directive(v, "no-store"));
+    status == StatusCode::OK && !no_store
+}
+
+/// Returns true if the request must bypass the cache, i.e. if its query has
+/// `nocache=true`.
+pub(crate) fn bypass(url: &Url) -> bool {
+    url.query_pairs()
+        .any(|(k, v)| k == "nocache" && v == "true")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::VARY;
+
+    fn url(path: &str) -> Url {
+        Url::parse("https://world.openfoodfacts.org/")
+            .unwrap()
+            .join(path)
+            .unwrap()
+    }
+
+    fn response(headers: &[(&str, &str)], stored_at: SystemTime) -> CachedResponse {
+        CachedResponse {
+            status: 200,
+            headers: headers
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+            body: b"{}".to_vec(),
+            stored_at,
+        }
+    }
+
+    #[test]
+    fn endpoints() {
+        let taxonomy = url("data/taxonomies/categories.json");
+        assert_eq!(CacheEndpoint::of(&taxonomy), CacheEndpoint::Taxonomy);
+        let facet = url("category/cheeses.json");
+        assert_eq!(CacheEndpoint::of(&facet), CacheEndpoint::Facet);
+        let product = url("api/v2/product/123");
+        assert_eq!(CacheEndpoint::of(&product), CacheEndpoint::Product);
+        let other = url("cgi/nutrients.pl");
+        assert_eq!(CacheEndpoint::of(&other), CacheEndpoint::Other);
+    }
+
+    #[test]
+    fn memory_lru() {
+        let cache = MemoryCache::new(2);
+        let r = response(&[], SystemTime::now());
+        cache.put("a", &r);
+        cache.put("b", &r);
+        assert!(cache.get("a").is_some());
+        // "b" is the least recently used.
+        cache.put("c", &r);
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+        cache.remove("a");
+        assert!(cache.get("a").is_none());
+        // "c" is now the least recently used.
+        cache.put("a", &r);
+        cache.put("b", &r);
+        assert!(cache.get("c").is_none());
+        assert!(cache.get("a").is_some());
+    }
+
+    #[test]
+    fn disk() {
+        let dir = std::env::temp_dir().join(format!("off-cache-test-{}", std::process::id()));
+        let cache = DiskCache::new(&dir).unwrap();
+        let r = response(&[("etag", "\"abc\"")], SystemTime::now());
+        assert!(cache.get("https://a/").is_none());
+        cache.put("https://a/", &r);
+        assert_eq!(cache.get("https://a/"), Some(r.clone()));
+        cache.remove("https://a/");
+        assert!(cache.get("https://a/").is_none());
+
+        // Another key with the same hash is a miss.
+        cache.put("https://a/", &r);
+        let entry = fs::read(cache.path("https://a/")).unwrap();
+        fs::write(cache.path("https://b/"), entry).unwrap();
+        assert!(cache.get("https://b/").is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn disk_concurrent_writes() {
+        let dir = std::env::temp_dir().join(format!("off-cache-race-{}", std::process::id()));
+        let cache = Arc::new(DiskCache::new(&dir).unwrap());
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                let cache = Arc::clone(&cache);
+                std::thread::spawn(move || {
+                    let mut r = response(&[], SystemTime::now());
+                    r.body = vec![b'0' + i; 100_000];
+                    for _ in 0..10 {
+                        cache.put("https://a/", &r);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let body = cache.get("https://a/").unwrap().body;
+        assert_eq!(body.len(), 100_000);
+        assert!(body.iter().all(|b| *b == body[0]));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn freshness() {
+        let now = SystemTime::now();
+        let hour_ago = now - Duration::from_secs(3600);
+        let taxonomy = url("data/taxonomies/categories.json");
+        let cache = Cache::memory(10);
+
+        // max-age
+        let r = response(&[("cache-control", "public, max-age=7200")], hour_ago);
+        cache.store.put(taxonomy.as_str(), &r);
+        assert!(matches!(cache.lookup(&taxonomy, now), Lookup::Fresh(_)));
+
+        // Stale, without validators.
+        let r = response(&[("cache-control", "max-age=60")], hour_ago);
+        cache.store.put(taxonomy.as_str(), &r);
+        match cache.lookup(&taxonomy, now) {
+            Lookup::Send(headers, None) => assert!(headers.is_empty()),
+            _ => panic!("expected a plain GET"),
+        }
+
+        // Stale, with validators.
+        let r = response(
+            &[
+                ("etag", "\"v1\""),
+                ("last-modified", "Wed, 21 Oct 2015 07:28:00 GMT"),
+            ],
+            hour_ago,
+        );
+        cache.store.put(taxonomy.as_str(), &r);
+        match cache.lookup(&taxonomy, now) {
+            Lookup::Send(headers, Some(_)) => {
+                assert_eq!(headers[IF_NONE_MATCH], "\"v1\"");
+                assert_eq!(headers[IF_MODIFIED_SINCE], "Wed, 21 Oct 2015 07:28:00 GMT");
+            }
+            _ => panic!("expected a conditional GET"),
+        }
+
+        // The endpoint TTL overrides the server headers.
+        let cache = cache.ttl(CacheEndpoint::Taxonomy, Duration::from_secs(86400));
+        let r = response(&[("cache-control", "no-cache")], hour_ago);
+        cache.store.put(taxonomy.as_str(), &r);
+        assert!(matches!(cache.lookup(&taxonomy, now), Lookup::Fresh(_)));
+    }
+
+    #[test]
+    fn revalidated() {
+        let then = SystemTime::UNIX_EPOCH;
+        let r = response(
+            &[
+                ("etag", "\"v1\""),
+                ("content-type", "application/json"),
+                ("content-length", "2"),
+                ("vary", "accept"),
+            ],
+            then,
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static("\"v2\""));
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("0"));
+        headers.append(VARY, HeaderValue::from_static("accept-encoding"));
+        headers.append(VARY, HeaderValue::from_static("cookie"));
+        let now = SystemTime::now();
+        let r = r.revalidated(&headers, now);
+        assert_eq!(r.stored_at, now);
+        assert_eq!(r.header(&ETAG), Some("\"v2\""));
+        assert_eq!(
+            r.header(&reqwest::header::CONTENT_TYPE),
+            Some("application/json")
+        );
+        assert_eq!(r.header(&CONTENT_LENGTH), Some("2"));
+        let vary: Vec<&str> = r
+            .headers
+            .iter()
+            .filter(|(n, _)| n == "vary")
+            .map(|(_, v)| v.as_str())
+            .collect();
+        assert_eq!(vary, ["accept-encoding", "cookie"]);
+    }
+
+    #[test]
+    fn to_response() {
+        let taxonomy = url("data/taxonomies/categories.json");
+        let r = response(&[("content-type", "application/json")], SystemTime::now());
+        let response: reqwest::blocking::Response = r.to_http(&taxonomy).into();
+        assert_eq!(response.url(), &taxonomy);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().unwrap(), "{}");
+    }
+
+    #[test]
+    fn storable() {
+        let mut headers = HeaderMap::new();
+        assert!(is_storable(StatusCode::OK, &headers));
+        assert!(!is_storable(StatusCode::NOT_FOUND, &headers));
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("private, no-store"));
+        assert!(!is_storable(StatusCode::OK, &headers));
+    }
+
+    #[test]
+    fn conditional_get() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+
+        // A server answering once with a body and an ETag, then with 304 if
+        // the ETag is sent back.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let mut revalidated = false;
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut request = Vec::new();
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    request.push(line.to_lowercase());
+                }
+                let response = if request.contains(&String::from("if-none-match: \"v1\"")) {
+                    revalidated = true;
+                    "HTTP/1.1 304 Not Modified\r\netag: \"v1\"\r\ncontent-length: 0\r\n\r\n"
+                } else {
+                    "HTTP/1.1 200 OK\r\netag: \"v1\"\r\ncontent-length: 2\r\n\r\n{}"
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            revalidated
+        });
+
+        let url = Url::parse(&format!("http://127.0.0.1:{}/brands.json", port)).unwrap();
+        let client = reqwest::blocking::Client::new();
+        let send = |headers| Ok(client.get(url.clone()).headers(headers).send()?);
+        let cache = Cache::memory(10);
+        for _ in 0..2 {
+            let response = cache.get(&url, send).unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.url(), &url);
+            assert_eq!(response.text().unwrap(), "{}");
+        }
+        assert!(server.join().unwrap());
+        // The content-length of the 304 is not merged.
+        let stored = cache.store.get(url.as_str()).unwrap();
+        assert_eq!(stored.header(&CONTENT_LENGTH), Some("2"));
+    }
+
+    #[test]
+    fn nocache_bypass() {
+        assert!(bypass(&url("brands.json?nocache=true")));
+        assert!(!bypass(&url("brands.json?nocache=false")));
+        assert!(!bypass(&url("brands.json")));
+    }
+}
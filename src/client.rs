@@ -1,15 +1,45 @@
 // Notes:
 //
-// * The 'cc' and 'lc' query parmeters are not supported. The country and
-//   language are always selected via the subdomain.
+// * The country and language are selected via the subdomain, or via the 'cc'
+//   and 'lc' query parameters in single host mode (see server.rs).
 // * Only JSON calls are supported.
+#[cfg(feature = "dump")]
+use crate::delta::{parse_index, Checkpoint, DeltaFile, ProductStore, SyncReport};
+#[cfg(feature = "dump")]
+use crate::dump::Dump;
+use crate::flavor::Flavor;
+use crate::image::{ImageCrop, ImageCropResponse, ImageUpload, ImageUploadResponse};
 use crate::locale::Locale;
 use crate::output::Output;
+use crate::pages::Paginator;
+use crate::product::{Product, ProductResponse};
+#[cfg(feature = "dump")]
+use crate::response::check;
+use crate::response::{api_status, decode};
+use crate::retry::RequestPolicy;
 use crate::search::{SearchQueryV0, SearchQueryV2};
+use crate::server::{join, with_params, Server};
+use crate::session::Session;
+use crate::taxonomy::Taxonomy;
+use crate::transport::Transport;
 use crate::types::{Params, Version, V0, V2};
+use crate::write::{ProductEdit, WriteResponse};
+use crate::Auth;
 
+#[cfg(feature = "dump")]
+use flate2::read::MultiGzDecoder;
+use reqwest::blocking::multipart::Form;
+use reqwest::blocking::Request;
 pub use reqwest::blocking::{Client as HttpClient, Response as HttpResponse};
-use url::{ParseError, Url};
+use reqwest::header::{HeaderValue, CONTENT_TYPE};
+use reqwest::{Method, StatusCode};
+#[cfg(feature = "dump")]
+use std::io::BufReader;
+use std::io::Read;
+#[cfg(feature = "dump")]
+use std::path::Path;
+use std::time::Duration;
+use url::{form_urlencoded, ParseError, Url};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -18,6 +48,50 @@ pub enum Error {
 
     #[error(transparent)]
     Parse(#[from] ParseError),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error("login failed for user '{0}'")]
+    Login(String),
+
+    #[error("no recorded response for {0}")]
+    NotRecorded(String),
+
+    /// The server answered `404 Not Found`.
+    #[error("not found: {0}")]
+    NotFound(Url),
+
+    /// The server answered `429 Too Many Requests`, with the delay given in
+    /// the `Retry-After` header, if any.
+    #[error("rate limited by the server")]
+    RateLimited { retry_after: Option<Duration> },
+
+    /// The server answered `401 Unauthorized` or `403 Forbidden`.
+    #[error("unauthorized: {status}")]
+    Unauthorized { status: StatusCode },
+
+    /// The server answered another error status, with the start of the body.
+    #[error("server error {status}: {body}")]
+    ServerError { status: StatusCode, body: String },
+
+    /// The response is not the expected JSON. `path` is the path of the
+    /// invalid value, e.g. `product.nutriments.energy_100g`.
+    #[error("invalid JSON at '{path}': {source}")]
+    Decode {
+        path: String,
+        source: serde_json::Error,
+    },
+
+    /// The OFF API reported a failure in the JSON `status` field, e.g. status
+    /// 0 and "product not found".
+    #[error("API status {status}: {status_verbose}")]
+    ApiStatus { status: i32, status_verbose: String },
+
+    /// A query of the [crate::offline::OfflineStore] failed.
+    #[cfg(feature = "offline")]
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
 }
 
 /// The return type of all OffClient methods.
@@ -25,18 +99,28 @@ pub type Result = std::result::Result<HttpResponse, Error>;
 
 /// The OFF API client.
 ///
-/// The client owns a [reqwest::Client] object. One single OFF client should
-/// be used per application.
+/// The client sends its requests through a [crate::Transport], the
+/// [reqwest::blocking::Client] built by [crate::OffBuilder::build] by default.
+/// One single OFF client should be used per application.
 ///
 /// All methods return an [OffResult] object.
 #[derive(Debug)]
-pub struct OffClient<V> {
+pub struct OffClient<V, T = HttpClient> {
     // The version marker.
     v: V,
     // The default locale to use when no locale is given in a method call.
     locale: Locale,
-    // The uderlying reqwest client.
-    client: HttpClient,
+    // The server to send the requests to.
+    server: Server,
+    // The credentials sent with write requests.
+    auth: Option<Auth>,
+    // The cookie session, in session mode.
+    session: Option<Session>,
+    // The rate limits, shared by all the threads using the client, and the
+    // retries of the GET requests.
+    policy: RequestPolicy,
+    // Sends the requests. The underlying reqwest client by default.
+    transport: T,
 }
 
 /// Generates common OFF Urls.
@@ -59,7 +143,7 @@ pub(crate) trait Urls {
     /// none given.
     fn cgi_url(&self, locale: Option<&Locale>) -> std::result::Result<Url, ParseError> {
         let base = self.base_url(locale)?;
-        base.join("cgi/")
+        join(&base, "cgi/")
     }
 
     // Return the base URL with the given locale. If locale is None, return the
@@ -67,13 +151,37 @@ pub(crate) trait Urls {
     fn host_with_locale(&self, locale: Option<&Locale>) -> std::result::Result<Url, ParseError>;
 }
 
+/// Returns the flavors to look up a product in: the flavor of the server
+/// first, then the other flavors. Servers with a custom domain serve a single
+/// flavor.
+pub(crate) fn flavors(server: &Server) -> Vec<Flavor> {
+    let mut flavors = vec![server.flavor];
+    if server.domain.is_none() {
+        flavors.extend(Flavor::ALL.iter().filter(|f| **f != server.flavor));
+    }
+    flavors
+}
+
+/// Returns the product URL on the given server. Used to look up products in
+/// other flavors than the client flavor.
+pub(crate) fn product_url(
+    server: &Server,
+    version: &str,
+    locale: &Locale,
+    barcode: &str,
+) -> std::result::Result<Url, ParseError> {
+    let base_url = server.host_url(locale)?;
+    let api_url = join(&base_url, &format!("api/{}/", version))?;
+    join(&api_url, &format!("product/{}", barcode))
+}
+
 /// Generate versioned API URLs.
 pub(crate) trait ApiUrl: Version + Urls {
     /// Return the versioned API URL with the given locale or the default locale if
     /// none given.
     fn api_url(&self, locale: Option<&Locale>) -> std::result::Result<Url, ParseError> {
         let base = self.base_url(locale)?;
-        base.join(&format!("api/{}/", self.version()))
+        join(&base, &format!("api/{}/", self.version()))
     }
 }
 
@@ -83,13 +191,19 @@ pub(crate) trait SearchUrl: ApiUrl {
     fn search_url(&self, locale: Option<&Locale>) -> std::result::Result<Url, ParseError>;
 }
 
-/// OFF request methods. At present, only GET is implemented.
+/// OFF request methods.
 pub trait RequestMethods {
     /// Build and send a GET request.
     fn get(&self, url: Url, params: Option<&Params>) -> Result;
+
+    /// Build and send a POST request with an urlencoded form body.
+    fn post(&self, url: Url, form: &Params) -> Result;
+
+    /// Build and send a POST request with a multipart form body.
+    fn post_multipart(&self, url: Url, form: Form) -> Result;
 }
 
-impl<V> Version for OffClient<V>
+impl<V, T> Version for OffClient<V, T>
 where
     V: Version,
 {
@@ -98,38 +212,68 @@ where
     }
 }
 
-impl<V> Urls for OffClient<V>
+impl<V, T> Urls for OffClient<V, T>
 where
     V: Version,
 {
     /// Returns the base URL with the given locale. If locale is None, return the
     /// client's default locale.
     fn host_with_locale(&self, locale: Option<&Locale>) -> std::result::Result<Url, ParseError> {
-        let url = format!(
-            "https://{}.openfoodfacts.org/",
-            locale.map_or(self.locale.to_string(), |l| l.to_string())
-        );
-        Url::parse(&url)
+        self.server.host_url(locale.unwrap_or(&self.locale))
     }
 }
 
-impl<V> ApiUrl for OffClient<V> where V: Version {}
+impl<V, T> ApiUrl for OffClient<V, T> where V: Version {}
 
-impl<V> RequestMethods for OffClient<V> {
+impl<V, T> RequestMethods for OffClient<V, T>
+where
+    T: Transport,
+{
     /// Builds and send a GET request.
+    /// Goes through the cache, waits for the rate limiter of the endpoint
+    /// and retries transient failures.
     fn get(&self, url: Url, params: Option<&Params>) -> Result {
-        let mut rb = self.client.get(url);
-        if let Some(p) = params {
-            rb = rb.query(p);
-        }
-        let response = rb.send()?;
-        Ok(response)
+        let url = with_params(url, params);
+        self.policy.get(&url, |headers| {
+            let mut request = Request::new(Method::GET, url.clone());
+            *request.headers_mut() = headers;
+            self.transport.send(request)
+        })
+    }
+
+    /// Builds and send a POST request.
+    fn post(&self, url: Url, form: &Params) -> Result {
+        let body = form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(form)
+            .finish();
+        let mut request = Request::new(Method::POST, url);
+        request.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/x-www-form-urlencoded"),
+        );
+        *request.body_mut() = Some(body.into());
+        self.policy.check(self.transport.send(request)?)
+    }
+
+    /// Builds and send a multipart POST request.
+    fn post_multipart(&self, url: Url, form: Form) -> Result {
+        let content_type = format!("multipart/form-data; boundary={}", form.boundary());
+        let mut body = Vec::new();
+        form.into_reader().read_to_end(&mut body)?;
+        let mut request = Request::new(Method::POST, url);
+        request.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_str(&content_type).expect("valid multipart boundary"),
+        );
+        *request.body_mut() = Some(body.into());
+        self.policy.check(self.transport.send(request)?)
     }
 }
 
-impl<V> OffClient<V>
+impl<V, T> OffClient<V, T>
 where
     V: Version + Copy,
+    T: Transport,
 {
     // ------------------------------------------------------------------------
     // Metadata
@@ -157,13 +301,30 @@ where
     ///     - nova_groups (*)
     ///     - nutrient_levels (*)
     ///     - states
+    ///
     /// (*) Only taxonomy. There is no facet equivalent.
     pub fn taxonomy(&self, taxonomy: &str) -> Result {
         let base_url = self.base_url_world()?; // force world locale.
-        let url = base_url.join(&format!("data/taxonomies/{}.json", taxonomy))?;
+        let url = join(&base_url, &format!("data/taxonomies/{}.json", taxonomy))?;
         self.get(url, None)
     }
 
+    /// Gets the given taxonomy with `nocache=true`, i.e. bypassing the cache
+    /// (see [crate::OffBuilder::cache]). Same arguments as
+    /// [OffClient::taxonomy].
+    pub fn taxonomy_nocache(&self, taxonomy: &str) -> Result {
+        let base_url = self.base_url_world()?; // force world locale.
+        let url = join(&base_url, &format!("data/taxonomies/{}.json", taxonomy))?;
+        let params = Output::new().nocache(true).params(&["nocache"]);
+        self.get(url, Some(&params))
+    }
+
+    /// Gets the given taxonomy and parses it into a [Taxonomy]. Same
+    /// arguments as [OffClient::taxonomy].
+    pub fn taxonomy_typed(&self, taxonomy: &str) -> std::result::Result<Taxonomy, Error> {
+        decode(self.taxonomy(taxonomy)?)
+    }
+
     /// Gets the given facet.
     ///
     /// # OFF API request
@@ -186,13 +347,14 @@ where
     ///     - states
     ///     - stores
     ///     - traces
-    ///     The name may be given in english or localized, i.e. additives (world), additifs (fr).
+    ///
+    ///   The name may be given in english or localized, i.e. additives (world), additifs (fr).
     /// * output - Optional output parameters. This call supports only the locale,
-    ///     pagination, fields and nocache parameters.
+    ///   pagination, fields and nocache parameters.
     pub fn facet(&self, facet: &str, output: Option<Output>) -> Result {
         // Borrow output and extract Option<&Locale>
         let base_url = self.base_url(output.as_ref().and_then(|o| o.locale.as_ref()))?;
-        let url = base_url.join(&format!("{}.json", facet))?;
+        let url = join(&base_url, &format!("{}.json", facet))?;
         let params = output.map(|o| o.params(&["page", "page_size", "fields", "nocache"]));
         self.get(url, params.as_ref())
     }
@@ -205,11 +367,13 @@ where
     ///
     /// # Arguments
     ///
-    /// * output - Optional output parameters. This call supports only the locale parameter.
+    /// * output - Optional output parameters. This call supports only the locale and
+    ///   nocache parameters.
     pub fn categories(&self, output: Option<Output>) -> Result {
         let base_url = self.base_url(output.as_ref().and_then(|o| o.locale.as_ref()))?;
-        let url = base_url.join("categories.json")?;
-        self.get(url, None)
+        let url = join(&base_url, "categories.json")?;
+        let params = output.map(|o| o.params(&["nocache"]));
+        self.get(url, params.as_ref())
     }
 
     /// Gets the nutrients by country.
@@ -221,11 +385,12 @@ where
     /// # Arguments
     ///
     /// * output - Optional output parameter. This call supports only the locale
-    ///   parameter.
+    ///   and nocache parameters.
     pub fn nutrients(&self, output: Option<Output>) -> Result {
         let cgi_url = self.cgi_url(output.as_ref().and_then(|o| o.locale.as_ref()))?;
-        let url = cgi_url.join("nutrients.pl")?;
-        self.get(url, None)
+        let url = join(&cgi_url, "nutrients.pl")?;
+        let params = output.map(|o| o.params(&["nocache"]));
+        self.get(url, params.as_ref())
     }
 
     /// Gets all products for the given facet or category.
@@ -237,21 +402,34 @@ where
     /// # Arguments
     ///
     /// * what - A facet name or "category". The facet name is always the singular name
-    ///     of the face type name (i.e. brands -> brand, entry-dates -> entry-date, etc).
-    ///     The facet name or the "category" literal may be given either in english or
-    ///     localized, i.e. additives (world), additifs (fr), category (world), categorie (fr).
+    ///   of the face type name (i.e. brands -> brand, entry-dates -> entry-date, etc).
+    ///   The facet name or the "category" literal may be given either in english or
+    ///   localized, i.e. additives (world), additifs (fr), category (world), categorie (fr).
     /// * id - The localized id of the facet or category. The IDs are returned by calls
-    ///     to the corresponding `facet(<facet_type>)` or `categories()` endpoint. For example,
-    ///     the IDs for the `entry-date` facet are returned by the call `facet("entry-dates")`.
-    /// * output - Optional output parameters. This call supports the locale, pagination
-    ///     and fields parameters.
+    ///   to the corresponding `facet(<facet_type>)` or `categories()` endpoint. For example,
+    ///   the IDs for the `entry-date` facet are returned by the call `facet("entry-dates")`.
+    /// * output - Optional output parameters. This call supports the locale, pagination,
+    ///   fields and nocache parameters.
     pub fn products_by(&self, what: &str, id: &str, output: Option<Output>) -> Result {
         let base_url = self.base_url(output.as_ref().and_then(|o| o.locale.as_ref()))?;
-        let url = base_url.join(&format!("{}/{}.json", what, id))?;
-        let params = output.map(|o| o.params(&["page", "page_size", "fields"]));
+        let url = join(&base_url, &format!("{}/{}.json", what, id))?;
+        let params = output.map(|o| o.params(&["page", "page_size", "fields", "nocache"]));
         self.get(url, params.as_ref())
     }
 
+    /// Returns an iterator over the products of all the pages of
+    /// [OffClient::products_by]. See [crate::Paginator].
+    pub fn products_by_iter(
+        &self,
+        what: &str,
+        id: &str,
+        output: Option<Output>,
+    ) -> std::result::Result<Paginator<'_>, Error> {
+        let base_url = self.base_url(output.as_ref().and_then(|o| o.locale.as_ref()))?;
+        let url = join(&base_url, &format!("{}/{}.json", what, id))?;
+        Ok(Paginator::new(self, url, Params::new(), output))
+    }
+
     // ------------------------------------------------------------------------
     // Read
     // ------------------------------------------------------------------------
@@ -264,22 +442,359 @@ where
     ///
     /// # Arguments
     ///
-    /// * barcode - The product barcode.
-    /// * output - Optional output parameters. This call only supports the locale
-    ///     and fields parameters.
-    pub fn product(&self, barcode: &str, output: Option<Output>) -> Result {
+    /// * barcode - The product barcode, as a string or a [crate::Barcode].
+    /// * output - Optional output parameters. This call only supports the locale,
+    ///   fields and nocache parameters.
+    pub fn product(&self, barcode: impl AsRef<str>, output: Option<Output>) -> Result {
         let api_url = self.api_url(output.as_ref().and_then(|o| o.locale.as_ref()))?;
-        let url = api_url.join(&format!("product/{}", barcode))?;
-        let params = output.map(|o| o.params(&["fields"]));
+        let url = join(&api_url, &format!("product/{}", barcode.as_ref()))?;
+        let params = output.map(|o| o.params(&["fields", "nocache"]));
         self.get(url, params.as_ref())
     }
 
-    pub(crate) fn new(v: V, locale: Locale, client: HttpClient) -> Self {
-        Self { v, locale, client }
+    /// Gets the given product and deserializes the response into a
+    /// [ProductResponse]. Same arguments as [OffClient::product].
+    ///
+    /// If the product is not found, the response `status` is 0 and its
+    /// `product` is None. With [crate::OffBuilder::error_for_status], a
+    /// missing product fails with [Error::NotFound] or [Error::ApiStatus]
+    /// instead.
+    pub fn product_typed(
+        &self,
+        barcode: impl AsRef<str>,
+        output: Option<Output>,
+    ) -> std::result::Result<ProductResponse, Error> {
+        let response = decode::<ProductResponse>(self.product(barcode, output)?)?;
+        if self.policy.error_for_status {
+            api_status(response.status, &response.status_verbose)?;
+        }
+        Ok(response)
+    }
+
+    /// Looks up the given product in all the flavors, starting with the client
+    /// flavor and then in the order of [Flavor::ALL]. Returns the first flavor
+    /// the product was found in, with the product, or None if no flavor has
+    /// the product. Same arguments as [OffClient::product].
+    ///
+    /// # OFF API request
+    ///
+    /// `GET https://{locale}.{flavor domain}/api/{version}/product/{barcode}`
+    ///
+    /// Clients with a custom domain only look up their own flavor.
+    pub fn product_any_flavor(
+        &self,
+        barcode: impl AsRef<str>,
+        output: Option<Output>,
+    ) -> std::result::Result<Option<(Flavor, Product)>, Error> {
+        let locale = output
+            .as_ref()
+            .and_then(|o| o.locale.as_ref())
+            .unwrap_or(&self.locale);
+        let params = output.as_ref().map(|o| o.params(&["fields", "nocache"]));
+        for flavor in flavors(&self.server) {
+            let server = self.server.with_flavor(flavor);
+            let url = product_url(&server, self.version(), locale, barcode.as_ref())?;
+            let response = match self.get(url, params.as_ref()) {
+                Err(Error::NotFound(_)) => continue,
+                response => response?,
+            };
+            if let Some(product) = decode::<ProductResponse>(response)?.product {
+                return Ok(Some((flavor, product)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the client flavor.
+    pub fn flavor(&self) -> Flavor {
+        self.server.flavor
+    }
+
+    // ------------------------------------------------------------------------
+    // Delta exports
+    // ------------------------------------------------------------------------
+
+    /// Gets the list of the delta exports, sorted by start. See [crate::delta].
+    ///
+    /// # OFF API request
+    ///
+    /// `GET https://static.openfoodfacts.org/data/delta/index.txt`
+    #[cfg(feature = "dump")]
+    pub fn delta_index(&self) -> std::result::Result<Vec<DeltaFile>, Error> {
+        let url = join(&self.server.static_url()?, "data/delta/index.txt")?;
+        let index = check(self.get_uncached(url)?)?.text()?;
+        Ok(parse_index(&index))
+    }
+
+    /// Gets the given delta export, a gzip-compressed JSONL stream.
+    ///
+    /// # OFF API request
+    ///
+    /// `GET https://static.openfoodfacts.org/data/delta/{name}`
+    #[cfg(feature = "dump")]
+    pub fn delta(
+        &self,
+        delta: &DeltaFile,
+    ) -> std::result::Result<Dump<BufReader<MultiGzDecoder<HttpResponse>>>, Error> {
+        let url = join(&self.server.static_url()?, "data/delta/")?;
+        let url = join(&url, &delta.name)?;
+        Ok(Dump::gzip(check(self.get_uncached(url)?)?))
+    }
+
+    // Sends a GET request past the cache, so the delta index is never stale
+    // and the deltas are streamed instead of buffered. Still waits for the
+    // rate limiter and retries transient failures.
+    #[cfg(feature = "dump")]
+    fn get_uncached(&self, url: Url) -> Result {
+        self.policy.send(&url, || {
+            self.transport.send(Request::new(Method::GET, url.clone()))
+        })
+    }
+
+    /// Applies the delta exports not applied yet to the store, oldest first.
+    ///
+    /// The progress is read from and saved to the [Checkpoint] file at
+    /// `checkpoint`, after each delta and once the store is flushed. On error,
+    /// the deltas applied so far are kept, the upserts of the failed delta are
+    /// rolled back (see [ProductStore::rollback]) and the next sync resumes
+    /// with the failed delta. Without a checkpoint file, all the available deltas are
+    /// applied: save a [Checkpoint::since] the export time of the full dump
+    /// first.
+    #[cfg(feature = "dump")]
+    pub fn sync_deltas<S: ProductStore>(
+        &self,
+        store: &mut S,
+        checkpoint: impl AsRef<Path>,
+    ) -> std::result::Result<SyncReport, Error> {
+        let path = checkpoint.as_ref();
+        let mut report = SyncReport {
+            checkpoint: Checkpoint::load(path)?,
+            ..SyncReport::default()
+        };
+        let deltas = self.delta_index()?;
+        let pending = report.checkpoint.pending(&deltas);
+        report.gap = report.checkpoint.has_gap(&pending);
+        for delta in pending {
+            let mut apply = || -> std::result::Result<(u64, u64), Error> {
+                let mut dump = self.delta(delta)?;
+                let mut products = 0;
+                for product in dump.products() {
+                    store.upsert(product?)?;
+                    products += 1;
+                }
+                store.flush()?;
+                Ok((products, dump.malformed()))
+            };
+            let (products, malformed) = match apply() {
+                Ok(counts) => counts,
+                Err(err) => {
+                    let _ = store.rollback();
+                    return Err(err);
+                }
+            };
+            report.products += products;
+            report.malformed += malformed;
+            report.checkpoint = Checkpoint::since(delta.end);
+            report.checkpoint.save(path)?;
+            report.applied.push(delta.name.clone());
+        }
+        Ok(report)
+    }
+
+    // ------------------------------------------------------------------------
+    // Write
+    // ------------------------------------------------------------------------
+
+    /// Returns an empty edit of the product with the given barcode.
+    pub fn product_edit(&self, barcode: impl AsRef<str>) -> ProductEdit {
+        ProductEdit::new(barcode.as_ref())
+    }
+
+    /// Saves the given product edit. Creates the product if it does not exist.
+    ///
+    /// # OFF API request
+    ///
+    /// `POST https://{locale}.openfoodfacts.org/cgi/product_jqm2.pl`
+    ///
+    /// The credentials set with [crate::OffBuilder::auth] are sent as the
+    /// `user_id` and `password` form fields. Without credentials the edit is
+    /// anonymous. With [crate::OffBuilder::error_for_status], a rejected edit
+    /// fails with [Error::ApiStatus].
+    pub fn save_product(&self, edit: ProductEdit) -> std::result::Result<WriteResponse, Error> {
+        let cgi_url = self.cgi_url(None)?;
+        let url = join(&cgi_url, "product_jqm2.pl")?;
+        let mut form = edit.params();
+        form.extend(self.credentials());
+        let response = self.send_write(|| self.post(url.clone(), &form))?;
+        let response = decode::<WriteResponse>(response)?;
+        if self.policy.error_for_status {
+            api_status(response.status, &response.status_verbose)?;
+        }
+        Ok(response)
+    }
+
+    /// Uploads a product image.
+    ///
+    /// # OFF API request
+    ///
+    /// `POST https://{locale}.openfoodfacts.org/cgi/product_image_upload.pl`
+    ///
+    /// Returns the id of the new image in [ImageUploadResponse::imgid]. Fails
+    /// with [Error::Io] if the image file cannot be read.
+    pub fn upload_image(
+        &self,
+        upload: ImageUpload,
+    ) -> std::result::Result<ImageUploadResponse, Error> {
+        let cgi_url = self.cgi_url(None)?;
+        let url = join(&cgi_url, "product_image_upload.pl")?;
+        let response = self.send_write(|| {
+            let mut form = upload.clone().form()?;
+            for (name, value) in self.credentials() {
+                form = form.text(name, value);
+            }
+            self.post_multipart(url.clone(), form)
+        })?;
+        decode::<ImageUploadResponse>(response)
+    }
+
+    /// Selects, and optionally crops, rotates and normalizes, an uploaded
+    /// product image.
+    ///
+    /// # OFF API request
+    ///
+    /// `POST https://{locale}.openfoodfacts.org/cgi/product_image_crop.pl`
+    pub fn select_image(&self, crop: ImageCrop) -> std::result::Result<ImageCropResponse, Error> {
+        let cgi_url = self.cgi_url(None)?;
+        let url = join(&cgi_url, "product_image_crop.pl")?;
+        let mut form = crop.params();
+        form.extend(self.credentials());
+        let response = self.send_write(|| self.post(url.clone(), &form))?;
+        decode::<ImageCropResponse>(response)
+    }
+
+    // ------------------------------------------------------------------------
+    // Session
+    // ------------------------------------------------------------------------
+
+    /// Logs in with the credentials given to [crate::OffBuilder::auth] and
+    /// keeps the session cookie. Only available in session mode (see
+    /// [crate::OffBuilder::session]).
+    ///
+    /// Write requests log in automatically, so calling this method is only
+    /// needed to check the credentials up front.
+    ///
+    /// # OFF API request
+    ///
+    /// `POST https://{locale}.openfoodfacts.org/cgi/session.pl`
+    ///
+    /// Fails with [Error::Login] if the client is not in session mode, has no
+    /// credentials or if the server did not open a session.
+    pub fn login(&self) -> std::result::Result<(), Error> {
+        let (auth, session) = match (&self.auth, &self.session) {
+            (Some(auth), Some(session)) => (auth, session),
+            (auth, _) => {
+                let user = auth.as_ref().map_or(String::new(), |a| a.0.clone());
+                return Err(Error::Login(user));
+            }
+        };
+        let url = self.session_url()?;
+        let form = vec![("user_id", auth.0.clone()), ("password", auth.1.clone())];
+        self.post(url.clone(), &form)?;
+        if session.is_active(&url) {
+            Ok(())
+        } else {
+            Err(Error::Login(auth.0.clone()))
+        }
+    }
+
+    /// Logs out and drops the session cookie. Does nothing if the client is
+    /// not in session mode.
+    ///
+    /// # OFF API request
+    ///
+    /// `GET https://{locale}.openfoodfacts.org/cgi/session.pl?logout=1`
+    pub fn logout(&self) -> std::result::Result<(), Error> {
+        if let Some(ref session) = self.session {
+            let url = self.session_url()?;
+            self.get(url.clone(), Some(&vec![("logout", String::from("1"))]))?;
+            session.expire(&url);
+        }
+        Ok(())
+    }
+
+    // Sends a write request. In session mode, logs in first if there is no
+    // active session, and logs in again and resends the request once if the
+    // server rejected the session.
+    fn send_write<F>(&self, send: F) -> Result
+    where
+        F: Fn() -> Result,
+    {
+        let session = match self.session {
+            Some(ref session) => session,
+            None => return send(),
+        };
+        if !session.is_active(&self.session_url()?) {
+            self.login()?;
+        }
+        match send() {
+            Ok(response)
+                if response.status() != StatusCode::UNAUTHORIZED
+                    && response.status() != StatusCode::FORBIDDEN =>
+            {
+                Ok(response)
+            }
+            Ok(_) | Err(Error::Unauthorized { .. }) => {
+                self.login()?;
+                send()
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    // The `user_id` and `password` form fields of write requests. Empty if
+    // the client has no credentials or authenticates with a session cookie.
+    fn credentials(&self) -> Params<'static> {
+        match self.auth {
+            Some(ref auth) if self.session.is_none() => {
+                vec![("user_id", auth.0.clone()), ("password", auth.1.clone())]
+            }
+            _ => Params::new(),
+        }
+    }
+
+    fn session_url(&self) -> std::result::Result<Url, ParseError> {
+        let cgi_url = self.cgi_url(None)?;
+        join(&cgi_url, "session.pl")
+    }
+
+    pub(crate) fn new(
+        v: V,
+        locale: Locale,
+        server: Server,
+        auth: Option<Auth>,
+        session: Option<Session>,
+        policy: RequestPolicy,
+        transport: T,
+    ) -> Self {
+        Self {
+            v,
+            locale,
+            server,
+            auth,
+            session,
+            policy,
+            transport,
+        }
+    }
+
+    /// Returns the transport of the client, i.e. to inspect the requests
+    /// received by a [crate::FakeTransport].
+    pub fn transport(&self) -> &T {
+        &self.transport
     }
 }
 
-impl OffClient<V0> {
+impl<T: Transport> OffClient<V0, T> {
     /// Returns the query builder for API V0.
     pub fn query(&self) -> SearchQueryV0 {
         SearchQueryV0::new()
@@ -289,19 +804,30 @@ impl OffClient<V0> {
     pub fn search(&self, query: SearchQueryV0, output: Option<Output>) -> Result {
         SearchQueryV0::search(query, self, output)
     }
+
+    /// Returns an iterator over the products of all the pages of the search
+    /// results. See [crate::Paginator].
+    pub fn search_iter(
+        &self,
+        query: SearchQueryV0,
+        output: Option<Output>,
+    ) -> std::result::Result<Paginator<'_>, Error> {
+        let (url, params) = SearchQueryV0::request(&query, self, output.clone())?;
+        Ok(Paginator::new(self, url, params, output))
+    }
 }
 
-impl SearchUrl for OffClient<V0> {
+impl<T> SearchUrl for OffClient<V0, T> {
     /// Returns the API V0 search URL.
     ///
     /// `https://{locale}.openfoodfacts.org/cgi/search.pl`
     fn search_url(&self, locale: Option<&Locale>) -> std::result::Result<Url, ParseError> {
         let cgi_url = self.cgi_url(locale)?;
-        cgi_url.join("search.pl")
+        join(&cgi_url, "search.pl")
     }
 }
 
-impl OffClient<V2> {
+impl<T: Transport> OffClient<V2, T> {
     /// Returns the query builder for API V2.
     pub fn query(&self) -> SearchQueryV2 {
         SearchQueryV2::new()
@@ -312,6 +838,17 @@ impl OffClient<V2> {
         SearchQueryV2::search(query, self, output)
     }
 
+    /// Returns an iterator over the products of all the pages of the search
+    /// results. See [crate::Paginator].
+    pub fn search_iter(
+        &self,
+        query: SearchQueryV2,
+        output: Option<Output>,
+    ) -> std::result::Result<Paginator<'_>, Error> {
+        let (url, params) = SearchQueryV2::request(&query, self, output.clone())?;
+        Ok(Paginator::new(self, url, params, output))
+    }
+
     /// Gets the products given in the `barcodes` list as a string of comma-separated
     /// product barcodes.
     ///
@@ -325,21 +862,21 @@ impl OffClient<V2> {
         let url = self.search_url(output.as_ref().and_then(|o| o.locale.as_ref()))?;
         let mut params = Params::new();
         params.push(("code", String::from(barcodes)));
-        if let Some(output_params) = output.map(|o| o.params(&["fields"])) {
+        if let Some(output_params) = output.map(|o| o.params(&["fields", "nocache"])) {
             params.extend(output_params);
         }
         self.get(url, Some(&params))
     }
 }
 
-impl SearchUrl for OffClient<V2> {
+impl<T> SearchUrl for OffClient<V2, T> {
     /// Returns the API V2 search URL.
     ///
     /// `https://{locale}.openfoodfacts.org/api/v2/search`
     fn search_url(&self, locale: Option<&Locale>) -> std::result::Result<Url, ParseError> {
         // Return the API URL with the locale given in Output::locale.
         let api_url = self.api_url(locale)?;
-        api_url.join("search")
+        join(&api_url, "search")
     }
 }
 
@@ -347,6 +884,12 @@ impl SearchUrl for OffClient<V2> {
 mod tests_client {
     use super::*;
 
+    #[test]
+    fn shared_across_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<OffClient<V2>>();
+    }
+
     #[test]
     fn version() {
         let client_v0 = crate::v0().build().unwrap();
@@ -399,6 +942,425 @@ mod tests_client {
     }
 }
 
+#[cfg(test)]
+mod tests_client_server {
+    use super::*;
+
+    #[test]
+    fn staging() {
+        let client = crate::v2().staging().build().unwrap();
+        assert_eq!(
+            client
+                .api_url(Some(&Locale::new("fr", None)))
+                .unwrap()
+                .as_str(),
+            "https://fr.openfoodfacts.net/api/v2/"
+        );
+    }
+
+    #[test]
+    fn single_host() {
+        let client = crate::v2()
+            .scheme("http")
+            .domain("localhost")
+            .port(8080)
+            .single_host(true)
+            .locale(Locale::new("fr", None))
+            .build()
+            .unwrap();
+        assert_eq!(
+            client.search_url(None).unwrap().as_str(),
+            "http://localhost:8080/api/v2/search?cc=fr"
+        );
+        assert_eq!(
+            client
+                .cgi_url(Some(&Locale::new("be", Some("nl"))))
+                .unwrap()
+                .as_str(),
+            "http://localhost:8080/cgi/?cc=be&lc=nl"
+        );
+        // Taxonomies always use the world locale.
+        assert_eq!(
+            client.base_url_world().unwrap().as_str(),
+            "http://localhost:8080/"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests_client_flavor {
+    use super::*;
+
+    #[test]
+    fn flavor() {
+        let client = crate::v2().flavor(Flavor::PetFood).build().unwrap();
+        assert_eq!(client.flavor(), Flavor::PetFood);
+        assert_eq!(
+            client.api_url(None).unwrap().as_str(),
+            "https://world.openpetfoodfacts.org/api/v2/"
+        );
+    }
+
+    #[test]
+    fn flavors_order() {
+        let server = Server::default().with_flavor(Flavor::Beauty);
+        assert_eq!(
+            flavors(&server),
+            [
+                Flavor::Beauty,
+                Flavor::Food,
+                Flavor::PetFood,
+                Flavor::Products
+            ]
+        );
+
+        let custom = Server {
+            domain: Some(String::from("localhost")),
+            ..Server::default()
+        };
+        assert_eq!(flavors(&custom), [Flavor::Food]);
+    }
+
+    #[test]
+    fn product_url_per_flavor() {
+        let server = Server::default().with_flavor(Flavor::Products);
+        assert_eq!(
+            product_url(&server, "v2", &Locale::new("fr", None), "123")
+                .unwrap()
+                .as_str(),
+            "https://fr.openproductsfacts.org/api/v2/product/123"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests_client_write {
+    use super::*;
+
+    #[test]
+    fn credentials() {
+        let client = crate::v2().build().unwrap();
+        assert!(client.credentials().is_empty());
+
+        let client = crate::v2().auth("user", "pwd").build().unwrap();
+        assert_eq!(
+            client.credentials(),
+            [
+                ("user_id", String::from("user")),
+                ("password", String::from("pwd"))
+            ]
+        );
+
+        // Session mode authenticates with the session cookie.
+        let client = crate::v2()
+            .auth("user", "pwd")
+            .session(true)
+            .build()
+            .unwrap();
+        assert!(client.credentials().is_empty());
+    }
+
+    #[test]
+    fn login_requires_session_mode() {
+        let client = crate::v2().auth("user", "pwd").build().unwrap();
+        assert!(matches!(client.login(), Err(Error::Login(user)) if user == "user"));
+        // Logging out without session is a no-op.
+        assert!(client.logout().is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests_client_transport {
+    use super::*;
+    use crate::{Cache, CacheEndpoint, FakeResponse, FakeTransport, RetryPolicy};
+    use std::time::Duration;
+
+    #[test]
+    fn product_typed() {
+        let fake = FakeTransport::new().route(
+            "https://world.openfoodfacts.org/api/v2/product/123*",
+            FakeResponse::json(r#"{"code": "123", "status": 1, "product": {"code": "123"}}"#),
+        );
+        let client = crate::v2().build_with(fake);
+        let output = Output::new().fields("code");
+        let response = client.product_typed("123", Some(output)).unwrap();
+        assert!(response.is_found());
+
+        let requests = client.transport().requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, Method::GET);
+        assert_eq!(requests[0].query("fields"), Some(String::from("code")));
+    }
+
+    #[test]
+    fn retries_server_errors() {
+        let fake = FakeTransport::new().route("*", FakeResponse::status(503));
+        let retry = RetryPolicy::new(2).base_delay(Duration::ZERO);
+        let client = crate::v2().retry(retry).build_with(fake);
+        let response = client.taxonomy("nova_groups").unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(client.transport().requests().len(), 2);
+    }
+
+    #[test]
+    fn taxonomy_nocache() {
+        let fake = FakeTransport::new().route("*", FakeResponse::json("{}"));
+        let cache = Cache::memory(10).ttl(CacheEndpoint::Taxonomy, Duration::from_secs(3600));
+        let client = crate::v2().cache(cache).build_with(fake);
+        client.taxonomy("nova_groups").unwrap();
+        client.taxonomy("nova_groups").unwrap();
+        assert_eq!(client.transport().requests().len(), 1);
+        client.taxonomy_nocache("nova_groups").unwrap();
+        let requests = client.transport().requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].query("nocache"), Some(String::from("true")));
+
+        let output = Output::new().nocache(true);
+        client.categories(Some(output.clone())).unwrap();
+        client.nutrients(Some(output)).unwrap();
+        let requests = client.transport().requests();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[2].query("nocache"), Some(String::from("true")));
+        assert_eq!(requests[3].query("nocache"), Some(String::from("true")));
+    }
+
+    #[test]
+    fn product_barcode() {
+        let client = crate::v2().build_with(FakeTransport::new());
+        let barcode = crate::Barcode::parse("036000291452").unwrap();
+        client.product(&barcode, None).unwrap();
+        assert_eq!(
+            client.transport().requests()[0].url.path(),
+            "/api/v2/product/0036000291452"
+        );
+    }
+
+    #[test]
+    fn error_for_status() {
+        let fake = FakeTransport::new()
+            .route(
+                "*/api/v2/product/1*",
+                FakeResponse::json(r#"{"status": 0, "status_verbose": "product not found"}"#),
+            )
+            .route(
+                "*/brands.json",
+                FakeResponse::status(429).header("retry-after", "5"),
+            )
+            .route("*", FakeResponse::status(404));
+        let client = crate::v2()
+            .retry(RetryPolicy::none())
+            .error_for_status(true)
+            .build_with(fake);
+        assert!(matches!(
+            client.product_typed("1", None),
+            Err(Error::ApiStatus { status: 0, .. })
+        ));
+        assert!(matches!(
+            client.facet("brands", None),
+            Err(Error::RateLimited { retry_after: Some(d) }) if d == Duration::from_secs(5)
+        ));
+        assert!(matches!(
+            client.taxonomy("unknown"),
+            Err(Error::NotFound(_))
+        ));
+        // Missing in all the flavors.
+        assert!(client.product_any_flavor("2", None).unwrap().is_none());
+    }
+
+    #[test]
+    fn error_responses_by_default() {
+        let fake = FakeTransport::new()
+            .route(
+                "*/api/v2/product/1*",
+                FakeResponse::json(r#"{"status": 0}"#),
+            )
+            .route(
+                "*/api/v2/product/2*",
+                FakeResponse::json(r#"{"status": []}"#),
+            )
+            .route("*", FakeResponse::status(404));
+        let client = crate::v2().build_with(fake);
+        assert!(!client.product_typed("1", None).unwrap().is_found());
+        assert!(matches!(
+            client.product_typed("2", None),
+            Err(Error::Decode { path, .. }) if path == "status"
+        ));
+        let response = client.taxonomy("unknown").unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn save_product_form() {
+        let fake = FakeTransport::new().route(
+            "*/cgi/product_jqm2.pl",
+            FakeResponse::json(r#"{"status": 1, "status_verbose": "fields saved"}"#),
+        );
+        let client = crate::v2().auth("user", "pwd").build_with(fake);
+        let edit = ProductEdit::new("123").brands("Acme");
+        assert!(client.save_product(edit).unwrap().is_ok());
+
+        let request = &client.transport().requests()[0];
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(
+            request.headers[CONTENT_TYPE],
+            "application/x-www-form-urlencoded"
+        );
+        let body = String::from_utf8(request.body.clone().unwrap()).unwrap();
+        assert_eq!(body, "code=123&brands=Acme&user_id=user&password=pwd");
+    }
+
+    #[cfg(feature = "dump")]
+    #[test]
+    fn sync_deltas() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::collections::HashMap;
+        use std::io::Write;
+
+        fn gzip(jsonl: &str) -> FakeResponse {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(jsonl.as_bytes()).unwrap();
+            FakeResponse::status(200).body(encoder.finish().unwrap())
+        }
+
+        let index = FakeResponse::status(200).body(
+            "openfoodfacts_products_100_200.json.gz\n\
+             openfoodfacts_products_200_300.json.gz\n\
+             openfoodfacts_products_300_400.json.gz\n",
+        );
+        let delta_200 = "{\"code\": \"1\", \"product_name\": \"v2\"}\n\
+                         not json\n\
+                         {\"code\": \"2\"}\n";
+        let delta_300 = "{\"code\": \"1\", \"product_name\": \"v3\"}\n";
+
+        let dir = std::env::temp_dir().join(format!("off-sync-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let checkpoint = dir.join("checkpoint.json");
+        Checkpoint::since(200).save(&checkpoint).unwrap();
+        let mut store = HashMap::new();
+
+        // The last delta is missing: the sync stops after the first one.
+        let fake = FakeTransport::new()
+            .route("*/data/delta/index.txt", index.clone())
+            .route("*/openfoodfacts_products_200_300.json.gz", gzip(delta_200))
+            .route("*", FakeResponse::status(404));
+        let client = crate::v2().retry(RetryPolicy::none()).build_with(fake);
+        assert!(matches!(
+            client.sync_deltas(&mut store, &checkpoint),
+            Err(Error::NotFound(_))
+        ));
+        assert_eq!(
+            client.transport().requests()[0].url.as_str(),
+            "https://static.openfoodfacts.org/data/delta/index.txt"
+        );
+        assert_eq!(store.len(), 2);
+        assert_eq!(Checkpoint::load(&checkpoint).unwrap().until, Some(300));
+
+        // The next sync resumes with the missing delta.
+        let fake = FakeTransport::new()
+            .route("*/data/delta/index.txt", index)
+            .route("*/openfoodfacts_products_300_400.json.gz", gzip(delta_300))
+            .route("*", FakeResponse::status(404));
+        let client = crate::v2().build_with(fake);
+        let report = client.sync_deltas(&mut store, &checkpoint).unwrap();
+        assert_eq!(report.applied, ["openfoodfacts_products_300_400.json.gz"]);
+        assert_eq!(report.products, 1);
+        assert!(!report.gap);
+        assert_eq!(report.checkpoint.until, Some(400));
+        assert_eq!(store["1"].product_name.as_deref(), Some("v3"));
+
+        // Up to date.
+        let report = client.sync_deltas(&mut store, &checkpoint).unwrap();
+        assert!(report.applied.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "dump")]
+    #[test]
+    fn sync_deltas_rollback() {
+        // Counts the upserts and rollbacks.
+        #[derive(Default)]
+        struct Store {
+            upserts: u32,
+            rollbacks: u32,
+        }
+
+        impl ProductStore for Store {
+            fn upsert(&mut self, _: Product) -> std::result::Result<(), Error> {
+                self.upserts += 1;
+                Ok(())
+            }
+
+            fn rollback(&mut self) -> std::result::Result<(), Error> {
+                self.rollbacks += 1;
+                Ok(())
+            }
+        }
+
+        // A delta truncated after its first product.
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        std::io::Write::write_all(&mut encoder, b"{\"code\": \"1\"}\n{\"code\": \"2\"}\n").unwrap();
+        let mut delta = encoder.finish().unwrap();
+        delta.truncate(delta.len() - 8);
+        let fake = FakeTransport::new()
+            .route(
+                "*/data/delta/index.txt",
+                FakeResponse::status(200).body("openfoodfacts_products_100_200.json.gz\n"),
+            )
+            .route(
+                "*/openfoodfacts_products_*",
+                FakeResponse::status(200).body(delta),
+            );
+        let client = crate::v2().build_with(fake);
+        let dir = std::env::temp_dir().join(format!("off-rollback-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let checkpoint = dir.join("checkpoint.json");
+        let mut store = Store::default();
+        assert!(client.sync_deltas(&mut store, &checkpoint).is_err());
+        assert_eq!(store.rollbacks, 1);
+        assert_eq!(Checkpoint::load(&checkpoint).unwrap().until, None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "dump")]
+    #[test]
+    fn deltas_bypass_cache() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"{\"code\": \"1\"}\n").unwrap();
+        let delta = encoder.finish().unwrap();
+        let fake = FakeTransport::new()
+            .route(
+                "*/data/delta/index.txt",
+                FakeResponse::status(200)
+                    .header("cache-control", "max-age=3600")
+                    .body("openfoodfacts_products_100_200.json.gz\n"),
+            )
+            .route(
+                "*/data/delta/openfoodfacts_products_*",
+                FakeResponse::status(200)
+                    .header("cache-control", "max-age=3600")
+                    .body(delta),
+            );
+        let cache = Cache::memory(10).ttl(CacheEndpoint::Other, Duration::from_secs(3600));
+        let client = crate::v2().cache(cache).build_with(fake);
+        for _ in 0..2 {
+            let deltas = client.delta_index().unwrap();
+            let codes: Vec<String> = client
+                .delta(&deltas[0])
+                .unwrap()
+                .products()
+                .map(|p| p.unwrap().code)
+                .collect();
+            assert_eq!(codes, ["1"]);
+        }
+        assert_eq!(client.transport().requests().len(), 4);
+    }
+}
+
 #[cfg(test)]
 mod tests_client_v0 {
     use super::*;
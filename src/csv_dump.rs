@@ -0,0 +1,289 @@
+//! Reader for the OFF CSV export.
+//!
+//! The database is also exported daily as `en.openfoodfacts.org.products.csv.gz`,
+//! a tab-separated file with one product per row. A [CsvDump] maps the rows
+//! into the [Product] model, like the JSONL [crate::dump::Dump]. Requires the
+//! `dump` feature.
+//!
+//! ```no_run
+//! use openfoodfacts::csv_dump::CsvDump;
+//!
+//! # fn main() -> Result<(), openfoodfacts::Error> {
+//! let mut dump = CsvDump::open("en.openfoodfacts.org.products.csv.gz")?;
+//! for product in dump.products() {
+//!     let product = product?;
+//!     println!("{} {:?}", product.code, product.categories_tags);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use crate::client::Error;
+use crate::dump::RawProduct;
+use crate::product::Product;
+use csv::{ReaderBuilder, StringRecord};
+use flate2::read::MultiGzDecoder;
+use serde::Deserialize;
+use serde_json::{Number, Value};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+// How the values of a column are mapped into the product.
+#[derive(Debug, Clone, PartialEq)]
+enum Column {
+    // `{name}_tags`: a comma-separated list.
+    Tags(String),
+    // `{nutrient}_100g`, `{nutrient}_serving`: a number in `nutriments`.
+    Nutriment(String),
+    // Any other column: a string.
+    Text(String),
+}
+
+impl Column {
+    fn new(name: &str) -> Self {
+        if name.ends_with("_tags") {
+            Self::Tags(name.to_string())
+        } else if name.ends_with("_100g") || name.ends_with("_serving") {
+            Self::Nutriment(name.to_string())
+        } else if name == "lang" {
+            // The main language, `lc` in the API.
+            Self::Text(String::from("lc"))
+        } else {
+            Self::Text(name.to_string())
+        }
+    }
+}
+
+/// A reader of the tab-separated CSV export.
+///
+/// The columns are read from the header, so new columns are supported
+/// without changes:
+///
+/// * `*_tags` columns are split on commas into lists.
+/// * `*_100g` and `*_serving` columns are nutriments, i.e. `sugars_100g`.
+/// * `lang` is the main language `lc`.
+/// * Other columns are strings. Those not covered by the [Product] model are
+///   kept in [Product::extra].
+///
+/// Empty values are omitted. Rows with a wrong number of values or invalid
+/// UTF-8 are skipped and counted in [CsvDump::malformed].
+#[derive(Debug)]
+pub struct CsvDump<R> {
+    reader: csv::Reader<R>,
+    columns: Vec<Column>,
+    headers: StringRecord,
+    malformed: u64,
+}
+
+impl CsvDump<MultiGzDecoder<File>> {
+    /// Opens a gzip-compressed CSV export.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::gzip(File::open(path)?)
+    }
+}
+
+impl<R: Read> CsvDump<MultiGzDecoder<R>> {
+    /// Reads a gzip-compressed CSV stream.
+    pub fn gzip(reader: R) -> Result<Self, Error> {
+        CsvDump::new(MultiGzDecoder::new(reader))
+    }
+}
+
+impl<R: Read> CsvDump<R> {
+    /// Reads an uncompressed CSV stream and its header.
+    pub fn new(reader: R) -> Result<Self, Error> {
+        // Values are not quoted and may contain quotes.
+        let mut reader = ReaderBuilder::new()
+            .delimiter(b'\t')
+            .quoting(false)
+            .from_reader(reader);
+        let headers = reader.headers().map_err(csv_error)?.clone();
+        Ok(Self {
+            reader,
+            columns: headers.iter().map(Column::new).collect(),
+            headers,
+            malformed: 0,
+        })
+    }
+
+    /// Returns the column names.
+    pub fn headers(&self) -> Vec<&str> {
+        self.headers.iter().collect()
+    }
+
+    /// Returns the number of malformed rows skipped so far.
+    pub fn malformed(&self) -> u64 {
+        self.malformed
+    }
+
+    /// Returns the products as raw JSON objects, in the layout of the API.
+    pub fn raw(&mut self) -> CsvRawProducts<'_, R> {
+        CsvRawProducts { dump: self }
+    }
+
+    /// Returns the products decoded into the [Product] model.
+    pub fn products(&mut self) -> CsvProducts<'_, R> {
+        CsvProducts { dump: self }
+    }
+
+    // Reads the next well-formed row.
+    fn next_raw(&mut self) -> Option<Result<RawProduct, Error>> {
+        let mut record = StringRecord::new();
+        loop {
+            match self.reader.read_record(&mut record) {
+                Ok(true) => return Some(Ok(self.row(&record))),
+                Ok(false) => return None,
+                Err(err) if err.is_io_error() => return Some(Err(csv_error(err))),
+                Err(_) => self.malformed += 1,
+            }
+        }
+    }
+
+    // Maps a row into a JSON object.
+    fn row(&self, record: &StringRecord) -> RawProduct {
+        let mut raw = RawProduct::new();
+        let mut nutriments = RawProduct::new();
+        for (column, value) in self.columns.iter().zip(record) {
+            if value.is_empty() {
+                continue;
+            }
+            match column {
+                Column::Tags(name) => {
+                    let tags = value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|t| !t.is_empty())
+                        .map(|t| Value::String(t.to_string()))
+                        .collect();
+                    raw.insert(name.clone(), Value::Array(tags));
+                }
+                Column::Nutriment(name) => {
+                    let number = value
+                        .parse::<f64>()
+                        .ok()
+                        .and_then(Number::from_f64)
+                        .map_or_else(|| Value::String(value.to_string()), Value::Number);
+                    nutriments.insert(name.clone(), number);
+                }
+                Column::Text(name) => {
+                    raw.insert(name.clone(), Value::String(value.to_string()));
+                }
+            }
+        }
+        if !nutriments.is_empty() {
+            raw.insert(String::from("nutriments"), Value::Object(nutriments));
+        }
+        raw
+    }
+}
+
+/// The iterator returned by [CsvDump::raw].
+#[derive(Debug)]
+pub struct CsvRawProducts<'a, R> {
+    dump: &'a mut CsvDump<R>,
+}
+
+impl<R: Read> Iterator for CsvRawProducts<'_, R> {
+    type Item = Result<RawProduct, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.dump.next_raw()
+    }
+}
+
+/// The iterator returned by [CsvDump::products].
+#[derive(Debug)]
+pub struct CsvProducts<'a, R> {
+    dump: &'a mut CsvDump<R>,
+}
+
+impl<R: Read> Iterator for CsvProducts<'_, R> {
+    type Item = Result<Product, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.dump.next_raw()? {
+                Ok(raw) => match Product::deserialize(Value::Object(raw)) {
+                    Ok(product) => return Some(Ok(product)),
+                    Err(_) => self.dump.malformed += 1,
+                },
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+// Only the header and I/O errors are reported, other CSV errors only happen
+// on malformed rows.
+fn csv_error(err: csv::Error) -> Error {
+    Error::Io(err.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    const FIXTURE: &str = include_str!("../tests/fixtures/products.csv");
+
+    #[test]
+    fn products() {
+        let mut dump = CsvDump::new(FIXTURE.as_bytes()).unwrap();
+        assert_eq!(dump.headers()[..3], ["code", "url", "created_t"]);
+        let products: Vec<Product> = dump.products().map(Result::unwrap).collect();
+        assert_eq!(products.len(), 3);
+        // A row with a missing column.
+        assert_eq!(dump.malformed(), 1);
+
+        let nutella = &products[0];
+        assert_eq!(nutella.code, "3017620422003");
+        assert_eq!(nutella.lc.as_deref(), Some("fr"));
+        assert_eq!(
+            nutella.product_name.as_deref(),
+            Some("Nutella \"original\"")
+        );
+        assert_eq!(nutella.created_t, Some(1457680652));
+        assert_eq!(nutella.categories_tags, ["en:spreads", "en:sweet-spreads"]);
+        assert_eq!(nutella.labels_tags, Vec::<String>::new());
+        assert_eq!(nutella.additives_tags, ["en:e322", "en:e322i"]);
+        assert_eq!(nutella.nutriscore_score, Some(26));
+        assert_eq!(nutella.nova_group, Some(4));
+        assert_eq!(nutella.nutriments.energy_kcal_100g, Some(539.0));
+        assert_eq!(nutella.nutriments.saturated_fat_100g, Some(10.6));
+        assert_eq!(nutella.nutriments.per_100g("nova-group"), None);
+        assert_eq!(nutella.nutriments.per_serving("salt"), Some(0.0161));
+        // Columns not covered by the model.
+        assert_eq!(
+            nutella.extra["main_category_en"],
+            Value::String(String::from("Sweet spreads"))
+        );
+
+        let water = &products[2];
+        assert_eq!(water.code, "3274080005003");
+        assert_eq!(water.nutriments.fat_100g, Some(0.0));
+        assert_eq!(water.nutriments.sugars_100g, None);
+        assert_eq!(water.nutriscore_score, None);
+    }
+
+    #[test]
+    fn new_columns() {
+        let csv = "code\tproduct_name\tnew_field\tnew_things_tags\tpolyols_100g\n\
+                   123\tTest\tvalue\ten:a,en:b\t1.5\n";
+        let mut dump = CsvDump::new(csv.as_bytes()).unwrap();
+        let raw = dump.raw().next().unwrap().unwrap();
+        assert_eq!(raw["new_field"], "value");
+        assert_eq!(raw["new_things_tags"], serde_json::json!(["en:a", "en:b"]));
+        assert_eq!(raw["nutriments"]["polyols_100g"], 1.5);
+    }
+
+    #[test]
+    fn gzip() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(FIXTURE.as_bytes()).unwrap();
+        let gz = encoder.finish().unwrap();
+        let mut dump = CsvDump::gzip(&gz[..]).unwrap();
+        assert_eq!(dump.products().count(), 3);
+    }
+}
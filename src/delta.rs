@@ -0,0 +1,230 @@
+//! Sync of a local mirror with the OFF delta exports.
+//!
+//! Besides the full dump, OFF publishes daily deltas: gzip-compressed JSONL
+//! files of the products changed over a period, listed in
+//! `https://static.openfoodfacts.org/data/delta/index.txt`. The deltas are
+//! named after the period they cover, i.e.
+//! `openfoodfacts_products_1700000000_1700086400.json.gz` for the changes
+//! between the two Unix timestamps.
+//!
+//! [OffClient::sync_deltas](crate::OffClient::sync_deltas) downloads the
+//! deltas not applied yet and upserts their products into a [ProductStore].
+//! The end of the last applied delta is saved in a [Checkpoint] file after
+//! each delta, so an interrupted sync resumes where it stopped. Requires the
+//! `dump` feature.
+//!
+//! ```no_run
+//! use openfoodfacts as off;
+//! use std::collections::HashMap;
+//!
+//! # fn main() -> Result<(), off::Error> {
+//! let client = off::v2().build()?;
+//! let mut mirror = HashMap::new();
+//! let report = client.sync_deltas(&mut mirror, "mirror.checkpoint.json")?;
+//! println!("{} products from {} deltas", report.products, report.applied.len());
+//! # Ok(())
+//! # }
+//! ```
+use crate::client::Error;
+use crate::product::Product;
+use crate::response::from_slice;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+// The name of the deltas: `{PREFIX}{start}_{end}{SUFFIX}`.
+const PREFIX: &str = "openfoodfacts_products_";
+const SUFFIX: &str = ".json.gz";
+
+/// A delta export, covering the product changes from `start` to `end`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeltaFile {
+    /// The file name, relative to the delta index.
+    pub name: String,
+    /// The start of the period, a Unix timestamp.
+    pub start: u64,
+    /// The end of the period, a Unix timestamp.
+    pub end: u64,
+}
+
+impl DeltaFile {
+    /// Parses a delta file name. Returns None if the name does not follow the
+    /// `openfoodfacts_products_{start}_{end}.json.gz` pattern.
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim();
+        let period = name.strip_prefix(PREFIX)?.strip_suffix(SUFFIX)?;
+        let (start, end) = period.split_once('_')?;
+        Some(Self {
+            name: name.to_string(),
+            start: start.parse().ok()?,
+            end: end.parse().ok()?,
+        })
+    }
+}
+
+/// Parses the delta index, one file name per line, and returns the deltas
+/// sorted by start. Other lines are ignored.
+pub fn parse_index(index: &str) -> Vec<DeltaFile> {
+    let mut deltas: Vec<DeltaFile> = index.lines().filter_map(DeltaFile::parse).collect();
+    deltas.sort_by_key(|d| (d.start, d.end));
+    deltas
+}
+
+/// A local store of products the deltas are applied to.
+///
+/// Products of a delta replace the stored products with the same code.
+/// Implemented for `HashMap<String, Product>`, keyed by code.
+pub trait ProductStore {
+    /// Inserts the product, or replaces the product with the same code.
+    fn upsert(&mut self, product: Product) -> Result<(), Error>;
+
+    /// Persists the upserts. Called after each delta, before the checkpoint
+    /// is saved.
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Discards the upserts since the last flush, if possible. Called when a
+    /// delta fails to apply: it is applied again by the next sync.
+    fn rollback(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl ProductStore for HashMap<String, Product> {
+    fn upsert(&mut self, product: Product) -> Result<(), Error> {
+        self.insert(product.code.clone(), product);
+        Ok(())
+    }
+}
+
+/// The sync progress, saved as JSON.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The end of the last applied delta, a Unix timestamp. None if no delta
+    /// was applied yet: all the deltas of the index are then applied.
+    pub until: Option<u64>,
+}
+
+impl Checkpoint {
+    /// Returns a checkpoint at the given timestamp, i.e. the export time of
+    /// the full dump the mirror was loaded from.
+    pub fn since(until: u64) -> Self {
+        Self { until: Some(until) }
+    }
+
+    /// Loads the checkpoint file. A missing file is an empty checkpoint.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        match fs::read(path) {
+            Ok(json) => from_slice(&json),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Saves the checkpoint file. The file is replaced at once, so an
+    /// interrupted save leaves the previous checkpoint.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let json = serde_json::to_vec(self).expect("checkpoint serialization");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Returns the deltas not applied yet, i.e. ending after the checkpoint.
+    pub fn pending<'a>(&self, deltas: &'a [DeltaFile]) -> Vec<&'a DeltaFile> {
+        deltas
+            .iter()
+            .filter(|d| self.until.is_none_or(|until| d.end > until))
+            .collect()
+    }
+
+    /// Returns true if the pending deltas do not start at the checkpoint,
+    /// i.e. the deltas in between were removed from the index. The mirror
+    /// then misses changes and should be reloaded from a full dump.
+    pub fn has_gap(&self, pending: &[&DeltaFile]) -> bool {
+        match (self.until, pending.first()) {
+            (Some(until), Some(first)) => first.start > until,
+            _ => false,
+        }
+    }
+}
+
+/// The outcome of [OffClient::sync_deltas](crate::OffClient::sync_deltas).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    /// The names of the applied deltas, oldest first.
+    pub applied: Vec<String>,
+    /// The number of upserted products.
+    pub products: u64,
+    /// The number of malformed lines skipped.
+    pub malformed: u64,
+    /// True if deltas between the checkpoint and the oldest available delta
+    /// are missing, see [Checkpoint::has_gap]. They are applied anyway.
+    pub gap: bool,
+    /// The checkpoint after the sync.
+    pub checkpoint: Checkpoint,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INDEX: &str = "openfoodfacts_products_1700086400_1700172800.json.gz\n\
+                         openfoodfacts_products_1700000000_1700086400.json.gz\n\
+                         \n\
+                         README.txt\n";
+
+    #[test]
+    fn index() {
+        let deltas = parse_index(INDEX);
+        assert_eq!(deltas.len(), 2);
+        assert_eq!(
+            deltas[0],
+            DeltaFile {
+                name: String::from("openfoodfacts_products_1700000000_1700086400.json.gz"),
+                start: 1700000000,
+                end: 1700086400,
+            }
+        );
+        assert_eq!(deltas[1].start, 1700086400);
+        assert_eq!(DeltaFile::parse("openfoodfacts_products_1_x.json.gz"), None);
+    }
+
+    #[test]
+    fn pending() {
+        let deltas = parse_index(INDEX);
+        assert_eq!(Checkpoint::default().pending(&deltas).len(), 2);
+        let checkpoint = Checkpoint::since(1700086400);
+        let pending = checkpoint.pending(&deltas);
+        assert_eq!(pending, [&deltas[1]]);
+        assert!(!checkpoint.has_gap(&pending));
+        assert!(Checkpoint::since(1700172800).pending(&deltas).is_empty());
+
+        let checkpoint = Checkpoint::since(1699000000);
+        assert!(checkpoint.has_gap(&checkpoint.pending(&deltas)));
+        assert!(!Checkpoint::default().has_gap(&deltas.iter().collect::<Vec<_>>()));
+    }
+
+    #[test]
+    fn checkpoint_file() {
+        let dir = std::env::temp_dir().join(format!("off-delta-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("checkpoint.json");
+        assert_eq!(Checkpoint::load(&path).unwrap(), Checkpoint::default());
+        Checkpoint::since(1700086400).save(&path).unwrap();
+        assert_eq!(Checkpoint::load(&path).unwrap().until, Some(1700086400));
+
+        fs::write(&path, "{\"until\": \"yesterday\"}").unwrap();
+        assert!(matches!(
+            Checkpoint::load(&path),
+            Err(Error::Decode { path, .. }) if path == "until"
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
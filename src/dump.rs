@@ -0,0 +1,355 @@
+//! Streaming reader for the OFF JSONL database dump.
+//!
+//! The full database is exported daily as `openfoodfacts-products.jsonl.gz`,
+//! one JSON product per line. A [Dump] reads it one line at a time, so memory
+//! stays bounded whatever the size of the file. Malformed lines are skipped
+//! and counted. Requires the `dump` feature.
+//!
+//! ```no_run
+//! use openfoodfacts::dump::Dump;
+//!
+//! # fn main() -> Result<(), openfoodfacts::Error> {
+//! let mut dump = Dump::open("openfoodfacts-products.jsonl.gz")?
+//!     .fields(&["code", "product_name", "nutriscore_grade"]);
+//! for product in dump.products() {
+//!     let product = product?;
+//!     println!("{} {:?}", product.code, product.nutriscore_grade);
+//! }
+//! println!("{} malformed lines", dump.malformed());
+//! # Ok(())
+//! # }
+//! ```
+use crate::client::Error;
+use crate::product::Product;
+use flate2::read::MultiGzDecoder;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+/// A JSON product of the dump, undecoded.
+pub type RawProduct = Map<String, Value>;
+
+// The number of lines sent at once to the decoding threads.
+const BATCH_SIZE: usize = 256;
+
+/// A reader of JSONL products.
+///
+/// Lines are read one at a time from any buffered reader, i.e. a gzip file
+/// opened with [Dump::open] or a plain JSONL file given to [Dump::new]. Empty
+/// lines are ignored. Lines that are not JSON objects, or that do not match
+/// the [Product] model, or are not valid UTF-8, are skipped and counted in
+/// [Dump::malformed]. A read error, i.e. a truncated file, ends the
+/// iteration.
+#[derive(Debug)]
+pub struct Dump<R> {
+    reader: R,
+    fields: Option<Vec<String>>,
+    lines: u64,
+    malformed: u64,
+    // Set after a read error: the stream cannot be resumed.
+    failed: bool,
+}
+
+impl Dump<BufReader<MultiGzDecoder<File>>> {
+    /// Opens a gzip-compressed JSONL dump.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::gzip(File::open(path)?))
+    }
+}
+
+impl<R: Read> Dump<BufReader<MultiGzDecoder<R>>> {
+    /// Reads a gzip-compressed JSONL stream.
+    pub fn gzip(reader: R) -> Self {
+        Dump::new(BufReader::new(MultiGzDecoder::new(reader)))
+    }
+}
+
+impl<R: BufRead> Dump<R> {
+    /// Reads an uncompressed JSONL stream.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            fields: None,
+            lines: 0,
+            malformed: 0,
+            failed: false,
+        }
+    }
+
+    /// Keeps only the given top-level fields of the products. Other fields
+    /// are dropped before decoding, which saves memory and time. The `code`
+    /// is always kept: it is required to decode a [Product].
+    pub fn fields(mut self, fields: &[&str]) -> Self {
+        let mut fields: Vec<String> = fields.iter().map(|f| f.to_string()).collect();
+        if !fields.iter().any(|f| f == "code") {
+            fields.push(String::from("code"));
+        }
+        self.fields = Some(fields);
+        self
+    }
+
+    /// Returns the number of lines read so far.
+    pub fn lines(&self) -> u64 {
+        self.lines
+    }
+
+    /// Returns the number of malformed lines skipped so far.
+    pub fn malformed(&self) -> u64 {
+        self.malformed
+    }
+
+    /// Returns the products as raw JSON objects.
+    pub fn raw(&mut self) -> RawProducts<'_, R> {
+        RawProducts { dump: self }
+    }
+
+    /// Returns the products decoded into the [Product] model.
+    pub fn products(&mut self) -> Products<'_, R> {
+        Products { dump: self }
+    }
+
+    /// Decodes the products on `threads` threads and calls `f` with each
+    /// one, in no particular order. Lines are read on the calling thread and
+    /// handed over in batches through a bounded queue, so memory stays
+    /// bounded.
+    ///
+    /// Stops at the first read error. The threads finish their current batch
+    /// first.
+    pub fn for_each_parallel<F>(&mut self, threads: usize, f: F) -> Result<(), Error>
+    where
+        F: Fn(Product) + Sync,
+    {
+        let threads = threads.max(1);
+        let malformed = AtomicU64::new(0);
+        let (sender, receiver) = mpsc::sync_channel::<Vec<Vec<u8>>>(threads * 2);
+        let receiver = Mutex::new(receiver);
+        let fields = self.fields.clone();
+        let fields = fields.as_deref();
+        let result = thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| loop {
+                    // The lock is released before decoding.
+                    let batch = match receiver.lock().unwrap().recv() {
+                        Ok(batch) => batch,
+                        Err(_) => break,
+                    };
+                    for line in batch {
+                        match decode(&line, fields) {
+                            Some(product) => f(product),
+                            None => {
+                                malformed.fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+                    }
+                });
+            }
+            let mut batch = Vec::with_capacity(BATCH_SIZE);
+            let result = loop {
+                match self.next_line() {
+                    Some(Ok(line)) => {
+                        batch.push(line);
+                        if batch.len() == BATCH_SIZE {
+                            let full =
+                                std::mem::replace(&mut batch, Vec::with_capacity(BATCH_SIZE));
+                            if sender.send(full).is_err() {
+                                break Ok(());
+                            }
+                        }
+                    }
+                    Some(Err(err)) => break Err(err),
+                    None => break Ok(()),
+                }
+            };
+            if !batch.is_empty() {
+                let _ = sender.send(batch);
+            }
+            // Closing the channel stops the threads.
+            drop(sender);
+            result
+        });
+        self.malformed += malformed.into_inner();
+        result
+    }
+
+    // Reads the next non-empty line. Returns None after a read error. Lines
+    // are read as bytes: invalid UTF-8 is a malformed line, not a read error.
+    fn next_line(&mut self) -> Option<Result<Vec<u8>, Error>> {
+        if self.failed {
+            return None;
+        }
+        loop {
+            let mut line = Vec::new();
+            match self.reader.read_until(b'\n', &mut line) {
+                Ok(0) => return None,
+                Ok(_) => {
+                    self.lines += 1;
+                    if !line.iter().all(u8::is_ascii_whitespace) {
+                        return Some(Ok(line));
+                    }
+                }
+                Err(err) => {
+                    self.failed = true;
+                    return Some(Err(err.into()));
+                }
+            }
+        }
+    }
+
+    // Reads and parses the next well-formed line.
+    fn next_raw(&mut self) -> Option<Result<RawProduct, Error>> {
+        loop {
+            let line = match self.next_line()? {
+                Ok(line) => line,
+                Err(err) => return Some(Err(err)),
+            };
+            match parse(&line, self.fields.as_deref()) {
+                Some(raw) => return Some(Ok(raw)),
+                None => self.malformed += 1,
+            }
+        }
+    }
+}
+
+/// The iterator returned by [Dump::raw].
+#[derive(Debug)]
+pub struct RawProducts<'a, R> {
+    dump: &'a mut Dump<R>,
+}
+
+impl<R: BufRead> Iterator for RawProducts<'_, R> {
+    type Item = Result<RawProduct, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.dump.next_raw()
+    }
+}
+
+/// The iterator returned by [Dump::products].
+#[derive(Debug)]
+pub struct Products<'a, R> {
+    dump: &'a mut Dump<R>,
+}
+
+impl<R: BufRead> Iterator for Products<'_, R> {
+    type Item = Result<Product, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.dump.next_raw()? {
+                Ok(raw) => match Product::deserialize(Value::Object(raw)) {
+                    Ok(product) => return Some(Ok(product)),
+                    Err(_) => self.dump.malformed += 1,
+                },
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+// Parses a line into a JSON object, keeping only the given fields.
+fn parse(line: &[u8], fields: Option<&[String]>) -> Option<RawProduct> {
+    let mut raw: RawProduct = serde_json::from_slice(line).ok()?;
+    if let Some(fields) = fields {
+        raw.retain(|key, _| fields.contains(key));
+    }
+    Some(raw)
+}
+
+fn decode(line: &[u8], fields: Option<&[String]>) -> Option<Product> {
+    let raw = parse(line, fields)?;
+    Product::deserialize(Value::Object(raw)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    const FIXTURE: &[u8] = include_bytes!("../tests/fixtures/products.jsonl.gz");
+
+    #[test]
+    fn products() {
+        let mut dump = Dump::gzip(FIXTURE);
+        let codes: Vec<String> = dump.products().map(|p| p.unwrap().code).collect();
+        assert_eq!(
+            codes,
+            [
+                "3017620422003",
+                "5449000000996",
+                "3274080005003",
+                "20123451"
+            ]
+        );
+        // An invalid JSON line, invalid UTF-8, an array and an invalid code.
+        assert_eq!(dump.malformed(), 4);
+        assert_eq!(dump.lines(), 9);
+    }
+
+    #[test]
+    fn fields() {
+        let mut dump = Dump::gzip(FIXTURE).fields(&["code", "nutriscore_grade"]);
+        let raw: Vec<RawProduct> = dump.raw().map(Result::unwrap).collect();
+        // The invalid code is only detected when decoding products.
+        assert_eq!(raw.len(), 5);
+        assert!(raw
+            .iter()
+            .all(|p| p.keys().all(|k| k == "code" || k == "nutriscore_grade")));
+        assert_eq!(raw[0]["nutriscore_grade"], "e");
+        assert_eq!(dump.malformed(), 3);
+
+        let mut dump = Dump::gzip(FIXTURE).fields(&["code"]);
+        let product = dump.products().next().unwrap().unwrap();
+        assert_eq!(product.product_name, None);
+
+        // The code is kept even if not asked for.
+        let mut dump = Dump::gzip(FIXTURE).fields(&["product_name"]);
+        let products: Vec<Product> = dump.products().map(Result::unwrap).collect();
+        assert_eq!(products.len(), 4);
+        assert_eq!(products[0].code, "3017620422003");
+        assert_eq!(products[0].product_name.as_deref(), Some("Nutella"));
+        assert_eq!(dump.malformed(), 4);
+    }
+
+    #[test]
+    fn plain() {
+        let jsonl = "{\"code\": \"1\"}\n\n{\"code\": 2}\n";
+        let mut dump = Dump::new(jsonl.as_bytes());
+        let codes: Vec<String> = dump.products().map(|p| p.unwrap().code).collect();
+        assert_eq!(codes, ["1", "2"]);
+
+        // Invalid UTF-8 is skipped, not a read error.
+        let jsonl = b"{\"code\": \"1\"}\n{\"code\": \"\xff\"}\n{\"code\": \"3\"}\n";
+        let mut dump = Dump::new(&jsonl[..]);
+        let codes: Vec<String> = dump.products().map(|p| p.unwrap().code).collect();
+        assert_eq!(codes, ["1", "3"]);
+        assert_eq!(dump.malformed(), 1);
+    }
+
+    #[test]
+    fn parallel() {
+        let codes = Mutex::new(HashSet::new());
+        let mut dump = Dump::gzip(FIXTURE);
+        dump.for_each_parallel(3, |product| {
+            codes.lock().unwrap().insert(product.code);
+        })
+        .unwrap();
+        let codes = codes.into_inner().unwrap();
+        assert_eq!(codes.len(), 4);
+        assert!(codes.contains("20123451"));
+        assert_eq!(dump.malformed(), 4);
+    }
+
+    #[test]
+    fn truncated() {
+        let mut dump = Dump::gzip(&FIXTURE[..FIXTURE.len() / 2]);
+        let results: Vec<_> = dump.products().collect();
+        // The iteration stops after the read error.
+        assert!(matches!(results.last(), Some(Err(Error::Io(_)))));
+        assert!(dump.products().next().is_none());
+    }
+}
@@ -0,0 +1,550 @@
+//! Local Eco-Score estimation.
+//!
+//! The Eco-Score starts from the life-cycle analysis (LCA) score of the
+//! Agribalyse category of the product, then applies bonuses and maluses for
+//! the production labels, the origins of the ingredients, the packaging and
+//! the threatened species. The computed [EcoScore] has the same shape as the
+//! `ecoscore_data` of the OFF products (see [Product::ecoscore_data]), so both
+//! can be compared field by field.
+//!
+//! ```
+//! use openfoodfacts::ecoscore::{CountryScores, EcoScoreInput, Origin, PackagingComponent};
+//! use openfoodfacts::nutriscore::Grade;
+//!
+//! let input = EcoScoreInput {
+//!     ef_total: 0.5,
+//!     labels_tags: vec![String::from("en:eu-organic")],
+//!     origins: vec![Origin::new("en:france", 100.0)],
+//!     packagings: vec![PackagingComponent::new("en:glass", 81.0, 1.0)],
+//!     ..EcoScoreInput::default()
+//! };
+//! let countries = CountryScores::new().country("en:france", 88.0, 98.0);
+//! let ecoscore = input.compute(&countries);
+//! assert_eq!(ecoscore.agribalyse.score, 53);
+//! assert_eq!(ecoscore.score, Some(78));
+//! assert_eq!(ecoscore.grade, Some(Grade::B));
+//! ```
+use crate::nutriscore::Grade;
+use crate::product::{de, Ingredient, Product};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The maximum of the sum of the adjustments.
+pub const MAX_ADJUSTMENTS: i32 = 25;
+
+/// The points of the production system labels. The best label counts.
+const PRODUCTION_SYSTEM_LABELS: &[(&str, i32)] = &[
+    ("fr:nature-et-progres", 20),
+    ("fr:bio-coherence", 20),
+    ("en:demeter", 20),
+    ("en:organic", 15),
+    ("en:eu-organic", 15),
+    ("fr:ab-agriculture-biologique", 15),
+    ("fr:haute-valeur-environnementale", 10),
+    ("en:utz-certified", 10),
+    ("en:rainforest-alliance", 10),
+    ("en:fairtrade-international", 10),
+    ("fr:bleu-blanc-coeur", 10),
+    ("fr:label-rouge", 10),
+    ("en:sustainable-seafood-msc", 10),
+    ("en:responsible-aquaculture-asc", 10),
+];
+
+/// The Agribalyse categories of the OFF categories: the category tag, the
+/// Agribalyse code and the EF single score per kg. A category matches its
+/// products and the products of its subcategories, i.e. "en:pastas" matches
+/// the products tagged "en:dry-pastas", as the OFF `categories_tags` hold all
+/// the parent categories. The footprints are rounded estimates, used only for
+/// the products without an OFF `ecoscore_data`.
+const AGRIBALYSE_CATEGORIES: &[(&str, &str, f64)] = &[
+    ("en:apples", "13039", 0.06),
+    ("en:beef", "6254", 5.6),
+    ("en:camemberts", "12001", 0.79),
+    ("en:colas", "18037", 0.09),
+    ("en:dark-chocolates", "31008", 1.5),
+    ("en:jams", "31032", 0.35),
+    ("en:pastas", "9811", 0.8),
+    ("en:plain-yogurts", "19024", 0.27),
+    ("en:white-rices", "9100", 0.45),
+];
+
+/// The origin of the ingredients when it is not known.
+pub const UNKNOWN_ORIGIN: &str = "en:unknown";
+
+/// A computed Eco-Score, or the `ecoscore_data` of an OFF product.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EcoScore {
+    pub agribalyse: Agribalyse,
+    pub adjustments: Adjustments,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub score: Option<i32>,
+    pub grade: Option<Grade>,
+}
+
+/// The LCA baseline: the Agribalyse category, its environmental footprint
+/// (the EF single score, per kg) and the score computed from it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Agribalyse {
+    #[serde(deserialize_with = "de::opt_string")]
+    pub code: Option<String>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub ef_total: Option<f64>,
+    #[serde(deserialize_with = "de::number")]
+    pub score: i32,
+}
+
+/// The bonuses and maluses.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Adjustments {
+    pub production_system: ProductionSystem,
+    pub origins_of_ingredients: OriginsOfIngredients,
+    pub packaging: Packaging,
+    pub threatened_species: ThreatenedSpecies,
+}
+
+impl Adjustments {
+    /// Returns the sum of the adjustments, capped at [MAX_ADJUSTMENTS].
+    pub fn total(&self) -> i32 {
+        let total = self.production_system.value
+            + self.origins_of_ingredients.value
+            + self.packaging.value
+            + self.threatened_species.value;
+        total.min(MAX_ADJUSTMENTS)
+    }
+}
+
+/// The bonus of the production labels, 0 to 20 points.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProductionSystem {
+    /// The labels giving points.
+    pub labels: Vec<String>,
+    #[serde(deserialize_with = "de::number")]
+    pub value: i32,
+}
+
+/// The bonus or malus of the origins of the ingredients, -5 to 20 points.
+///
+/// `epi_score` and `transportation_score` are the means of the scores of the
+/// origins, weighted by their percentage. `epi_value` (-5 to 5 points) and
+/// `transportation_value` (0 to 15 points) are derived from them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OriginsOfIngredients {
+    pub aggregated_origins: Vec<Origin>,
+    #[serde(deserialize_with = "de::number")]
+    pub epi_score: f64,
+    #[serde(deserialize_with = "de::number")]
+    pub transportation_score: f64,
+    #[serde(deserialize_with = "de::number")]
+    pub epi_value: i32,
+    #[serde(deserialize_with = "de::number")]
+    pub transportation_value: i32,
+    #[serde(deserialize_with = "de::number")]
+    pub value: i32,
+}
+
+/// An origin and the percentage of the ingredients coming from it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Origin {
+    /// The country tag, i.e. "en:france", or [UNKNOWN_ORIGIN].
+    pub origin: String,
+    #[serde(deserialize_with = "de::number")]
+    pub percent: f64,
+}
+
+impl Origin {
+    pub fn new(origin: &str, percent: f64) -> Self {
+        Self {
+            origin: origin.to_string(),
+            percent,
+        }
+    }
+}
+
+/// The malus of the packaging, -15 to 0 points.
+///
+/// The packaging score starts at 100 and each component removes its
+/// material impact (100 - material score) times its shape ratio. Products
+/// without packaging data get the -15 points malus.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Packaging {
+    pub packagings: Vec<PackagingComponent>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub score: Option<f64>,
+    #[serde(deserialize_with = "de::number")]
+    pub value: i32,
+}
+
+/// A packaging component: its material score (0 to 100, the higher the
+/// better) and its shape ratio (i.e. 0.2 for a lid, 1 for a bottle).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PackagingComponent {
+    pub material: Option<String>,
+    pub shape: Option<String>,
+    #[serde(deserialize_with = "de::number")]
+    pub ecoscore_material_score: f64,
+    #[serde(deserialize_with = "de::number")]
+    pub ecoscore_shape_ratio: f64,
+}
+
+impl PackagingComponent {
+    pub fn new(material: &str, material_score: f64, shape_ratio: f64) -> Self {
+        Self {
+            material: Some(material.to_string()),
+            shape: None,
+            ecoscore_material_score: material_score,
+            ecoscore_shape_ratio: shape_ratio,
+        }
+    }
+}
+
+/// The malus of the ingredients threatening species (palm oil), -10 or 0
+/// points.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThreatenedSpecies {
+    pub ingredient: Option<String>,
+    #[serde(deserialize_with = "de::number")]
+    pub value: i32,
+}
+
+/// The Environmental Performance Index (EPI) score and the transportation
+/// score of the origin countries, 0 to 100.
+///
+/// The transportation scores depend on the country where the product is
+/// sold, so the table is given by the caller, i.e. from the OFF Eco-Score
+/// data for the target country. Unknown countries score 0.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CountryScores {
+    scores: HashMap<String, (f64, f64)>,
+}
+
+impl CountryScores {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the scores of the given country tag.
+    pub fn country(mut self, country: &str, epi_score: f64, transportation_score: f64) -> Self {
+        self.scores
+            .insert(country.to_string(), (epi_score, transportation_score));
+        self
+    }
+
+    /// Returns the (EPI, transportation) scores of the given country.
+    pub fn get(&self, country: &str) -> (f64, f64) {
+        self.scores.get(country).copied().unwrap_or_default()
+    }
+}
+
+/// The data used by the Eco-Score.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EcoScoreInput {
+    pub agribalyse_code: Option<String>,
+    /// The EF single score of the Agribalyse category, per kg.
+    pub ef_total: f64,
+    pub labels_tags: Vec<String>,
+    /// The origins of the ingredients. Empty means unknown.
+    pub origins: Vec<Origin>,
+    /// The packaging components. Empty means unknown.
+    pub packagings: Vec<PackagingComponent>,
+    /// The first ingredient threatening species, i.e. "en:palm-oil".
+    pub threatening_ingredient: Option<String>,
+}
+
+impl EcoScoreInput {
+    /// Reads the input from a product.
+    ///
+    /// The Agribalyse category comes from the `ecoscore_data` of the product,
+    /// so the computed Eco-Score matches the OFF one. Products without it get
+    /// the category of their `categories_tags` (see [agribalyse_category]).
+    /// The aggregated origins and the packaging components
+    /// come from the `ecoscore_data`. Products without `ecoscore_data`
+    /// origins use their `origins_tags`, evenly split. The palm oil is found
+    /// with the `from_palm_oil` ingredients or the `en:palm-oil` ingredients
+    /// analysis.
+    ///
+    /// Returns None if the product has no Agribalyse footprint.
+    pub fn from_product(product: &Product) -> Option<Self> {
+        let data = product.ecoscore_data().unwrap_or_default();
+        let (agribalyse_code, ef_total) = match data.agribalyse.ef_total {
+            Some(ef_total) => (data.agribalyse.code, ef_total),
+            None => {
+                let (code, ef_total) = agribalyse_category(&product.categories_tags)?;
+                (Some(code.to_string()), ef_total)
+            }
+        };
+        let mut origins = data.adjustments.origins_of_ingredients.aggregated_origins;
+        if origins.is_empty() && !product.origins_tags.is_empty() {
+            let percent = 100.0 / product.origins_tags.len() as f64;
+            origins = product
+                .origins_tags
+                .iter()
+                .map(|origin| Origin::new(origin, percent))
+                .collect();
+        }
+        let palm_oil = has_palm_oil(&product.ingredients)
+            || product
+                .ingredients_analysis_tags
+                .iter()
+                .any(|t| t == "en:palm-oil");
+        Some(Self {
+            agribalyse_code,
+            ef_total,
+            labels_tags: product.labels_tags.clone(),
+            origins,
+            packagings: data.adjustments.packaging.packagings,
+            threatening_ingredient: palm_oil.then(|| String::from("en:palm-oil")),
+        })
+    }
+
+    /// Computes the Eco-Score with the given origin country scores.
+    pub fn compute(&self, countries: &CountryScores) -> EcoScore {
+        let adjustments = Adjustments {
+            production_system: self.production_system(),
+            origins_of_ingredients: self.origins_of_ingredients(countries),
+            packaging: self.packaging(),
+            threatened_species: ThreatenedSpecies {
+                ingredient: self.threatening_ingredient.clone(),
+                value: if self.threatening_ingredient.is_some() {
+                    -10
+                } else {
+                    0
+                },
+            },
+        };
+        let agribalyse = Agribalyse {
+            code: self.agribalyse_code.clone(),
+            ef_total: Some(self.ef_total),
+            score: lca_score(self.ef_total),
+        };
+        let score = (agribalyse.score + adjustments.total()).clamp(0, 100);
+        EcoScore {
+            agribalyse,
+            adjustments,
+            score: Some(score),
+            grade: Some(grade(score)),
+        }
+    }
+
+    fn production_system(&self) -> ProductionSystem {
+        let labels: Vec<(&str, i32)> = PRODUCTION_SYSTEM_LABELS
+            .iter()
+            .filter(|(label, _)| self.labels_tags.iter().any(|t| t == label))
+            .copied()
+            .collect();
+        ProductionSystem {
+            labels: labels.iter().map(|(label, _)| label.to_string()).collect(),
+            value: labels.iter().map(|(_, points)| *points).max().unwrap_or(0),
+        }
+    }
+
+    fn origins_of_ingredients(&self, countries: &CountryScores) -> OriginsOfIngredients {
+        let origins = if self.origins.is_empty() {
+            vec![Origin::new(UNKNOWN_ORIGIN, 100.0)]
+        } else {
+            self.origins.clone()
+        };
+        let (epi_score, transportation_score) =
+            origins.iter().fold((0.0, 0.0), |(epi, transportation), o| {
+                let (e, t) = countries.get(&o.origin);
+                (
+                    epi + e * o.percent / 100.0,
+                    transportation + t * o.percent / 100.0,
+                )
+            });
+        let epi_value = (epi_score / 10.0 - 5.0).round() as i32;
+        let transportation_value = (transportation_score / 100.0 * 15.0).round() as i32;
+        OriginsOfIngredients {
+            aggregated_origins: origins,
+            epi_score,
+            transportation_score,
+            epi_value,
+            transportation_value,
+            value: epi_value + transportation_value,
+        }
+    }
+
+    fn packaging(&self) -> Packaging {
+        if self.packagings.is_empty() {
+            return Packaging {
+                value: -15,
+                ..Packaging::default()
+            };
+        }
+        let score = self.packagings.iter().fold(100.0, |score, p| {
+            score - (100.0 - p.ecoscore_material_score) * p.ecoscore_shape_ratio
+        });
+        Packaging {
+            packagings: self.packagings.clone(),
+            score: Some(score),
+            value: ((score / 10.0 - 10.0).round() as i32).max(-15),
+        }
+    }
+}
+
+/// Returns the Agribalyse code and EF single score per kg of the given
+/// categories. The categories are ordered from the most generic to the most
+/// specific, as the OFF `categories_tags`: the last known category wins.
+pub fn agribalyse_category(categories_tags: &[String]) -> Option<(&'static str, f64)> {
+    categories_tags.iter().rev().find_map(|tag| {
+        AGRIBALYSE_CATEGORIES
+            .iter()
+            .find(|(category, _, _)| category == tag)
+            .map(|(_, code, ef_total)| (*code, *ef_total))
+    })
+}
+
+/// Returns the LCA score (0 to 100) of an environmental footprint.
+pub fn lca_score(ef_total: f64) -> i32 {
+    if ef_total <= 0.0 {
+        return 100;
+    }
+    let score =
+        100.0 - 20.0 * (10.0 * ef_total + 1.0).ln() / (2.0 + 1.0 / (100.0 * ef_total.powi(4))).ln();
+    (score.round() as i32).clamp(0, 100)
+}
+
+/// Returns the grade of an Eco-Score: A from 80, B from 60, C from 40, D from
+/// 20, else E.
+pub fn grade(score: i32) -> Grade {
+    match score {
+        s if s >= 80 => Grade::A,
+        s if s >= 60 => Grade::B,
+        s if s >= 40 => Grade::C,
+        s if s >= 20 => Grade::D,
+        _ => Grade::E,
+    }
+}
+
+fn has_palm_oil(ingredients: &[Ingredient]) -> bool {
+    ingredients
+        .iter()
+        .any(|i| i.from_palm_oil.as_deref() == Some("yes") || has_palm_oil(&i.ingredients))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A product of the fixtures, with the `ecoscore_data` computed by OFF and
+    // the country scores of the origins.
+    #[derive(Deserialize)]
+    struct Fixture {
+        name: String,
+        countries: HashMap<String, (f64, f64)>,
+        product: Product,
+    }
+
+    #[test]
+    fn fixtures() {
+        let fixtures: Vec<Fixture> =
+            serde_json::from_str(include_str!("../tests/fixtures/ecoscore.json")).unwrap();
+        for fixture in fixtures {
+            let countries = fixture
+                .countries
+                .iter()
+                .fold(CountryScores::new(), |countries, (country, (e, t))| {
+                    countries.country(country, *e, *t)
+                });
+            let input = EcoScoreInput::from_product(&fixture.product).unwrap();
+            let expected = fixture.product.ecoscore_data().unwrap();
+            assert_eq!(input.compute(&countries), expected, "{}", fixture.name);
+        }
+    }
+
+    #[test]
+    fn agribalyse_categories() {
+        let tags = |tags: &[&str]| -> Vec<String> { tags.iter().map(|t| t.to_string()).collect() };
+        assert_eq!(
+            agribalyse_category(&tags(&["en:groceries", "en:pastas", "en:dry-pastas"])),
+            Some(("9811", 0.8))
+        );
+        assert_eq!(agribalyse_category(&tags(&["en:groceries"])), None);
+
+        // The `ecoscore_data` wins over the categories, which are the
+        // fallback.
+        let product: Product = serde_json::from_value(serde_json::json!({
+            "code": "1",
+            "categories_tags": ["en:spreads", "en:jams"],
+            "ecoscore_data": {"agribalyse": {"code": "31032", "ef_total": 1.0}},
+        }))
+        .unwrap();
+        let input = EcoScoreInput::from_product(&product).unwrap();
+        assert_eq!(input.agribalyse_code.as_deref(), Some("31032"));
+        assert_eq!(input.ef_total, 1.0);
+        let mut product = product;
+        product.extra.remove("ecoscore_data");
+        assert_eq!(
+            EcoScoreInput::from_product(&product).unwrap().ef_total,
+            0.35
+        );
+        let product = Product {
+            categories_tags: tags(&["en:spreads"]),
+            ..product
+        };
+        assert_eq!(EcoScoreInput::from_product(&product), None);
+    }
+
+    #[test]
+    fn lca_scores() {
+        assert_eq!(lca_score(0.0), 100);
+        assert_eq!(lca_score(0.2), 90);
+        assert_eq!(lca_score(0.5), 53);
+        assert_eq!(lca_score(1.0), 31);
+        assert_eq!(lca_score(30.0), 0);
+    }
+
+    #[test]
+    fn grades() {
+        assert_eq!(grade(100), Grade::A);
+        assert_eq!(grade(80), Grade::A);
+        assert_eq!(grade(79), Grade::B);
+        assert_eq!(grade(40), Grade::C);
+        assert_eq!(grade(20), Grade::D);
+        assert_eq!(grade(0), Grade::E);
+    }
+
+    #[test]
+    fn adjustments() {
+        let input = EcoScoreInput {
+            ef_total: 1.0,
+            labels_tags: vec![String::from("en:organic"), String::from("en:demeter")],
+            ..EcoScoreInput::default()
+        };
+        let ecoscore = input.compute(&CountryScores::new());
+        let adjustments = &ecoscore.adjustments;
+        assert_eq!(adjustments.production_system.value, 20);
+        assert_eq!(
+            adjustments.production_system.labels,
+            ["en:demeter", "en:organic"]
+        );
+        // Unknown origins and packaging.
+        assert_eq!(
+            adjustments.origins_of_ingredients.aggregated_origins,
+            [Origin::new(UNKNOWN_ORIGIN, 100.0)]
+        );
+        assert_eq!(adjustments.origins_of_ingredients.value, -5);
+        assert_eq!(adjustments.packaging.value, -15);
+        assert_eq!(adjustments.total(), 0);
+
+        // Capped bonuses.
+        let input = EcoScoreInput {
+            origins: vec![Origin::new("en:france", 100.0)],
+            packagings: vec![PackagingComponent::new("en:cardboard", 100.0, 1.0)],
+            ..input
+        };
+        let countries = CountryScores::new().country("en:france", 100.0, 100.0);
+        assert_eq!(
+            input.compute(&countries).adjustments.total(),
+            MAX_ADJUSTMENTS
+        );
+    }
+}
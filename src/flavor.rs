@@ -0,0 +1,144 @@
+use std::fmt::{self, Display, Formatter};
+
+/// The Product Opener databases sharing the OFF API.
+///
+/// # Variants:
+///
+/// * Food - Open Food Facts, `openfoodfacts.org`.
+/// * Beauty - Open Beauty Facts, `openbeautyfacts.org`.
+/// * PetFood - Open Pet Food Facts, `openpetfoodfacts.org`.
+/// * Products - Open Products Facts, `openproductsfacts.org`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Flavor {
+    #[default]
+    Food,
+    Beauty,
+    PetFood,
+    Products,
+}
+
+// Taxonomies and facets shared by all flavors.
+const COMMON_TAXONOMIES: &[&str] = &[
+    "brands",
+    "categories",
+    "countries",
+    "labels",
+    "languages",
+    "origins",
+    "packaging_materials",
+    "packaging_shapes",
+    "packaging_recycling",
+    "states",
+];
+
+const COMMON_FACETS: &[&str] = &[
+    "brands",
+    "categories",
+    "countries",
+    "entry-dates",
+    "labels",
+    "languages",
+    "packaging",
+    "purchase-places",
+    "states",
+    "stores",
+];
+
+impl Flavor {
+    /// All the flavors, in the order used by
+    /// [crate::OffClient::product_any_flavor].
+    pub const ALL: [Flavor; 4] = [Self::Food, Self::Beauty, Self::PetFood, Self::Products];
+
+    /// Returns the domain of the flavor, i.e. "openbeautyfacts.org", or
+    /// "openbeautyfacts.net" for the staging server.
+    pub fn domain(&self, staging: bool) -> String {
+        let tld = if staging { "net" } else { "org" };
+        format!("{}.{}", self, tld)
+    }
+
+    /// Returns the names of the taxonomies available in this flavor, for use
+    /// with [crate::OffClient::taxonomy].
+    pub fn taxonomies(&self) -> Vec<&'static str> {
+        let specific: &[&str] = match self {
+            Self::Food => &[
+                "additives",
+                "additives_classes",
+                "allergens",
+                "ingredients",
+                "ingredients_analysis",
+                "nova_groups",
+                "nutrient_levels",
+                "food_groups",
+            ],
+            Self::Beauty => &["ingredients", "periods_after_opening"],
+            Self::PetFood => &["additives", "allergens", "ingredients"],
+            Self::Products => &[],
+        };
+        let mut taxonomies = COMMON_TAXONOMIES.to_vec();
+        taxonomies.extend_from_slice(specific);
+        taxonomies
+    }
+
+    /// Returns the names of the facets available in this flavor, for use with
+    /// [crate::OffClient::facet].
+    pub fn facets(&self) -> Vec<&'static str> {
+        let specific: &[&str] = match self {
+            Self::Food => &[
+                "additives",
+                "allergens",
+                "ingredients",
+                "traces",
+                "nutrition-grades",
+                "nova-groups",
+                "ecoscore",
+            ],
+            Self::Beauty => &["ingredients", "periods-after-opening"],
+            Self::PetFood => &["additives", "allergens", "ingredients", "traces"],
+            Self::Products => &[],
+        };
+        let mut facets = COMMON_FACETS.to_vec();
+        facets.extend_from_slice(specific);
+        facets
+    }
+}
+
+impl Display for Flavor {
+    /// Writes the flavor name, which is also its second-level domain, i.e.
+    /// "openbeautyfacts".
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Food => "openfoodfacts",
+            Self::Beauty => "openbeautyfacts",
+            Self::PetFood => "openpetfoodfacts",
+            Self::Products => "openproductsfacts",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn domain() {
+        assert_eq!(Flavor::Food.domain(false), "openfoodfacts.org");
+        assert_eq!(Flavor::Beauty.domain(false), "openbeautyfacts.org");
+        assert_eq!(Flavor::PetFood.domain(false), "openpetfoodfacts.org");
+        assert_eq!(Flavor::Products.domain(true), "openproductsfacts.net");
+    }
+
+    #[test]
+    fn taxonomies_and_facets() {
+        assert!(Flavor::Food.taxonomies().contains(&"nova_groups"));
+        assert!(!Flavor::Beauty.taxonomies().contains(&"nova_groups"));
+        assert!(Flavor::Beauty
+            .taxonomies()
+            .contains(&"periods_after_opening"));
+        assert!(Flavor::Products.taxonomies().contains(&"categories"));
+
+        assert!(Flavor::Food.facets().contains(&"nutrition-grades"));
+        assert!(!Flavor::Products.facets().contains(&"ingredients"));
+        assert!(Flavor::Beauty.facets().contains(&"brands"));
+    }
+}
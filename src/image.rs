@@ -0,0 +1,721 @@
+use crate::flavor::Flavor;
+use crate::product::{de, Image, Product, SelectedImage};
+use crate::types::Params;
+use reqwest::blocking::multipart::{Form, Part};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+
+/// The type of a selected product image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageField {
+    Front,
+    Ingredients,
+    Nutrition,
+    Packaging,
+}
+
+impl ImageField {
+    /// Returns the image id for the given language, i.e. "front_fr".
+    pub fn id(&self, lc: &str) -> String {
+        format!("{}_{}", self, lc)
+    }
+}
+
+impl Display for ImageField {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let field = match self {
+            Self::Front => "front",
+            Self::Ingredients => "ingredients",
+            Self::Nutrition => "nutrition",
+            Self::Packaging => "packaging",
+        };
+        write!(f, "{}", field)
+    }
+}
+
+// The image file to upload.
+#[derive(Debug, Clone)]
+enum ImageSource {
+    Path(PathBuf),
+    Bytes(Vec<u8>),
+}
+
+/// An image upload request.
+///
+/// The uploaded image is added to the product images and, if no image was
+/// selected yet for the given field and language, selected as the front,
+/// ingredients, nutrition or packaging image.
+///
+/// ```
+/// use openfoodfacts::image::{ImageField, ImageUpload};
+///
+/// let upload = ImageUpload::from_bytes("0000000000000", ImageField::Front, "fr", vec![0xFF, 0xD8]);
+/// assert_eq!(upload.imagefield(), "front_fr");
+/// ```
+#[derive(Debug, Clone)]
+pub struct ImageUpload {
+    code: String,
+    field: ImageField,
+    lc: String,
+    source: ImageSource,
+}
+
+impl ImageUpload {
+    /// Creates an upload of the image file at the given path.
+    pub fn from_path(code: &str, field: ImageField, lc: &str, path: impl Into<PathBuf>) -> Self {
+        Self::new(code, field, lc, ImageSource::Path(path.into()))
+    }
+
+    /// Creates an upload of the given image bytes (JPEG or PNG).
+    pub fn from_bytes(code: &str, field: ImageField, lc: &str, bytes: Vec<u8>) -> Self {
+        Self::new(code, field, lc, ImageSource::Bytes(bytes))
+    }
+
+    /// Returns the barcode of the product.
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// Returns the image id, i.e. "front_fr".
+    pub fn imagefield(&self) -> String {
+        self.field.id(&self.lc)
+    }
+
+    /// Returns the multipart form of the upload, producing the parts
+    ///
+    /// ```code
+    /// code=<code>
+    /// imagefield=<field>_<lc>
+    /// imgupload_<field>_<lc>=<image file>
+    /// ```
+    ///
+    /// Fails if the image file cannot be read.
+    pub(crate) fn form(self) -> std::io::Result<Form> {
+        let imagefield = self.imagefield();
+        let part = match self.source {
+            ImageSource::Path(path) => Part::file(path)?,
+            ImageSource::Bytes(bytes) => {
+                Part::bytes(bytes).file_name(format!("{}.jpg", imagefield))
+            }
+        };
+        Ok(Form::new()
+            .text("code", self.code)
+            .text("imagefield", imagefield.clone())
+            .part(format!("imgupload_{}", imagefield), part))
+    }
+
+    fn new(code: &str, field: ImageField, lc: &str, source: ImageSource) -> Self {
+        Self {
+            code: String::from(code),
+            field,
+            lc: String::from(lc),
+            source,
+        }
+    }
+}
+
+/// An image selection request. Selects an uploaded image as the front,
+/// ingredients, nutrition or packaging image of the product, optionally
+/// cropped, rotated and normalized.
+///
+/// ```
+/// use openfoodfacts::image::{ImageCrop, ImageField};
+///
+/// let crop = ImageCrop::new("0000000000000", "3", ImageField::Nutrition, "en")
+///     .crop(10, 20, 410, 620)
+///     .rotate(90)
+///     .normalize(true);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ImageCrop {
+    code: String,
+    imgid: String,
+    field: ImageField,
+    lc: String,
+    crop: Option<(u32, u32, u32, u32)>,
+    angle: Option<u32>,
+    normalize: Option<bool>,
+    white_magic: Option<bool>,
+    coordinates_image_size: Option<String>,
+}
+
+impl ImageCrop {
+    /// Creates a selection of the uploaded image `imgid` for the given field
+    /// and language. Without further options the whole image is selected.
+    pub fn new(code: &str, imgid: &str, field: ImageField, lc: &str) -> Self {
+        Self {
+            code: String::from(code),
+            imgid: String::from(imgid),
+            field,
+            lc: String::from(lc),
+            crop: None,
+            angle: None,
+            normalize: None,
+            white_magic: None,
+            coordinates_image_size: None,
+        }
+    }
+
+    /// Crops the image to the rectangle (x1, y1) - (x2, y2).
+    pub fn crop(mut self, x1: u32, y1: u32, x2: u32, y2: u32) -> Self {
+        self.crop = Some((x1, y1, x2, y2));
+        self
+    }
+
+    /// Rotates the image clockwise. One of 0, 90, 180 or 270 degrees.
+    pub fn rotate(mut self, angle: u32) -> Self {
+        self.angle = Some(angle);
+        self
+    }
+
+    /// Normalizes the colors of the image.
+    pub fn normalize(mut self, normalize: bool) -> Self {
+        self.normalize = Some(normalize);
+        self
+    }
+
+    /// Removes the white background of the image.
+    pub fn white_magic(mut self, white_magic: bool) -> Self {
+        self.white_magic = Some(white_magic);
+        self
+    }
+
+    /// Sets the size of the image the crop coordinates refer to. One of
+    /// "full" or "400". The server default is "400".
+    pub fn coordinates_image_size(mut self, size: &str) -> Self {
+        self.coordinates_image_size = Some(String::from(size));
+        self
+    }
+
+    /// Returns the form parameters of the selection.
+    pub fn params(&self) -> Params<'_> {
+        let mut params: Params = vec![
+            ("code", self.code.clone()),
+            ("imgid", self.imgid.clone()),
+            ("id", self.field.id(&self.lc)),
+        ];
+        if let Some((x1, y1, x2, y2)) = self.crop {
+            params.push(("x1", x1.to_string()));
+            params.push(("y1", y1.to_string()));
+            params.push(("x2", x2.to_string()));
+            params.push(("y2", y2.to_string()));
+        }
+        if let Some(angle) = self.angle {
+            params.push(("angle", angle.to_string()));
+        }
+        if let Some(normalize) = self.normalize {
+            params.push(("normalize", normalize.to_string()));
+        }
+        if let Some(white_magic) = self.white_magic {
+            params.push(("white_magic", white_magic.to_string()));
+        }
+        if let Some(ref size) = self.coordinates_image_size {
+            params.push(("coordinates_image_size", size.clone()));
+        }
+        params
+    }
+}
+
+/// The response of an image upload.
+///
+/// On success, `status` is "status ok" and `image` holds the id of the new
+/// image. If the same image was already uploaded, `status` is
+/// "status not ok", `error` gives the reason and `imgid` holds the id of the
+/// existing image.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImageUploadResponse {
+    pub status: String,
+    pub imagefield: Option<String>,
+    pub image: Option<UploadedImage>,
+    pub error: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub imgid: Option<String>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl ImageUploadResponse {
+    /// Returns true if the image was uploaded.
+    pub fn is_ok(&self) -> bool {
+        self.status == "status ok"
+    }
+
+    /// Returns the id of the uploaded image, or of the existing image if the
+    /// same image was already uploaded.
+    pub fn imgid(&self) -> Option<&str> {
+        self.image
+            .as_ref()
+            .and_then(|i| i.imgid.as_deref())
+            .or(self.imgid.as_deref())
+    }
+}
+
+/// The new image of an [ImageUploadResponse].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UploadedImage {
+    #[serde(deserialize_with = "de::opt_string")]
+    pub imgid: Option<String>,
+    pub thumb_url: Option<String>,
+    pub crop_url: Option<String>,
+}
+
+/// The response of an image selection.
+///
+/// On success, `status` is "status ok", `imagefield` is the id of the
+/// selected image (i.e. "front_fr") and `image` holds its new revision.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImageCropResponse {
+    pub status: String,
+    pub imagefield: Option<String>,
+    pub image: Option<SelectedImageRevision>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl ImageCropResponse {
+    /// Returns true if the image was selected.
+    pub fn is_ok(&self) -> bool {
+        self.status == "status ok"
+    }
+}
+
+/// The selected image of an [ImageCropResponse].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SelectedImageRevision {
+    #[serde(deserialize_with = "de::opt_string")]
+    pub imgid: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub rev: Option<String>,
+    pub display_url: Option<String>,
+}
+
+/// The resolutions of the product images.
+///
+/// # Variants:
+///
+/// * Thumb - 100 pixels.
+/// * Small - 200 pixels.
+/// * Display - 400 pixels.
+/// * Full - The uploaded resolution.
+///
+/// Uploaded images are only available in the Thumb, Display and Full
+/// resolutions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageResolution {
+    Thumb,
+    Small,
+    Display,
+    Full,
+}
+
+impl ImageResolution {
+    /// Returns the size used in the image file names and in [Image::sizes],
+    /// i.e. "400" or "full".
+    pub fn size(&self) -> &'static str {
+        match self {
+            Self::Thumb => "100",
+            Self::Small => "200",
+            Self::Display => "400",
+            Self::Full => "full",
+        }
+    }
+}
+
+/// Returns the folder of the images of the product with the given barcode.
+///
+/// Barcodes of more than 8 digits are padded to 13 digits and split into
+/// `{3}/{3}/{3}/{rest}` folders. Shorter or non numeric codes are used as is.
+///
+/// ```
+/// use openfoodfacts::image::barcode_path;
+///
+/// assert_eq!(barcode_path("3017620422003"), "301/762/042/2003");
+/// assert_eq!(barcode_path("20123451"), "20123451");
+/// ```
+pub fn barcode_path(code: &str) -> String {
+    if code.len() <= 8 || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return String::from(code);
+    }
+    let code = format!("{:0>13}", code);
+    format!(
+        "{}/{}/{}/{}",
+        &code[..3],
+        &code[3..6],
+        &code[6..9],
+        &code[9..]
+    )
+}
+
+/// Builds the URLs of the images of a product, from its barcode and its
+/// `images` and `selected_images` fields.
+///
+/// Selected images (front, ingredients, ...) are looked up in the requested
+/// language first, then in the main language of the product and then in the
+/// other languages, in alphabetical order.
+///
+/// ```
+/// use openfoodfacts::image::{ImageField, ImageResolution, ImageUrls};
+/// use openfoodfacts::Product;
+///
+/// let product: Product = serde_json::from_str(
+///     r#"{"code": "3017620422003", "lc": "fr", "images": {"front_fr": {"imgid": "3", "rev": "4"}}}"#,
+/// )
+/// .unwrap();
+/// let urls = product.image_urls();
+/// assert_eq!(
+///     urls.selected(ImageField::Front, "en", ImageResolution::Display).as_deref(),
+///     Some("https://images.openfoodfacts.org/images/products/301/762/042/2003/front_fr.4.400.jpg")
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct ImageUrls<'a> {
+    // The URL of the product images folder, with a trailing slash.
+    base: String,
+    code: &'a str,
+    lc: Option<&'a str>,
+    images: Option<&'a HashMap<String, Image>>,
+    selected_images: Option<&'a HashMap<String, SelectedImage>>,
+}
+
+impl<'a> ImageUrls<'a> {
+    /// Creates the image URLs of the product with the given barcode, without
+    /// image metadata: only [ImageUrls::raw] URLs are available.
+    pub fn new(code: &'a str) -> Self {
+        Self {
+            base: String::new(),
+            code,
+            lc: None,
+            images: None,
+            selected_images: None,
+        }
+        .flavor(Flavor::Food, false)
+    }
+
+    /// Creates the image URLs of the given product.
+    pub fn from_product(product: &'a Product) -> Self {
+        Self {
+            lc: product.lc.as_deref(),
+            images: Some(&product.images),
+            selected_images: Some(&product.selected_images),
+            ..Self::new(&product.code)
+        }
+    }
+
+    /// Sets the flavor and server of the images. Open Food Facts production
+    /// images by default.
+    pub fn flavor(mut self, flavor: Flavor, staging: bool) -> Self {
+        self.base = format!(
+            "https://images.{}/images/products/{}/",
+            flavor.domain(staging),
+            barcode_path(self.code)
+        );
+        self
+    }
+
+    /// Returns the URL of the uploaded image `imgid`, None for the Small
+    /// resolution.
+    pub fn raw(&self, imgid: &str, resolution: ImageResolution) -> Option<String> {
+        match resolution {
+            ImageResolution::Full => Some(format!("{}{}.jpg", self.base, imgid)),
+            ImageResolution::Small => None,
+            _ => Some(format!("{}{}.{}.jpg", self.base, imgid, resolution.size())),
+        }
+    }
+
+    /// Returns the ids of the uploaded images, in upload order.
+    pub fn raw_ids(&self) -> Vec<&str> {
+        let mut ids: Vec<(u64, &str)> = self
+            .images
+            .into_iter()
+            .flat_map(|images| images.keys())
+            .filter_map(|id| Some((id.parse().ok()?, id.as_str())))
+            .collect();
+        ids.sort_unstable();
+        ids.into_iter().map(|(_, id)| id).collect()
+    }
+
+    /// Returns the URL of the selected image of the given type, in the given
+    /// language or, if there is none, in another language. None if no image
+    /// is selected in any language.
+    pub fn selected(
+        &self,
+        field: ImageField,
+        lc: &str,
+        resolution: ImageResolution,
+    ) -> Option<String> {
+        self.languages(field, lc)
+            .into_iter()
+            .find_map(|lc| self.selected_in(field, lc, resolution))
+    }
+
+    // Returns the URL of the selected image in the given language. Falls back
+    // to the URLs of `selected_images` if `images` lacks the image.
+    fn selected_in(
+        &self,
+        field: ImageField,
+        lc: &str,
+        resolution: ImageResolution,
+    ) -> Option<String> {
+        let id = field.id(lc);
+        if let Some(image) = self.images.and_then(|images| images.get(&id)) {
+            let available = image.sizes.is_empty() || image.sizes.contains_key(resolution.size());
+            if let (Some(rev), true) = (&image.rev, available) {
+                return Some(format!(
+                    "{}{}.{}.{}.jpg",
+                    self.base,
+                    id,
+                    rev,
+                    resolution.size()
+                ));
+            }
+        }
+        let selected = self.selected_images?.get(&field.to_string())?;
+        let urls = match resolution {
+            ImageResolution::Thumb => &selected.thumb,
+            ImageResolution::Small => &selected.small,
+            ImageResolution::Display => &selected.display,
+            ImageResolution::Full => return None,
+        };
+        urls.get(lc).cloned()
+    }
+
+    // The languages to look the selected image up in: the requested
+    // language, the main language of the product and the other languages
+    // with a selected image.
+    fn languages<'b>(&'b self, field: ImageField, lc: &'b str) -> Vec<&'b str> {
+        let prefix = format!("{}_", field);
+        let mut others: Vec<&str> = self
+            .images
+            .into_iter()
+            .flat_map(|images| images.keys())
+            .filter_map(|id| id.strip_prefix(&prefix))
+            .chain(
+                self.selected_images
+                    .and_then(|selected| selected.get(&field.to_string()))
+                    .into_iter()
+                    .flat_map(|selected| selected.display.keys().map(String::as_str)),
+            )
+            .collect();
+        others.sort_unstable();
+        let mut languages = Vec::new();
+        for lc in std::iter::once(lc).chain(self.lc).chain(others) {
+            if !languages.contains(&lc) {
+                languages.push(lc);
+            }
+        }
+        languages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn product() -> Product {
+        serde_json::from_str(
+            r#"{
+                "code": "3017620422003",
+                "lc": "fr",
+                "images": {
+                    "1": {"uploaded_t": 1},
+                    "12": {"uploaded_t": 2},
+                    "2": {"uploaded_t": 3},
+                    "front_fr": {"imgid": "2", "rev": "4", "sizes": {"100": {}, "400": {}, "full": {}}},
+                    "front_de": {"imgid": "1", "rev": "7"},
+                    "nutrition_it": {"imgid": "12", "rev": "9"}
+                },
+                "selected_images": {
+                    "ingredients": {"display": {"en": "https://images/ingredients_en.5.400.jpg"}}
+                }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn barcode_paths() {
+        assert_eq!(barcode_path("3017620422003"), "301/762/042/2003");
+        assert_eq!(barcode_path("10012345600019"), "100/123/456/00019");
+        // Padded to 13 digits.
+        assert_eq!(barcode_path("036000291452"), "003/600/029/1452");
+        assert_eq!(barcode_path("123456789"), "000/012/345/6789");
+        // Short and non numeric codes.
+        assert_eq!(barcode_path("20123451"), "20123451");
+        assert_eq!(barcode_path("abc123456789"), "abc123456789");
+    }
+
+    #[test]
+    fn raw_urls() {
+        let urls = ImageUrls::new("20123451");
+        let base = "https://images.openfoodfacts.org/images/products/20123451/";
+        assert_eq!(
+            urls.raw("3", ImageResolution::Full),
+            Some(format!("{}3.jpg", base))
+        );
+        assert_eq!(
+            urls.raw("3", ImageResolution::Thumb),
+            Some(format!("{}3.100.jpg", base))
+        );
+        assert_eq!(urls.raw("3", ImageResolution::Small), None);
+        assert!(urls.raw_ids().is_empty());
+
+        let product = product();
+        assert_eq!(product.image_urls().raw_ids(), ["1", "2", "12"]);
+        let beauty = product.image_urls().flavor(Flavor::Beauty, true);
+        assert_eq!(
+            beauty.raw("1", ImageResolution::Display).unwrap(),
+            "https://images.openbeautyfacts.net/images/products/301/762/042/2003/1.400.jpg"
+        );
+    }
+
+    #[test]
+    fn selected_urls() {
+        let product = product();
+        let urls = product.image_urls();
+        let base = "https://images.openfoodfacts.org/images/products/301/762/042/2003/";
+        let selected = |field, lc, resolution| urls.selected(field, lc, resolution);
+
+        assert_eq!(
+            selected(ImageField::Front, "de", ImageResolution::Small),
+            Some(format!("{}front_de.7.200.jpg", base))
+        );
+        // Falls back to the main language.
+        assert_eq!(
+            selected(ImageField::Front, "en", ImageResolution::Display),
+            Some(format!("{}front_fr.4.400.jpg", base))
+        );
+        // Missing size, then falls back to the other languages.
+        assert_eq!(
+            selected(ImageField::Front, "fr", ImageResolution::Small),
+            Some(format!("{}front_de.7.200.jpg", base))
+        );
+        assert_eq!(
+            selected(ImageField::Nutrition, "fr", ImageResolution::Full),
+            Some(format!("{}nutrition_it.9.full.jpg", base))
+        );
+        // From selected_images.
+        assert_eq!(
+            selected(ImageField::Ingredients, "fr", ImageResolution::Display).as_deref(),
+            Some("https://images/ingredients_en.5.400.jpg")
+        );
+        assert_eq!(
+            selected(ImageField::Ingredients, "fr", ImageResolution::Thumb),
+            None
+        );
+        assert_eq!(
+            selected(ImageField::Packaging, "fr", ImageResolution::Display),
+            None
+        );
+    }
+
+    #[test]
+    fn imagefield() {
+        assert_eq!(ImageField::Front.id("fr"), "front_fr");
+        assert_eq!(ImageField::Ingredients.id("en"), "ingredients_en");
+        assert_eq!(ImageField::Nutrition.id("de"), "nutrition_de");
+        assert_eq!(ImageField::Packaging.id("it"), "packaging_it");
+    }
+
+    #[test]
+    fn upload_form() {
+        let upload = ImageUpload::from_bytes(
+            "0000000000000",
+            ImageField::Front,
+            "fr",
+            b"JPEG DATA".to_vec(),
+        );
+        let mut body = String::new();
+        upload
+            .form()
+            .unwrap()
+            .into_reader()
+            .read_to_string(&mut body)
+            .unwrap();
+        assert!(body.contains("name=\"code\"\r\n\r\n0000000000000"));
+        assert!(body.contains("name=\"imagefield\"\r\n\r\nfront_fr"));
+        assert!(body.contains("name=\"imgupload_front_fr\"; filename=\"front_fr.jpg\""));
+        assert!(body.contains("JPEG DATA"));
+    }
+
+    #[test]
+    fn upload_missing_file() {
+        let upload = ImageUpload::from_path(
+            "0000000000000",
+            ImageField::Front,
+            "fr",
+            "/does/not/exist.jpg",
+        );
+        assert!(upload.form().is_err());
+    }
+
+    #[test]
+    fn crop_params() {
+        let crop = ImageCrop::new("0000000000000", "3", ImageField::Nutrition, "en");
+        assert_eq!(
+            &crop.params(),
+            &[
+                ("code", String::from("0000000000000")),
+                ("imgid", String::from("3")),
+                ("id", String::from("nutrition_en")),
+            ]
+        );
+
+        let crop = crop
+            .crop(10, 20, 410, 620)
+            .rotate(90)
+            .normalize(true)
+            .white_magic(false)
+            .coordinates_image_size("full");
+        assert_eq!(
+            &crop.params()[3..],
+            &[
+                ("x1", String::from("10")),
+                ("y1", String::from("20")),
+                ("x2", String::from("410")),
+                ("y2", String::from("620")),
+                ("angle", String::from("90")),
+                ("normalize", String::from("true")),
+                ("white_magic", String::from("false")),
+                ("coordinates_image_size", String::from("full")),
+            ]
+        );
+    }
+
+    #[test]
+    fn upload_response() {
+        let ok: ImageUploadResponse = serde_json::from_str(
+            r#"{"status": "status ok", "imagefield": "front_fr",
+                "image": {"imgid": 3, "thumb_url": "3.100.jpg", "crop_url": "3.400.jpg"}}"#,
+        )
+        .unwrap();
+        assert!(ok.is_ok());
+        assert_eq!(ok.imgid(), Some("3"));
+
+        let duplicate: ImageUploadResponse = serde_json::from_str(
+            r#"{"status": "status not ok", "imgid": 2,
+                "error": "This picture has already been sent."}"#,
+        )
+        .unwrap();
+        assert!(!duplicate.is_ok());
+        assert_eq!(duplicate.imgid(), Some("2"));
+    }
+
+    #[test]
+    fn crop_response() {
+        let ok: ImageCropResponse = serde_json::from_str(
+            r#"{"status": "status ok", "imagefield": "front_fr",
+                "image": {"imgid": "3", "rev": 12, "display_url": "front_fr.12.400.jpg"}}"#,
+        )
+        .unwrap();
+        assert!(ok.is_ok());
+        assert_eq!(ok.image.unwrap().rev.as_deref(), Some("12"));
+    }
+}
@@ -12,7 +12,14 @@
 //! set, the builder produces a client with
 //!
 //! * locale: "world"
+//! * flavor: [Flavor::Food]
+//! * server: `https://{locale}.openfoodfacts.org/`
 //! * auth: None (only needed for write operations)
+//! * session: disabled
+//! * rate limits: the OFF quotas, see [RateLimit::off_quota]
+//! * retries: [RetryPolicy::default], for GET requests only
+//! * cache: None
+//! * error responses: returned as responses, see [OffBuilder::error_for_status]
 //! * user agent: "OffRustClient - {OS name} - Version {lib version} - {github repo URL}"
 //!
 //! ```
@@ -24,6 +31,9 @@
 //! # }
 //! ```
 //!
+//! With the `async` feature enabled, [OffBuilder::build_async] builds an
+//! `AsyncOffClient` instead, with the same methods as the blocking client.
+//!
 //! # Processing client responses
 //!
 //! Contrary to other client implementations, the rust client returns the HTTP response
@@ -49,6 +59,23 @@
 //! # }
 //! ```
 //!
+//! Products can also be deserialized into the typed [Product] model with
+//! `product_typed()`. Fields not covered by the model are kept in
+//! [Product::extra].
+//!
+//! ```
+//! use openfoodfacts as off;
+//!
+//! # fn main() -> Result<(), off::Error> {
+//! let client = off::v2().build().unwrap();
+//! let response = client.product_typed("3017620422003", None)?;
+//! if let Some(product) = response.product {
+//!     println!("{:?} {:?}", product.product_name_in("fr"), product.nutriscore_grade);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
 //! # Searching
 //!
 //! The search API is version specific. Different methods are implemented per
@@ -70,20 +97,97 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! All the products of a search or of `products_by` can be walked with
+//! `search_iter()` and `products_by_iter()`, which fetch the pages on demand
+//! (see [Paginator]). The async client returns a stream instead.
+//!
+//! # Writing
+//!
+//! Products are created and edited with a [write::ProductEdit]. Images are
+//! uploaded with an [image::ImageUpload] and selected with an
+//! [image::ImageCrop]. All write requests are sent with the credentials given
+//! to [OffBuilder::auth]. In session mode ([OffBuilder::session]), the client logs in once and
+//! authenticates with the session cookie instead.
+//!
+//! ```no_run
+//! use openfoodfacts as off;
+//!
+//! # fn main() -> Result<(), off::Error> {
+//! let client = off::v2().auth("username", "password").build()?;
+//! let edit = client
+//!     .product_edit("0000000000000")
+//!     .product_name("Chocolate spread")
+//!     .quantity("400 g");
+//! let response = client.save_product(edit)?;
+//! assert!(response.is_ok());
+//! # Ok(())
+//! # }
+//! ```
 #![allow(dead_code)]
+#[cfg(feature = "async")]
+pub use crate::async_client::{AsyncHttpClient, AsyncHttpResponse, AsyncOffClient, AsyncResult};
+pub use crate::barcode::{Barcode, BarcodeError, BarcodeKind, Restriction};
+pub use crate::cache::{Cache, CacheEndpoint, CacheStore, CachedResponse, DiskCache, MemoryCache};
 pub use crate::client::{Error, HttpClient, HttpResponse, OffClient, Result};
+pub use crate::flavor::Flavor;
 pub use crate::locale::Locale;
 pub use crate::output::Output;
+#[cfg(feature = "async")]
+pub use crate::pages::AsyncPaginator;
+pub use crate::pages::Paginator;
+pub use crate::product::{Product, ProductResponse, SearchResponse};
+pub use crate::rate_limit::{EndpointClass, RateLimit};
+pub use crate::retry::{RetryEvent, RetryPolicy};
+pub use crate::transport::{FakeResponse, FakeTransport, RecordedRequest, Transport};
 pub use crate::types::{V0, V2};
+pub use crate::vcr::{
+    Body, Cassette, CassetteRequest, CassetteResponse, Interaction, MatchOn, Player, Recorder,
+    Redact,
+};
 
+use crate::rate_limit::RateLimiter;
+use crate::retry::{RequestPolicy, RetryHook};
+use crate::server::Server;
+use crate::session::Session;
 use crate::types::Version;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use std::env::consts::OS;
 
+#[cfg(feature = "async")]
+mod async_client;
+mod barcode;
+mod cache;
 mod client;
+#[cfg(feature = "dump")]
+pub mod csv_dump;
+#[cfg(feature = "dump")]
+pub mod delta;
+#[cfg(feature = "dump")]
+pub mod dump;
+pub mod ecoscore;
+mod flavor;
+pub mod image;
 mod locale;
+pub mod nova;
+pub mod nutriscore;
+#[cfg(feature = "offline")]
+pub mod offline;
 mod output;
+mod pages;
+pub mod product;
+mod rate_limit;
+mod response;
+mod retry;
 pub mod search;
+mod server;
+mod session;
+pub mod taxonomy;
+mod transport;
 mod types;
+mod vcr;
+pub mod write;
 
 /// The version of this library.
 pub const VERSION: &str = "alpha";
@@ -118,7 +222,7 @@ pub fn v2() -> OffBuilder<V2> {
 
 /// Authentication tuple (username, password).
 #[derive(Debug, PartialEq)]
-struct Auth(String, String);
+pub(crate) struct Auth(String, String);
 
 /// The Open Food Facts API client builder.
 #[derive(Debug)]
@@ -127,8 +231,23 @@ pub struct OffBuilder<V> {
     v: V,
     // The default locale.
     locale: Locale,
+    // The server to send the requests to.
+    server: Server,
     // Optional. Only needed for write operations.
     auth: Option<Auth>,
+    // If true, write requests authenticate with a session cookie.
+    session: bool,
+    // The rate limits of the product, search and facet endpoints. None
+    // disables the limit.
+    rate_limits: [Option<RateLimit>; 3],
+    // The retry policy of GET requests.
+    retry: RetryPolicy,
+    // Called before each retry. Optional.
+    retry_hook: Option<RetryHook>,
+    // The cache of GET responses. Optional.
+    cache: Option<Cache>,
+    // Whether error responses are turned into errors.
+    error_for_status: bool,
     // The User-Agent header value to send on each request. Optional.
     // If not given, use the default user agent.
     user_agent: Option<String>,
@@ -144,12 +263,189 @@ where
         self
     }
 
-    /// Sets the authentication credentials.
+    /// Sets the URL scheme. Defaults to "https".
+    pub fn scheme(mut self, scheme: &str) -> Self {
+        self.server.scheme = scheme.to_string();
+        self
+    }
+
+    /// Sets the flavor, i.e. the Product Opener database to query. Defaults
+    /// to [Flavor::Food].
+    ///
+    /// ```
+    /// use openfoodfacts::{self as off, Flavor};
+    ///
+    /// # fn main() -> Result<(), reqwest::Error> {
+    /// // https://world.openbeautyfacts.org/
+    /// let client = off::v2().flavor(Flavor::Beauty).build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn flavor(mut self, flavor: Flavor) -> Self {
+        self.server.flavor = flavor;
+        self
+    }
+
+    /// Sets a custom server domain. Defaults to the flavor domain, i.e.
+    /// "openfoodfacts.org".
+    pub fn domain(mut self, domain: &str) -> Self {
+        self.server.domain = Some(domain.to_string());
+        self
+    }
+
+    /// Sets the server port. Defaults to the scheme default port.
+    pub fn port(mut self, port: u16) -> Self {
+        self.server.port = Some(port);
+        self
+    }
+
+    /// Enables the single host mode. Instead of selecting the locale with the
+    /// subdomain (`https://fr.openfoodfacts.org/`), all requests are sent to
+    /// the domain itself and the locale is given with the `cc` and `lc` query
+    /// parameters. Use it with servers that have no locale subdomains, such as
+    /// a local mock server.
+    ///
+    /// ```
+    /// use openfoodfacts as off;
+    ///
+    /// # fn main() -> Result<(), reqwest::Error> {
+    /// let client = off::v2()
+    ///     .scheme("http")
+    ///     .domain("localhost")
+    ///     .port(8080)
+    ///     .single_host(true)
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn single_host(mut self, single_host: bool) -> Self {
+        self.server.single_host = single_host;
+        self
+    }
+
+    /// Sends the requests to the staging server of the flavor, i.e.
+    /// `openfoodfacts.net`. The staging server requires the HTTP Basic auth
+    /// credentials "off"/"off", which are sent with each request. They are
+    /// not the account credentials: set those with [OffBuilder::auth].
+    pub fn staging(mut self) -> Self {
+        self.server.staging = true;
+        self
+    }
+
+    /// Sets the account credentials. They are sent as the `user_id` and
+    /// `password` fields of the write requests, or to log in in session mode.
     pub fn auth(mut self, username: &str, password: &str) -> Self {
         self.auth = Some(Auth(username.to_string(), password.to_string()));
         self
     }
 
+    /// Enables the cookie session mode. Instead of sending the credentials
+    /// with each write request, the client logs in through `cgi/session.pl`
+    /// and keeps the session cookie. See [OffClient::login].
+    pub fn session(mut self, session: bool) -> Self {
+        self.session = session;
+        self
+    }
+
+    /// Sets the rate limit of the given endpoint class. None disables the
+    /// limit. Defaults to the OFF quota ([RateLimit::off_quota]).
+    ///
+    /// The OFF servers block the clients that go over the quotas. The limits
+    /// are applied client-side with a token bucket per endpoint class, shared
+    /// by all the threads using the client: requests over the limit wait for
+    /// their turn.
+    ///
+    /// ```
+    /// use openfoodfacts::{self as off, EndpointClass, RateLimit};
+    ///
+    /// # fn main() -> Result<(), reqwest::Error> {
+    /// let client = off::v2()
+    ///     .rate_limit(EndpointClass::Search, Some(RateLimit::per_minute(5)))
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn rate_limit(mut self, class: EndpointClass, limit: Option<RateLimit>) -> Self {
+        self.rate_limits[class.index()] = limit;
+        self
+    }
+
+    /// Disables the rate limits of all the endpoint classes, i.e. for a local
+    /// server.
+    pub fn no_rate_limit(mut self) -> Self {
+        self.rate_limits = [None; 3];
+        self
+    }
+
+    /// Sets the retry policy of GET requests. Defaults to
+    /// [RetryPolicy::default]. Use [RetryPolicy::none] to disable the
+    /// retries.
+    ///
+    /// ```
+    /// use openfoodfacts::{self as off, RetryPolicy};
+    /// use std::time::Duration;
+    ///
+    /// # fn main() -> Result<(), reqwest::Error> {
+    /// let client = off::v2()
+    ///     .retry(RetryPolicy::new(5).max_delay(Duration::from_secs(10)))
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Sets a hook called before each retry, i.e. to log the retries.
+    ///
+    /// ```
+    /// use openfoodfacts as off;
+    ///
+    /// # fn main() -> Result<(), reqwest::Error> {
+    /// let client = off::v2()
+    ///     .on_retry(|event| {
+    ///         eprintln!(
+    ///             "{} failed (attempt {}), retrying in {:?}",
+    ///             event.url, event.attempt, event.delay
+    ///         )
+    ///     })
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn on_retry<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&RetryEvent) + Send + Sync + 'static,
+    {
+        self.retry_hook = Some(RetryHook(std::sync::Arc::new(hook)));
+        self
+    }
+
+    /// Sets the cache of GET responses. See [Cache].
+    pub fn cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Turns the error responses into errors: [Error::NotFound],
+    /// [Error::RateLimited], [Error::Unauthorized] or [Error::ServerError].
+    /// Typed methods also fail with [Error::ApiStatus] if the OFF API reports
+    /// a failure, e.g. a product not found. Disabled by default: error
+    /// responses are returned as is.
+    ///
+    /// ```
+    /// use openfoodfacts::{self as off, FakeResponse, FakeTransport};
+    ///
+    /// let fake = FakeTransport::new().route("*", FakeResponse::status(404));
+    /// let client = off::v2().error_for_status(true).build_with(fake);
+    /// assert!(matches!(client.taxonomy("unknown"), Err(off::Error::NotFound(_))));
+    /// ```
+    pub fn error_for_status(mut self, value: bool) -> Self {
+        self.error_for_status = value;
+        self
+    }
+
     /// Sets the user agent string.
     pub fn user_agent(mut self, user_agent: &str) -> Self {
         self.user_agent = Some(user_agent.to_string());
@@ -159,22 +455,101 @@ where
     /// Creates a new OffClient for the `V` version of the API, with the current
     /// builder options. Consumes the builder.
     pub fn build(self) -> std::result::Result<OffClient<V>, reqwest::Error> {
-        let client = self.build_http_client()?;
-        Ok(OffClient::new(self.v, self.locale, client))
+        let session = if self.session {
+            Some(Session::default())
+        } else {
+            None
+        };
+        let client = self.build_http_client(session.as_ref())?;
+        let policy = self.request_policy();
+        Ok(OffClient::new(
+            self.v,
+            self.locale,
+            self.server,
+            self.auth,
+            session,
+            policy,
+            client,
+        ))
+    }
+
+    /// Creates a new OffClient sending its requests through the given
+    /// transport instead of a reqwest client. Consumes the builder.
+    ///
+    /// The user agent, the staging HTTP Basic auth header and the session cookies are
+    /// handled by the reqwest client: they are left to the transport, and the
+    /// session mode is ignored. The rate limits are ignored for local
+    /// transports (see [Transport::is_local]).
+    ///
+    /// ```
+    /// use openfoodfacts::{self as off, FakeResponse, FakeTransport};
+    ///
+    /// let fake = FakeTransport::new().route("*/brands.json", FakeResponse::json("{}"));
+    /// let client = off::v2().build_with(fake);
+    /// ```
+    pub fn build_with<T: Transport>(self, transport: T) -> OffClient<V, T> {
+        let mut policy = self.request_policy();
+        if transport.is_local() {
+            policy.limiter = RateLimiter::new([None; 3]);
+        }
+        OffClient::new(
+            self.v,
+            self.locale,
+            self.server,
+            self.auth,
+            None,
+            policy,
+            transport,
+        )
+    }
+
+    /// Returns the reqwest client [OffBuilder::build] would send the requests
+    /// with, without the session cookies, e.g. to wrap it in a
+    /// [crate::Recorder] given to [OffBuilder::build_with].
+    pub fn http_client(&self) -> std::result::Result<HttpClient, reqwest::Error> {
+        self.build_http_client(None)
+    }
+
+    /// Creates a new [AsyncOffClient] for the `V` version of the API, with the
+    /// current builder options. Consumes the builder. Requires the `async`
+    /// feature.
+    #[cfg(feature = "async")]
+    pub fn build_async(self) -> std::result::Result<AsyncOffClient<V>, reqwest::Error> {
+        let client = self.build_async_http_client()?;
+        let policy = self.request_policy();
+        Ok(AsyncOffClient::new(
+            self.v,
+            self.locale,
+            self.server,
+            policy,
+            client,
+        ))
     }
 
     // Creates a new builder for the given API version with the following
     // defaults:
     //
     // * The default locale is set to `Locale::default()`.
+    // * The server is `https://{locale}.openfoodfacts.org/`.
     // * No authentication credentials
+    // * No cookie session
+    // * The OFF rate limits
+    // * The default retry policy, without hook
+    // * No cache
     // * The user agent is set to
     //   `OffRustClient - {OS name} - Version {lib version} - {github repo URL}`
     fn new(v: V) -> Self {
         Self {
             v,
             locale: Locale::default(),
+            server: Server::default(),
             auth: None,
+            session: false,
+            rate_limits: RateLimiter::default().limits(),
+            retry: RetryPolicy::default(),
+            retry_hook: None,
+            cache: None,
+            error_for_status: false,
             // TODO: Get version and URL from somewhere else ?
             user_agent: Some(format!(
                 "OffRustClient - {} - Version {} - {}",
@@ -183,21 +558,25 @@ where
         }
     }
 
-    fn build_http_client(&self) -> reqwest::Result<HttpClient> {
-        // Default headers
-        let mut headers = reqwest::header::HeaderMap::new();
-        if let Some(ref auth) = self.auth {
-            // TODO: Needs to be encoded !
-            let basic_auth = format!("Basic {}:{}", auth.0, auth.1);
-            headers.insert(
-                reqwest::header::AUTHORIZATION,
-                reqwest::header::HeaderValue::from_str(&basic_auth).unwrap(),
-            );
+    fn request_policy(&self) -> RequestPolicy {
+        RequestPolicy {
+            limiter: RateLimiter::new(self.rate_limits),
+            retry: self.retry,
+            hook: self.retry_hook.clone(),
+            cache: self.cache.clone(),
+            error_for_status: self.error_for_status,
         }
+    }
+
+    fn build_http_client(&self, session: Option<&Session>) -> reqwest::Result<HttpClient> {
         let mut cb = HttpClient::builder();
+        let headers = self.default_headers();
         if !headers.is_empty() {
             cb = cb.default_headers(headers);
         }
+        if let Some(session) = session {
+            cb = cb.cookie_provider(session.jar());
+        }
         if let Some(ref user_agent) = self.user_agent {
             cb = cb.user_agent(user_agent);
         }
@@ -205,6 +584,33 @@ where
         // TODO: Timeouts
         cb.build()
     }
+
+    #[cfg(feature = "async")]
+    fn build_async_http_client(&self) -> reqwest::Result<AsyncHttpClient> {
+        let mut cb = AsyncHttpClient::builder();
+        let headers = self.default_headers();
+        if !headers.is_empty() {
+            cb = cb.default_headers(headers);
+        }
+        if let Some(ref user_agent) = self.user_agent {
+            cb = cb.user_agent(user_agent);
+        }
+        cb.build()
+    }
+
+    // The headers sent on each request.
+    fn default_headers(&self) -> reqwest::header::HeaderMap {
+        let mut headers = reqwest::header::HeaderMap::new();
+        // The staging server gate, not the account credentials.
+        if self.server.staging {
+            let credentials = BASE64.encode("off:off");
+            let mut value =
+                reqwest::header::HeaderValue::from_str(&format!("Basic {}", credentials)).unwrap();
+            value.set_sensitive(true);
+            headers.insert(reqwest::header::AUTHORIZATION, value);
+        }
+        headers
+    }
 }
 
 #[cfg(test)]
@@ -215,7 +621,14 @@ mod tests {
     fn default() {
         let builder = v0();
         assert_eq!(builder.locale, Locale::default());
+        assert_eq!(builder.server, Server::default());
         assert_eq!(builder.auth, None);
+        assert!(!builder.session);
+        assert_eq!(builder.rate_limits, RateLimiter::default().limits());
+        assert_eq!(builder.retry, RetryPolicy::default());
+        assert!(builder.retry_hook.is_none());
+        assert!(!builder.error_for_status);
+        assert!(builder.default_headers().is_empty());
         assert_eq!(
             builder.user_agent,
             Some(format!(
@@ -229,13 +642,109 @@ mod tests {
     fn options() {
         let builder = v0()
             .locale(Locale::new("gr", None))
+            .scheme("http")
+            .domain("localhost")
+            .port(8080)
+            .single_host(true)
             .auth("user", "pwd")
+            .session(true)
             .user_agent("user agent");
         assert_eq!(builder.locale, Locale::new("gr", None));
+        assert_eq!(
+            builder.server,
+            Server {
+                scheme: String::from("http"),
+                domain: Some(String::from("localhost")),
+                port: Some(8080),
+                single_host: true,
+                ..Server::default()
+            }
+        );
         assert_eq!(
             builder.auth,
             Some(Auth(String::from("user"), String::from("pwd")))
         );
+        assert!(builder.session);
         assert_eq!(builder.user_agent, Some(String::from("user agent")));
     }
+
+    #[test]
+    fn staging() {
+        let builder = v0().flavor(Flavor::PetFood).staging();
+        assert_eq!(builder.server.domain(), "openpetfoodfacts.net");
+    }
+
+    #[test]
+    fn rate_limits() {
+        let builder = v0().rate_limit(EndpointClass::Search, Some(RateLimit::per_minute(5)));
+        assert_eq!(
+            builder.rate_limits,
+            [
+                Some(RateLimit::per_minute(100)),
+                Some(RateLimit::per_minute(5)),
+                Some(RateLimit::per_minute(10)),
+            ]
+        );
+        let builder = builder.rate_limit(EndpointClass::Product, None);
+        assert_eq!(builder.rate_limits[0], None);
+        assert_eq!(builder.no_rate_limit().rate_limits, [None; 3]);
+    }
+
+    #[test]
+    fn retry() {
+        let builder = v0().retry(RetryPolicy::none()).on_retry(|_| {});
+        assert_eq!(builder.retry.max_attempts, 1);
+        assert!(builder.retry_hook.is_some());
+    }
+
+    #[test]
+    fn basic_auth() {
+        // The account credentials are not sent as HTTP Basic auth.
+        let builder = v0().auth("user", "pwd");
+        assert!(builder.default_headers().is_empty());
+
+        let builder = builder.staging();
+        let headers = builder.default_headers();
+        let value = headers.get(reqwest::header::AUTHORIZATION).unwrap();
+        assert_eq!(value, "Basic b2ZmOm9mZg==");
+        assert!(value.is_sensitive());
+    }
+
+    #[test]
+    fn staging_request() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+
+        // A server answering once and returning the request headers.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            for line in BufReader::new(&stream).lines() {
+                let line = line.unwrap();
+                if line.is_empty() {
+                    break;
+                }
+                request.push(line.to_lowercase());
+            }
+            let response = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n{}";
+            stream.write_all(response.as_bytes()).unwrap();
+            request
+        });
+
+        // The custom domain overrides the staging domain, not the gate.
+        let client = v2()
+            .staging()
+            .auth("user", "pwd")
+            .scheme("http")
+            .domain("127.0.0.1")
+            .port(port)
+            .single_host(true)
+            .build()
+            .unwrap();
+        client.taxonomy("nova_groups").unwrap();
+        let request = server.join().unwrap();
+        assert!(request.contains(&String::from("authorization: basic b2zmom9mzg==")));
+    }
 }
@@ -37,7 +37,7 @@ use std::fmt::{Display, Formatter};
 ///
 /// Locales can be converted into a String "{cc}" or "{cc}-{lc}" with
 /// [Locale::to_string()].
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Locale {
     pub cc: String,
     pub lc: Option<String>,
@@ -69,8 +69,8 @@ impl Default for Locale {
 
 impl Display for Locale {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        if self.lc.is_some() {
-            write!(f, "{}-{}", self.cc, self.lc.as_ref().unwrap())
+        if let Some(lc) = &self.lc {
+            write!(f, "{}-{}", self.cc, lc)
         } else {
             write!(f, "{}", self.cc)
         }
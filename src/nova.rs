@@ -0,0 +1,430 @@
+//! Local NOVA group classification.
+//!
+//! The NOVA group of a product is the highest group of the markers found in
+//! its categories, ingredients and additives, as in the `nova_groups_markers`
+//! of the OFF products. Products without markers are unprocessed (group 1).
+//!
+//! ```
+//! use openfoodfacts::nova::{MarkerSource, NovaClassifier, NovaGroup};
+//!
+//! let classifier = NovaClassifier::new();
+//! let nova = classifier.classify_tags(&[], &["en:sugar", "en:cocoa", "en:emulsifier"], &["en:e322"]);
+//! assert_eq!(nova.group, NovaGroup::UltraProcessed);
+//! let markers: Vec<_> = nova.markers_of(NovaGroup::UltraProcessed).collect();
+//! assert_eq!(markers[0].source, MarkerSource::Ingredients);
+//! assert_eq!(markers[0].tag, "en:emulsifier");
+//! ```
+use crate::product::{Ingredient, Product};
+use serde_json::Value;
+
+/// The NOVA groups.
+///
+/// # Variants:
+///
+/// * Unprocessed - Unprocessed or minimally processed foods.
+/// * CulinaryIngredients - Processed culinary ingredients (salt, sugar, oils,
+///   butter, ...).
+/// * Processed - Processed foods, made with culinary ingredients.
+/// * UltraProcessed - Ultra-processed foods, made with ingredients and
+///   additives of industrial use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NovaGroup {
+    Unprocessed,
+    CulinaryIngredients,
+    Processed,
+    UltraProcessed,
+}
+
+impl NovaGroup {
+    /// Returns the number of the group, 1 to 4.
+    pub fn number(&self) -> u8 {
+        match self {
+            Self::Unprocessed => 1,
+            Self::CulinaryIngredients => 2,
+            Self::Processed => 3,
+            Self::UltraProcessed => 4,
+        }
+    }
+}
+
+/// Where a marker was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MarkerSource {
+    Categories,
+    Ingredients,
+    Additives,
+}
+
+impl MarkerSource {
+    /// Returns the name of the source in `nova_groups_markers`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Categories => "categories",
+            Self::Ingredients => "ingredients",
+            Self::Additives => "additives",
+        }
+    }
+}
+
+/// A marker found in a product.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NovaMarker {
+    pub group: NovaGroup,
+    pub source: MarkerSource,
+    /// The category, ingredient or additive tag, i.e. "en:e322".
+    pub tag: String,
+}
+
+/// The result of a classification: the group and all the markers found,
+/// sorted by group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NovaClassification {
+    pub group: NovaGroup,
+    pub markers: Vec<NovaMarker>,
+}
+
+impl NovaClassification {
+    /// Returns the markers of the given group, i.e. the reasons of a group 4.
+    pub fn markers_of(&self, group: NovaGroup) -> impl Iterator<Item = &NovaMarker> {
+        self.markers.iter().filter(move |m| m.group == group)
+    }
+}
+
+const CULINARY_INGREDIENTS_CATEGORIES: &[&str] = &[
+    "en:salts",
+    "en:sugars",
+    "en:honeys",
+    "en:maple-syrups",
+    "en:vegetable-oils",
+    "en:fats",
+    "en:butters",
+    "en:vinegars",
+    "en:starches",
+];
+
+const PROCESSED_CATEGORIES: &[&str] = &["en:cheeses", "en:wines", "en:beers", "en:ciders"];
+
+const PROCESSED_INGREDIENTS: &[&str] = &[
+    "en:salt",
+    "en:sugar",
+    "en:honey",
+    "en:maple-syrup",
+    "en:vegetable-oil",
+    "en:butter",
+    "en:vinegar",
+];
+
+const ULTRA_PROCESSED_CATEGORIES: &[&str] = &["en:sodas", "en:energy-drinks"];
+
+const ULTRA_PROCESSED_INGREDIENTS: &[&str] = &[
+    "en:flavouring",
+    "en:natural-flavouring",
+    "en:artificial-flavouring",
+    "en:colour",
+    "en:emulsifier",
+    "en:thickener",
+    "en:sweetener",
+    "en:flavour-enhancer",
+    "en:glazing-agent",
+    "en:hydrolysed-proteins",
+    "en:invert-sugar",
+    "en:glucose-syrup",
+    "en:glucose-fructose-syrup",
+    "en:high-fructose-corn-syrup",
+    "en:dextrose",
+    "en:fructose",
+    "en:maltodextrin",
+    "en:lactose",
+    "en:milk-proteins",
+    "en:whey",
+    "en:casein",
+    "en:gluten",
+    "en:modified-starch",
+    "en:hydrogenated-oil",
+    "en:interesterified-oil",
+    "en:soy-protein-isolate",
+];
+
+/// Classifies products into NOVA groups.
+///
+/// The default markers are:
+///
+/// * group 2: the culinary ingredients categories (salts, sugars, oils, ...)
+/// * group 3: the culinary ingredients (salt, sugar, oil, butter, ...) and
+///   the cheeses and fermented drinks categories
+/// * group 4: the ingredients of industrial use (flavourings, emulsifiers,
+///   glucose syrup, whey, ...), the sodas and the additives of the colours
+///   (E100 to E199), emulsifiers, thickeners and stabilisers (E322, E400 to
+///   E499), flavour enhancers (E620 to E650), glazing agents (E900 to E909),
+///   sweeteners (E950 to E969) and modified starches (E1400 to E1452)
+///   classes.
+///
+/// Group 3 ingredients do not apply to the culinary ingredients themselves,
+/// i.e. salt in a salt. Other markers are added with [NovaClassifier::marker].
+#[derive(Debug, Clone, PartialEq)]
+pub struct NovaClassifier {
+    markers: Vec<(MarkerSource, String, NovaGroup)>,
+}
+
+impl Default for NovaClassifier {
+    fn default() -> Self {
+        let markers = [
+            (
+                MarkerSource::Categories,
+                CULINARY_INGREDIENTS_CATEGORIES,
+                NovaGroup::CulinaryIngredients,
+            ),
+            (
+                MarkerSource::Categories,
+                PROCESSED_CATEGORIES,
+                NovaGroup::Processed,
+            ),
+            (
+                MarkerSource::Ingredients,
+                PROCESSED_INGREDIENTS,
+                NovaGroup::Processed,
+            ),
+            (
+                MarkerSource::Categories,
+                ULTRA_PROCESSED_CATEGORIES,
+                NovaGroup::UltraProcessed,
+            ),
+            (
+                MarkerSource::Ingredients,
+                ULTRA_PROCESSED_INGREDIENTS,
+                NovaGroup::UltraProcessed,
+            ),
+        ];
+        Self {
+            markers: markers
+                .iter()
+                .flat_map(|(source, tags, group)| {
+                    tags.iter()
+                        .map(move |tag| (*source, tag.to_string(), *group))
+                })
+                .collect(),
+        }
+    }
+}
+
+impl NovaClassifier {
+    /// Creates a classifier with the default markers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a marker.
+    pub fn marker(mut self, source: MarkerSource, tag: &str, group: NovaGroup) -> Self {
+        self.markers.push((source, tag.to_string(), group));
+        self
+    }
+
+    /// Classifies a product from its `categories_tags`, its ingredients and
+    /// its `additives_tags`. The ingredients are the parsed ingredients and
+    /// their sub-ingredients, and the `ingredients_tags` if the product has
+    /// them (they include the parents of the ingredients, i.e. "en:flavouring"
+    /// for "en:vanilla-flavouring").
+    ///
+    /// Returns None if the product has no ingredients and no category marker,
+    /// as OFF does not compute the NOVA group without ingredients.
+    pub fn classify(&self, product: &Product) -> Option<NovaClassification> {
+        let mut ingredients = Vec::new();
+        ingredient_ids(&product.ingredients, &mut ingredients);
+        if let Some(Value::Array(tags)) = product.extra.get("ingredients_tags") {
+            ingredients.extend(tags.iter().filter_map(Value::as_str));
+        }
+        let categories: Vec<&str> = product.categories_tags.iter().map(String::as_str).collect();
+        let additives: Vec<&str> = product.additives_tags.iter().map(String::as_str).collect();
+        let nova = self.classify_tags(&categories, &ingredients, &additives);
+        let has_category_marker = nova
+            .markers
+            .iter()
+            .any(|m| m.source == MarkerSource::Categories);
+        if ingredients.is_empty() && !has_category_marker {
+            None
+        } else {
+            Some(nova)
+        }
+    }
+
+    /// Classifies a product from its category, ingredient and additive tags.
+    pub fn classify_tags(
+        &self,
+        categories: &[&str],
+        ingredients: &[&str],
+        additives: &[&str],
+    ) -> NovaClassification {
+        let mut markers: Vec<NovaMarker> = Vec::new();
+        let mut add = |group, source, tag: &str| {
+            let marker = NovaMarker {
+                group,
+                source,
+                tag: tag.to_string(),
+            };
+            if !markers.contains(&marker) {
+                markers.push(marker);
+            }
+        };
+        for (source, tags) in [
+            (MarkerSource::Categories, categories),
+            (MarkerSource::Ingredients, ingredients),
+            (MarkerSource::Additives, additives),
+        ] {
+            for tag in tags {
+                for (_, _, group) in self
+                    .markers
+                    .iter()
+                    .filter(|(s, t, _)| *s == source && t == tag)
+                {
+                    add(*group, source, tag);
+                }
+            }
+        }
+        for tag in additives {
+            if is_ultra_processing_additive(tag) {
+                add(NovaGroup::UltraProcessed, MarkerSource::Additives, tag);
+            }
+        }
+
+        let culinary = markers.iter().any(|m| {
+            m.group == NovaGroup::CulinaryIngredients && m.source == MarkerSource::Categories
+        });
+        if culinary {
+            markers.retain(|m| {
+                !(m.group == NovaGroup::Processed && m.source == MarkerSource::Ingredients)
+            });
+        }
+        markers.sort_by_key(|m| m.group);
+        NovaClassification {
+            group: markers
+                .iter()
+                .map(|m| m.group)
+                .max()
+                .unwrap_or(NovaGroup::Unprocessed),
+            markers,
+        }
+    }
+}
+
+// Collects the ids of the ingredients and of their sub-ingredients.
+fn ingredient_ids<'a>(ingredients: &'a [Ingredient], ids: &mut Vec<&'a str>) {
+    for ingredient in ingredients {
+        if let Some(id) = &ingredient.id {
+            ids.push(id);
+        }
+        ingredient_ids(&ingredient.ingredients, ids);
+    }
+}
+
+// Returns true if the additive tag (i.e. "en:e322i") belongs to an
+// ultra-processing class.
+fn is_ultra_processing_additive(tag: &str) -> bool {
+    let number: String = tag
+        .strip_prefix("en:e")
+        .unwrap_or_default()
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    match number.parse::<u32>() {
+        Ok(n) => matches!(
+            n,
+            100..=199 | 322 | 400..=499 | 620..=650 | 900..=909 | 950..=969 | 1400..=1452
+        ),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(markers: &[NovaMarker]) -> Vec<(u8, &str, &str)> {
+        markers
+            .iter()
+            .map(|m| (m.group.number(), m.source.as_str(), m.tag.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn groups() {
+        let classifier = NovaClassifier::new();
+        let nova = classifier.classify_tags(&["en:fruits"], &["en:apple"], &[]);
+        assert_eq!(nova.group, NovaGroup::Unprocessed);
+        assert!(nova.markers.is_empty());
+
+        let nova = classifier.classify_tags(&["en:salts"], &["en:salt"], &[]);
+        assert_eq!(nova.group, NovaGroup::CulinaryIngredients);
+        assert_eq!(tags(&nova.markers), [(2, "categories", "en:salts")]);
+
+        let nova = classifier.classify_tags(&["en:breads"], &["en:flour", "en:salt"], &[]);
+        assert_eq!(nova.group, NovaGroup::Processed);
+        assert_eq!(tags(&nova.markers), [(3, "ingredients", "en:salt")]);
+
+        let nova = classifier.classify_tags(
+            &["en:cheeses"],
+            &["en:milk", "en:salt", "en:colour"],
+            &["en:e160b", "en:e250"],
+        );
+        assert_eq!(nova.group, NovaGroup::UltraProcessed);
+        assert_eq!(
+            tags(&nova.markers),
+            [
+                (3, "categories", "en:cheeses"),
+                (3, "ingredients", "en:salt"),
+                (4, "ingredients", "en:colour"),
+                (4, "additives", "en:e160b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn custom_markers() {
+        let classifier =
+            NovaClassifier::new().marker(MarkerSource::Additives, "en:e250", NovaGroup::Processed);
+        let nova = classifier.classify_tags(&[], &["en:pork"], &["en:e250"]);
+        assert_eq!(nova.group, NovaGroup::Processed);
+        assert_eq!(tags(&nova.markers), [(3, "additives", "en:e250")]);
+    }
+
+    #[test]
+    fn additives() {
+        assert!(is_ultra_processing_additive("en:e322i"));
+        assert!(is_ultra_processing_additive("en:e471"));
+        assert!(is_ultra_processing_additive("en:e1422"));
+        assert!(!is_ultra_processing_additive("en:e330"));
+        assert!(!is_ultra_processing_additive("en:e300"));
+        assert!(!is_ultra_processing_additive("fr:e471"));
+    }
+
+    #[test]
+    fn classify_product() {
+        let product: Product = serde_json::from_str(
+            r#"{
+                "code": "3017620422003",
+                "categories_tags": ["en:spreads"],
+                "ingredients": [
+                    {"id": "en:sugar"},
+                    {"id": "en:emulsifier", "ingredients": [{"id": "en:soya-lecithin"}]},
+                    {"id": "en:vanillin"}
+                ],
+                "ingredients_tags": ["en:sugar", "en:emulsifier", "en:soya-lecithin", "en:vanillin", "en:flavouring"],
+                "additives_tags": ["en:e322", "en:e322i"]
+            }"#,
+        )
+        .unwrap();
+        let nova = NovaClassifier::new().classify(&product).unwrap();
+        assert_eq!(nova.group, NovaGroup::UltraProcessed);
+        assert_eq!(
+            tags(&nova.markers),
+            [
+                (3, "ingredients", "en:sugar"),
+                (4, "ingredients", "en:emulsifier"),
+                (4, "ingredients", "en:flavouring"),
+                (4, "additives", "en:e322"),
+                (4, "additives", "en:e322i"),
+            ]
+        );
+
+        assert_eq!(NovaClassifier::new().classify(&Product::default()), None);
+    }
+}
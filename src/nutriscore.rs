@@ -0,0 +1,601 @@
+//! Local Nutri-Score computation.
+//!
+//! Computes the Nutri-Score of a product from its nutrition facts per 100g
+//! (or 100ml for beverages), with either the 2017 algorithm or the 2023
+//! update. The components and their points follow the `nutriscore_data` of
+//! the OFF products.
+//!
+//! ```
+//! use openfoodfacts::nutriscore::{Grade, NutriScoreCategory, NutriScoreInput, NutriScoreVersion};
+//!
+//! let input = NutriScoreInput {
+//!     energy_kj: 2252.0,
+//!     sugars: 56.3,
+//!     saturated_fat: 10.6,
+//!     fat: 30.9,
+//!     salt: 0.107,
+//!     proteins: 6.3,
+//!     ..NutriScoreInput::default()
+//! };
+//! let nutriscore = input.compute(NutriScoreVersion::V2023, NutriScoreCategory::General);
+//! assert_eq!(nutriscore.score, 31);
+//! assert_eq!(nutriscore.grade, Grade::E);
+//! ```
+use crate::product::Nutriments;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+
+/// The Nutri-Score algorithms.
+///
+/// # Variants:
+///
+/// * V2017 - The original algorithm, `nutriscore_2021` in OFF.
+/// * V2023 - The 2023 update, `nutriscore_2023` in OFF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NutriScoreVersion {
+    V2017,
+    V2023,
+}
+
+/// The food categories with specific Nutri-Score rules.
+///
+/// # Variants:
+///
+/// * General - All other foods.
+/// * Cheese - Proteins are always counted.
+/// * Fats - Added fats and oils (2017), also nuts and seeds (2023). The
+///   saturated fat / fat ratio replaces the saturated fat.
+/// * Beverage - Values per 100ml, with beverage thresholds.
+/// * Water - Always graded A.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NutriScoreCategory {
+    General,
+    Cheese,
+    Fats,
+    Beverage,
+    Water,
+}
+
+/// The Nutri-Score and Eco-Score grades. They are serialized in lower case,
+/// as in the OFF products.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Grade {
+    A,
+    B,
+    C,
+    D,
+    E,
+}
+
+impl Display for Grade {
+    /// Formats the grade as OFF does, in lower case.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let grade = match self {
+            Self::A => "a",
+            Self::B => "b",
+            Self::C => "c",
+            Self::D => "d",
+            Self::E => "e",
+        };
+        write!(f, "{}", grade)
+    }
+}
+
+/// The nutrition facts used by the Nutri-Score, per 100g or 100ml.
+///
+/// Masses are in grams and `fruits_vegetables` is a percentage: the fruits,
+/// vegetables, nuts and colza, walnut and olive oils for the 2017 algorithm,
+/// the fruits, vegetables and legumes for the 2023 one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NutriScoreInput {
+    pub energy_kj: f64,
+    pub sugars: f64,
+    pub fat: f64,
+    pub saturated_fat: f64,
+    pub salt: f64,
+    pub fiber: f64,
+    pub proteins: f64,
+    pub fruits_vegetables: f64,
+    /// Beverages with non-nutritive sweeteners (2023 only).
+    pub non_nutritive_sweeteners: bool,
+    /// Red meat products have their protein points capped at 2 (2023 only).
+    pub red_meat: bool,
+}
+
+/// A component of the score: a nutrient, its value and its points.
+///
+/// Names are the ones of the OFF `nutriscore_data`, i.e. "energy",
+/// "saturated_fat_ratio" or "fruits_vegetables_legumes".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Component {
+    pub name: &'static str,
+    pub value: f64,
+    pub points: i32,
+    pub max_points: i32,
+}
+
+/// A computed Nutri-Score.
+///
+/// `score` is the negative points minus the positive points. Proteins are
+/// not counted in the positive points when `count_proteins` is false.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NutriScore {
+    pub version: NutriScoreVersion,
+    pub category: NutriScoreCategory,
+    pub score: i32,
+    pub grade: Grade,
+    pub negative_points: i32,
+    pub positive_points: i32,
+    pub count_proteins: bool,
+    pub negative: Vec<Component>,
+    pub positive: Vec<Component>,
+}
+
+impl NutriScore {
+    /// Returns the points of the given component.
+    pub fn points(&self, name: &str) -> Option<i32> {
+        self.negative
+            .iter()
+            .chain(&self.positive)
+            .find(|c| c.name == name)
+            .map(|c| c.points)
+    }
+}
+
+// The points of a value: the number of thresholds it exceeds.
+fn points(value: f64, thresholds: &[f64]) -> i32 {
+    thresholds.iter().filter(|t| value > **t).count() as i32
+}
+
+// The saturated fat ratio table uses "greater or equal" thresholds.
+fn ratio_points(value: f64, thresholds: &[f64]) -> i32 {
+    thresholds.iter().filter(|t| value >= **t).count() as i32
+}
+
+fn component(name: &'static str, value: f64, points: i32, max_points: i32) -> Component {
+    Component {
+        name,
+        value,
+        points,
+        max_points,
+    }
+}
+
+const ENERGY: &[f64] = &[
+    335.0, 670.0, 1005.0, 1340.0, 1675.0, 2010.0, 2345.0, 2680.0, 3015.0, 3350.0,
+];
+const SATURATED_FAT: &[f64] = &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0];
+const SATURATED_FAT_RATIO: &[f64] = &[10.0, 16.0, 22.0, 28.0, 34.0, 40.0, 46.0, 52.0, 58.0, 64.0];
+
+// 2017 thresholds.
+const SUGARS_2017: &[f64] = &[4.5, 9.0, 13.5, 18.0, 22.5, 27.0, 31.0, 36.0, 40.0, 45.0];
+const SODIUM_2017: &[f64] = &[
+    90.0, 180.0, 270.0, 360.0, 450.0, 540.0, 630.0, 720.0, 810.0, 900.0,
+];
+const FIBER_2017: &[f64] = &[0.9, 1.9, 2.8, 3.7, 4.7];
+const PROTEINS_2017: &[f64] = &[1.6, 3.2, 4.8, 6.4, 8.0];
+const ENERGY_BEVERAGES_2017: &[f64] = &[
+    0.0, 30.0, 60.0, 90.0, 120.0, 150.0, 180.0, 210.0, 240.0, 270.0,
+];
+const SUGARS_BEVERAGES_2017: &[f64] = &[0.0, 1.5, 3.0, 4.5, 6.0, 7.5, 9.0, 10.5, 12.0, 13.5];
+
+// 2023 thresholds.
+const SUGARS_2023: &[f64] = &[
+    3.4, 6.8, 10.0, 14.0, 17.0, 20.0, 24.0, 27.0, 31.0, 34.0, 37.0, 41.0, 44.0, 48.0, 51.0,
+];
+const SALT_2023: &[f64] = &[
+    0.2, 0.4, 0.6, 0.8, 1.0, 1.2, 1.4, 1.6, 1.8, 2.0, 2.2, 2.4, 2.6, 2.8, 3.0, 3.2, 3.4, 3.6, 3.8,
+    4.0,
+];
+const ENERGY_FROM_SATURATED_FAT_2023: &[f64] = &[
+    120.0, 240.0, 360.0, 480.0, 600.0, 720.0, 840.0, 960.0, 1080.0, 1200.0,
+];
+const FIBER_2023: &[f64] = &[3.0, 4.1, 5.2, 6.3, 7.4];
+const PROTEINS_2023: &[f64] = &[2.4, 4.8, 7.2, 9.6, 12.0, 14.0, 17.0];
+const ENERGY_BEVERAGES_2023: &[f64] = &[
+    30.0, 90.0, 150.0, 210.0, 240.0, 270.0, 300.0, 330.0, 360.0, 390.0,
+];
+const SUGARS_BEVERAGES_2023: &[f64] = &[0.5, 2.0, 3.5, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0];
+const PROTEINS_BEVERAGES_2023: &[f64] = &[1.2, 1.5, 1.8, 2.1, 2.4, 2.7, 3.0];
+
+impl NutriScoreInput {
+    /// Reads the input from the nutrition facts of a product. The fruits and
+    /// vegetables percentage is read from the `{name}_100g` nutriments
+    /// estimated by OFF from the ingredients, `fruits-vegetables-nuts` for
+    /// the 2017 algorithm and `fruits-vegetables-legumes` for the 2023 one.
+    ///
+    /// Returns None if the energy, sugars, saturated fat, salt (or sodium) or
+    /// proteins are missing. The energy falls back to the energy in kcal.
+    pub fn from_nutriments(nutriments: &Nutriments, version: NutriScoreVersion) -> Option<Self> {
+        let energy_kj = nutriments
+            .energy_kj_100g
+            .or_else(|| nutriments.energy_kcal_100g.map(|kcal| kcal * 4.184))?;
+        let salt = nutriments
+            .salt_100g
+            .or_else(|| nutriments.sodium_100g.map(|sodium| sodium * 2.5))?;
+        let fruits = match version {
+            NutriScoreVersion::V2017 => "fruits-vegetables-nuts-estimate-from-ingredients",
+            NutriScoreVersion::V2023 => "fruits-vegetables-legumes-estimate-from-ingredients",
+        };
+        Some(Self {
+            energy_kj,
+            sugars: nutriments.sugars_100g?,
+            fat: nutriments.fat_100g.unwrap_or_default(),
+            saturated_fat: nutriments.saturated_fat_100g?,
+            salt,
+            fiber: nutriments.fiber_100g.unwrap_or_default(),
+            proteins: nutriments.proteins_100g?,
+            fruits_vegetables: nutriments.per_100g(fruits).unwrap_or_default(),
+            non_nutritive_sweeteners: false,
+            red_meat: false,
+        })
+    }
+
+    /// Computes the Nutri-Score with the given algorithm and category rules.
+    pub fn compute(&self, version: NutriScoreVersion, category: NutriScoreCategory) -> NutriScore {
+        match version {
+            NutriScoreVersion::V2017 => self.compute_2017(category),
+            NutriScoreVersion::V2023 => self.compute_2023(category),
+        }
+    }
+
+    // The saturated fat / fat ratio, in percent.
+    fn saturated_fat_ratio(&self) -> f64 {
+        if self.fat > 0.0 {
+            self.saturated_fat / self.fat * 100.0
+        } else {
+            0.0
+        }
+    }
+
+    fn compute_2017(&self, category: NutriScoreCategory) -> NutriScore {
+        let beverage = matches!(
+            category,
+            NutriScoreCategory::Beverage | NutriScoreCategory::Water
+        );
+        let (energy, sugars) = if beverage {
+            (ENERGY_BEVERAGES_2017, SUGARS_BEVERAGES_2017)
+        } else {
+            (ENERGY, SUGARS_2017)
+        };
+        let mut negative = vec![
+            component("energy", self.energy_kj, points(self.energy_kj, energy), 10),
+            component("sugars", self.sugars, points(self.sugars, sugars), 10),
+        ];
+        if category == NutriScoreCategory::Fats {
+            let ratio = self.saturated_fat_ratio();
+            negative.push(component(
+                "saturated_fat_ratio",
+                ratio,
+                ratio_points(ratio, SATURATED_FAT_RATIO),
+                10,
+            ));
+        } else {
+            negative.push(component(
+                "saturated_fat",
+                self.saturated_fat,
+                points(self.saturated_fat, SATURATED_FAT),
+                10,
+            ));
+        }
+        let sodium = self.salt * 400.0;
+        negative.push(component("sodium", sodium, points(sodium, SODIUM_2017), 10));
+
+        let fruits = self.fruits_vegetables;
+        let (fruits_points, fruits_max) = match (beverage, fruits) {
+            (true, f) if f > 80.0 => (10, 10),
+            (true, f) if f > 60.0 => (4, 10),
+            (true, f) if f > 40.0 => (2, 10),
+            (true, _) => (0, 10),
+            (false, f) if f > 80.0 => (5, 5),
+            (false, f) if f > 60.0 => (2, 5),
+            (false, f) if f > 40.0 => (1, 5),
+            (false, _) => (0, 5),
+        };
+        let positive = vec![
+            component(
+                "fruits_vegetables_nuts_colza_walnut_olive_oils",
+                fruits,
+                fruits_points,
+                fruits_max,
+            ),
+            component("fiber", self.fiber, points(self.fiber, FIBER_2017), 5),
+            component(
+                "proteins",
+                self.proteins,
+                points(self.proteins, PROTEINS_2017),
+                5,
+            ),
+        ];
+
+        let negative_points: i32 = negative.iter().map(|c| c.points).sum();
+        let count_proteins =
+            negative_points < 11 || category == NutriScoreCategory::Cheese || fruits_points >= 5;
+        let score = score(negative_points, &positive, count_proteins);
+        let grade = match category {
+            NutriScoreCategory::Water => Grade::A,
+            NutriScoreCategory::Beverage => grade(score.1, [i32::MIN, 1, 5, 9]),
+            _ => grade(score.1, [-1, 2, 10, 18]),
+        };
+        NutriScore {
+            version: NutriScoreVersion::V2017,
+            category,
+            score: score.1,
+            grade,
+            negative_points,
+            positive_points: score.0,
+            count_proteins,
+            negative,
+            positive,
+        }
+    }
+
+    fn compute_2023(&self, category: NutriScoreCategory) -> NutriScore {
+        let beverage = matches!(
+            category,
+            NutriScoreCategory::Beverage | NutriScoreCategory::Water
+        );
+        let mut negative = Vec::new();
+        if category == NutriScoreCategory::Fats {
+            let energy = self.saturated_fat * 37.0;
+            let ratio = self.saturated_fat_ratio();
+            negative.push(component(
+                "energy_from_saturated_fat",
+                energy,
+                points(energy, ENERGY_FROM_SATURATED_FAT_2023),
+                10,
+            ));
+            negative.push(component(
+                "sugars",
+                self.sugars,
+                points(self.sugars, SUGARS_2023),
+                15,
+            ));
+            negative.push(component(
+                "saturated_fat_ratio",
+                ratio,
+                ratio_points(ratio, SATURATED_FAT_RATIO),
+                10,
+            ));
+        } else {
+            let (energy, sugars, sugars_max) = if beverage {
+                (ENERGY_BEVERAGES_2023, SUGARS_BEVERAGES_2023, 10)
+            } else {
+                (ENERGY, SUGARS_2023, 15)
+            };
+            negative.push(component(
+                "energy",
+                self.energy_kj,
+                points(self.energy_kj, energy),
+                10,
+            ));
+            negative.push(component(
+                "sugars",
+                self.sugars,
+                points(self.sugars, sugars),
+                sugars_max,
+            ));
+            negative.push(component(
+                "saturated_fat",
+                self.saturated_fat,
+                points(self.saturated_fat, SATURATED_FAT),
+                10,
+            ));
+        }
+        negative.push(component(
+            "salt",
+            self.salt,
+            points(self.salt, SALT_2023),
+            20,
+        ));
+        if beverage {
+            let sweeteners = if self.non_nutritive_sweeteners { 4 } else { 0 };
+            negative.push(component(
+                "non_nutritive_sweeteners",
+                f64::from(u8::from(self.non_nutritive_sweeteners)),
+                sweeteners,
+                4,
+            ));
+        }
+
+        let fruits = self.fruits_vegetables;
+        let fruits_points = match fruits {
+            f if f > 80.0 => 5,
+            f if f > 60.0 => 2,
+            f if f > 40.0 => 1,
+            _ => 0,
+        };
+        let (fruits_points, fruits_max, proteins) = if beverage {
+            let points = match fruits_points {
+                5 => 6,
+                2 => 4,
+                1 => 2,
+                _ => 0,
+            };
+            (points, 6, PROTEINS_BEVERAGES_2023)
+        } else {
+            (fruits_points, 5, PROTEINS_2023)
+        };
+        let mut proteins_points = points(self.proteins, proteins);
+        if self.red_meat {
+            proteins_points = proteins_points.min(2);
+        }
+        let positive = vec![
+            component("proteins", self.proteins, proteins_points, 7),
+            component("fiber", self.fiber, points(self.fiber, FIBER_2023), 5),
+            component(
+                "fruits_vegetables_legumes",
+                fruits,
+                fruits_points,
+                fruits_max,
+            ),
+        ];
+
+        let negative_points: i32 = negative.iter().map(|c| c.points).sum();
+        let count_proteins = match category {
+            NutriScoreCategory::Beverage | NutriScoreCategory::Water => true,
+            NutriScoreCategory::Cheese => true,
+            NutriScoreCategory::Fats => negative_points < 7,
+            NutriScoreCategory::General => negative_points < 11,
+        };
+        let score = score(negative_points, &positive, count_proteins);
+        let grade = match category {
+            NutriScoreCategory::Water => Grade::A,
+            NutriScoreCategory::Beverage => grade(score.1, [i32::MIN, 2, 6, 9]),
+            NutriScoreCategory::Fats => grade(score.1, [-6, 2, 10, 18]),
+            _ => grade(score.1, [0, 2, 10, 18]),
+        };
+        NutriScore {
+            version: NutriScoreVersion::V2023,
+            category,
+            score: score.1,
+            grade,
+            negative_points,
+            positive_points: score.0,
+            count_proteins,
+            negative,
+            positive,
+        }
+    }
+}
+
+// Returns the positive points and the score.
+fn score(negative_points: i32, positive: &[Component], count_proteins: bool) -> (i32, i32) {
+    let positive_points: i32 = positive
+        .iter()
+        .filter(|c| count_proteins || c.name != "proteins")
+        .map(|c| c.points)
+        .sum();
+    (positive_points, negative_points - positive_points)
+}
+
+// Returns the grade of the score, given the maximum scores of the A to D
+// grades.
+fn grade(score: i32, max_scores: [i32; 4]) -> Grade {
+    let grades = [Grade::A, Grade::B, Grade::C, Grade::D];
+    max_scores
+        .iter()
+        .zip(grades)
+        .find(|(max, _)| score <= **max)
+        .map_or(Grade::E, |(_, grade)| grade)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    // A product of the fixtures, with the `nutriscore_data` computed by OFF
+    // for each version.
+    #[derive(Deserialize)]
+    struct Fixture {
+        name: String,
+        category: String,
+        #[serde(default)]
+        non_nutritive_sweeteners: bool,
+        #[serde(default)]
+        red_meat: bool,
+        nutriments: Nutriments,
+        nutriscore_data: HashMap<String, HashMap<String, serde_json::Value>>,
+    }
+
+    fn category(name: &str) -> NutriScoreCategory {
+        match name {
+            "general" => NutriScoreCategory::General,
+            "cheese" => NutriScoreCategory::Cheese,
+            "fats" => NutriScoreCategory::Fats,
+            "beverage" => NutriScoreCategory::Beverage,
+            "water" => NutriScoreCategory::Water,
+            _ => panic!("unknown category {}", name),
+        }
+    }
+
+    #[test]
+    fn fixtures() {
+        let fixtures: Vec<Fixture> =
+            serde_json::from_str(include_str!("../tests/fixtures/nutriscore.json")).unwrap();
+        for fixture in fixtures {
+            for (version, expected) in &fixture.nutriscore_data {
+                let version = match version.as_str() {
+                    "2017" => NutriScoreVersion::V2017,
+                    "2023" => NutriScoreVersion::V2023,
+                    _ => panic!("unknown version {}", version),
+                };
+                let mut input = NutriScoreInput::from_nutriments(&fixture.nutriments, version)
+                    .unwrap_or_else(|| panic!("{}: missing nutriments", fixture.name));
+                input.non_nutritive_sweeteners = fixture.non_nutritive_sweeteners;
+                input.red_meat = fixture.red_meat;
+                let nutriscore = input.compute(version, category(&fixture.category));
+                let context = format!("{} {:?}", fixture.name, version);
+                for (key, value) in expected {
+                    let actual = match key.as_str() {
+                        "grade" => serde_json::json!(nutriscore.grade.to_string()),
+                        "score" => serde_json::json!(nutriscore.score),
+                        "negative_points" => serde_json::json!(nutriscore.negative_points),
+                        "positive_points" => serde_json::json!(nutriscore.positive_points),
+                        "count_proteins" => serde_json::json!(nutriscore.count_proteins),
+                        key => {
+                            let name = key.strip_suffix("_points").unwrap();
+                            serde_json::json!(nutriscore.points(name).unwrap())
+                        }
+                    };
+                    assert_eq!(&actual, value, "{}: {}", context, key);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn thresholds() {
+        assert_eq!(points(335.0, ENERGY), 0);
+        assert_eq!(points(335.1, ENERGY), 1);
+        assert_eq!(points(5000.0, ENERGY), 10);
+        assert_eq!(ratio_points(9.9, SATURATED_FAT_RATIO), 0);
+        assert_eq!(ratio_points(10.0, SATURATED_FAT_RATIO), 1);
+    }
+
+    #[test]
+    fn grades() {
+        let general = [-1, 2, 10, 18];
+        assert_eq!(grade(-5, general), Grade::A);
+        assert_eq!(grade(-1, general), Grade::A);
+        assert_eq!(grade(0, general), Grade::B);
+        assert_eq!(grade(10, general), Grade::C);
+        assert_eq!(grade(18, general), Grade::D);
+        assert_eq!(grade(19, general), Grade::E);
+        assert_eq!(Grade::B.to_string(), "b");
+    }
+
+    #[test]
+    fn water() {
+        let input = NutriScoreInput::default();
+        for version in [NutriScoreVersion::V2017, NutriScoreVersion::V2023] {
+            let nutriscore = input.compute(version, NutriScoreCategory::Water);
+            assert_eq!(nutriscore.grade, Grade::A);
+        }
+    }
+
+    #[test]
+    fn missing_nutriments() {
+        let nutriments = Nutriments {
+            energy_kcal_100g: Some(100.0),
+            sugars_100g: Some(1.0),
+            saturated_fat_100g: Some(1.0),
+            sodium_100g: Some(0.4),
+            proteins_100g: Some(1.0),
+            ..Nutriments::default()
+        };
+        let input =
+            NutriScoreInput::from_nutriments(&nutriments, NutriScoreVersion::V2023).unwrap();
+        assert!((input.energy_kj - 418.4).abs() < 1e-9);
+        assert!((input.salt - 1.0).abs() < 1e-9);
+        assert_eq!(input.fiber, 0.0);
+
+        let nutriments = Nutriments {
+            sugars_100g: None,
+            ..nutriments
+        };
+        assert!(NutriScoreInput::from_nutriments(&nutriments, NutriScoreVersion::V2023).is_none());
+    }
+}
@@ -0,0 +1,743 @@
+//! SQLite product store, to look up products without network access.
+//!
+//! An [OfflineStore] keeps products in a SQLite database, indexed by barcode,
+//! brand, category and country tags. Products are imported from a dump, from
+//! API responses or from the delta exports, as a [ProductStore].
+//!
+//! The store is also a [Transport]: an [crate::OffClient] built with it
+//! answers [crate::OffClient::product] and simple searches from the database,
+//! and a [Fallback] transport does so only when the network is unavailable.
+//! Requires the `offline` feature.
+//!
+//! ```no_run
+//! use openfoodfacts::{self as off, dump::Dump, offline::OfflineStore};
+//!
+//! # fn main() -> Result<(), off::Error> {
+//! let store = OfflineStore::open("products.db")?;
+//! store.import(Dump::open("openfoodfacts-products.jsonl.gz")?.products())?;
+//!
+//! let client = off::v2().build_with(store);
+//! let response = client.product_typed("3017620422003", None)?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::client::{Error, HttpResponse};
+use crate::delta::ProductStore;
+use crate::product::Product;
+use crate::response::from_slice;
+use crate::transport::{FakeResponse, Transport};
+use reqwest::blocking::Request;
+use reqwest::Method;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::sync::Mutex;
+use url::Url;
+
+// The indexed tag fields, named as in the search parameters.
+const TAG_FIELDS: [&str; 3] = ["brands_tags", "categories_tags", "countries_tags"];
+
+// The default page size of the searches, as on the server.
+const PAGE_SIZE: usize = 24;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS products (
+        code TEXT PRIMARY KEY,
+        product TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS product_tags (
+        field TEXT NOT NULL,
+        tag TEXT NOT NULL,
+        code TEXT NOT NULL,
+        PRIMARY KEY (field, tag, code)
+    ) WITHOUT ROWID;
+    CREATE INDEX IF NOT EXISTS product_tags_code ON product_tags (code);
+";
+
+// A filter of the searches: the products with any of the `tags` in the tag
+// `field`, i.e. `categories_tags`, or without them if `negate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TagFilter {
+    field: &'static str,
+    tags: Vec<String>,
+    negate: bool,
+}
+
+/// A SQLite database of products.
+///
+/// Products are stored as JSON in the layout of the API, so they are read
+/// back as they were imported, and replaced by code. The `brands_tags`,
+/// `categories_tags` and `countries_tags` are indexed.
+///
+/// # Transport
+///
+/// As a [Transport], the store answers the following GET requests:
+///
+/// * `/api/{version}/product/{barcode}`: the product, or `404 Not Found` with
+///   a status 0, as the server does.
+/// * `/api/v2/search`: the products matching the `code`, `brands_tags`,
+///   `categories_tags` and `countries_tags` parameters, sorted by code. As on
+///   the server, `,` separates the tags that must all match, `|` the tags of
+///   which any must match, and a leading `-` excludes the tag.
+/// * `/brand/{id}.json`, `/category/{id}.json`, `/country/{id}.json`: the
+///   products of [crate::OffClient::products_by]. An `id` without language
+///   prefix also matches the `en:` tag.
+///
+/// The `page`, `page_size` and `fields` parameters are supported. Searches
+/// with other criteria get a `400 Bad Request`, and other requests a `501 Not
+/// Implemented`. The clients built on the store skip the rate limits, see
+/// [Transport::is_local].
+#[derive(Debug)]
+pub struct OfflineStore {
+    conn: Mutex<Connection>,
+}
+
+impl OfflineStore {
+    /// Opens the database at the given path, creating it if needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(Connection::open(path)?)
+    }
+
+    /// Creates a database in memory.
+    pub fn in_memory() -> Result<Self, Error> {
+        Self::new(Connection::open_in_memory()?)
+    }
+
+    fn new(conn: Connection) -> Result<Self, Error> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Inserts the product, or replaces the product with the same code.
+    pub fn insert(&self, product: &Product) -> Result<(), Error> {
+        insert(&self.conn.lock().unwrap(), product)
+    }
+
+    /// Inserts the products in a single transaction, i.e. those of a
+    /// [crate::dump::Dump] or of a [crate::Paginator]. Returns the number of
+    /// inserted products. Stops at the first error, keeping nothing.
+    pub fn import<I>(&self, products: I) -> Result<u64, Error>
+    where
+        I: IntoIterator<Item = Result<Product, Error>>,
+    {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut count = 0;
+        for product in products {
+            insert(&tx, &product?)?;
+            count += 1;
+        }
+        tx.commit()?;
+        Ok(count)
+    }
+
+    /// Returns the number of products.
+    pub fn len(&self) -> Result<u64, Error> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.query_row("SELECT COUNT(*) FROM products", [], |row| row.get(0))?)
+    }
+
+    /// Returns true if the store has no products.
+    pub fn is_empty(&self) -> Result<bool, Error> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Returns the product with the given barcode.
+    pub fn product(&self, code: &str) -> Result<Option<Product>, Error> {
+        self.product_json(code)?
+            .map(|json| from_slice(json.as_bytes()))
+            .transpose()
+    }
+
+    /// Returns the products of the given brand tag, i.e. `ferrero`.
+    pub fn by_brand(&self, tag: &str) -> Result<Vec<Product>, Error> {
+        self.by_tag("brands_tags", tag)
+    }
+
+    /// Returns the products of the given category tag, i.e. `en:cheeses`.
+    pub fn by_category(&self, tag: &str) -> Result<Vec<Product>, Error> {
+        self.by_tag("categories_tags", tag)
+    }
+
+    /// Returns the products sold in the given country tag, i.e. `en:france`.
+    pub fn by_country(&self, tag: &str) -> Result<Vec<Product>, Error> {
+        self.by_tag("countries_tags", tag)
+    }
+
+    /// Returns a [Fallback] transport sending the requests through `network`,
+    /// and answering them from this store when the network is unavailable.
+    pub fn fallback<T: Transport>(self, network: T) -> Fallback<T> {
+        Fallback {
+            network,
+            store: self,
+        }
+    }
+
+    fn by_tag(&self, field: &'static str, tag: &str) -> Result<Vec<Product>, Error> {
+        let filter = TagFilter {
+            field,
+            tags: vec![tag.to_string()],
+            negate: false,
+        };
+        let (_, products) = self.search(&[], &[filter], None, 0)?;
+        products
+            .iter()
+            .map(|json| from_slice(json.as_bytes()))
+            .collect()
+    }
+
+    // Returns the number of matching products, and the JSON of at most
+    // `limit` of them, or all of them if None.
+    fn search(
+        &self,
+        codes: &[String],
+        filters: &[TagFilter],
+        limit: Option<usize>,
+        offset: usize,
+    ) -> Result<(u64, Vec<String>), Error> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<&str> = Vec::new();
+        if !codes.is_empty() {
+            conditions.push(format!("p.code IN ({})", placeholders(codes.len())));
+            values.extend(codes.iter().map(String::as_str));
+        }
+        for filter in filters {
+            conditions.push(format!(
+                "{}EXISTS (SELECT 1 FROM product_tags t \
+                 WHERE t.field = ? AND t.tag IN ({}) AND t.code = p.code)",
+                if filter.negate { "NOT " } else { "" },
+                placeholders(filter.tags.len())
+            ));
+            values.push(filter.field);
+            values.extend(filter.tags.iter().map(String::as_str));
+        }
+        let filter = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", conditions.join(" AND "))
+        };
+
+        let conn = self.conn.lock().unwrap();
+        let count = conn.query_row(
+            &format!("SELECT COUNT(*) FROM products p{}", filter),
+            params_from_iter(&values),
+            |row| row.get(0),
+        )?;
+        // SQLite takes a negative limit as no limit.
+        let limit = limit.map_or(-1, |l| l as i64);
+        let mut stmt = conn.prepare(&format!(
+            "SELECT p.product FROM products p{} ORDER BY p.code LIMIT {} OFFSET {}",
+            filter, limit, offset
+        ))?;
+        let products = stmt
+            .query_map(params_from_iter(&values), |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok((count, products))
+    }
+
+    // Answers a request of an OffClient.
+    fn respond(&self, url: &Url) -> Result<FakeResponse, Error> {
+        let segments: Vec<&str> = url.path_segments().map_or(Vec::new(), |s| s.collect());
+        let response = match segments[..] {
+            ["api", _, "product", code] => self.product_response(url, code)?,
+            ["api", "v2", "search"] => match search_filters(url) {
+                Ok((codes, filters)) => self.search_response(url, &codes, &filters)?,
+                Err(name) => FakeResponse::status(400)
+                    .body(format!("search parameter not supported offline: {}", name)),
+            },
+            [what, id] => match (facet_field(what), id.strip_suffix(".json")) {
+                (Some(field), Some(id)) => {
+                    let mut tags = vec![id.to_string()];
+                    if !id.contains(':') {
+                        tags.push(format!("en:{}", id));
+                    }
+                    let filter = TagFilter {
+                        field,
+                        tags,
+                        negate: false,
+                    };
+                    self.search_response(url, &[], &[filter])?
+                }
+                _ => not_implemented(),
+            },
+            _ => not_implemented(),
+        };
+        Ok(response)
+    }
+
+    fn product_json(&self, code: &str) -> Result<Option<String>, Error> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn
+            .query_row(
+                "SELECT product FROM products WHERE code = ?1",
+                [code],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    fn product_response(&self, url: &Url, code: &str) -> Result<FakeResponse, Error> {
+        let fields = query(url, "fields");
+        let response = match self.product_json(code)? {
+            Some(json) => {
+                let body = json!({
+                    "code": code,
+                    "status": 1,
+                    "status_verbose": "product found",
+                    "product": project(&json, fields.as_deref()),
+                });
+                FakeResponse::json(&body.to_string())
+            }
+            None => {
+                let body = json!({
+                    "code": code,
+                    "status": 0,
+                    "status_verbose": "product not found",
+                });
+                FakeResponse::status(404)
+                    .header("content-type", "application/json")
+                    .body(body.to_string())
+            }
+        };
+        Ok(response)
+    }
+
+    fn search_response(
+        &self,
+        url: &Url,
+        codes: &[String],
+        filters: &[TagFilter],
+    ) -> Result<FakeResponse, Error> {
+        let page = query_number(url, "page").unwrap_or(1).max(1);
+        let page_size = query_number(url, "page_size").unwrap_or(PAGE_SIZE).max(1);
+        let skip = (page - 1) * page_size;
+        let (count, products) = self.search(codes, filters, Some(page_size), skip)?;
+        let fields = query(url, "fields");
+        let products = products
+            .iter()
+            .map(|json| project(json, fields.as_deref()))
+            .collect::<Vec<_>>();
+        let body = json!({
+            "count": count,
+            "page": page,
+            "page_count": (count as usize).div_ceil(page_size),
+            "page_size": page_size,
+            "skip": skip,
+            "products": products,
+        });
+        Ok(FakeResponse::json(&body.to_string()))
+    }
+}
+
+impl Transport for OfflineStore {
+    fn send(&self, request: Request) -> Result<HttpResponse, Error> {
+        let url = request.url();
+        let response = if request.method() == Method::GET {
+            self.respond(url)?
+        } else {
+            FakeResponse::status(405).body("only GET requests are answered offline")
+        };
+        Ok(response.to_response(url))
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+}
+
+impl ProductStore for OfflineStore {
+    /// Inserts the product in the current transaction, started by the first
+    /// upsert after a flush. Faster than [OfflineStore::insert] for many
+    /// products.
+    fn upsert(&mut self, product: Product) -> Result<(), Error> {
+        let conn = self.conn.get_mut().unwrap();
+        if conn.is_autocommit() {
+            conn.execute_batch("BEGIN")?;
+        }
+        insert(conn, &product)
+    }
+
+    /// Commits the transaction of the upserts.
+    fn flush(&mut self) -> Result<(), Error> {
+        let conn = self.conn.get_mut().unwrap();
+        if !conn.is_autocommit() {
+            conn.execute_batch("COMMIT")?;
+        }
+        Ok(())
+    }
+
+    /// Rolls the transaction of the upserts back, so that the next inserts
+    /// are not part of it.
+    fn rollback(&mut self) -> Result<(), Error> {
+        let conn = self.conn.get_mut().unwrap();
+        if !conn.is_autocommit() {
+            conn.execute_batch("ROLLBACK")?;
+        }
+        Ok(())
+    }
+}
+
+/// A transport sending the requests through another transport, i.e. the
+/// reqwest client, and answering them from an [OfflineStore] when the
+/// network is unavailable. Returned by [OfflineStore::fallback].
+///
+/// Only connection errors and timeouts fall back to the store: the responses
+/// of the server, errors included, are returned as is.
+///
+/// ```no_run
+/// use openfoodfacts::{self as off, offline::OfflineStore};
+///
+/// # fn main() -> Result<(), off::Error> {
+/// let builder = off::v2();
+/// let store = OfflineStore::open("products.db")?;
+/// let network = builder.http_client()?;
+/// let client = builder.build_with(store.fallback(network));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Fallback<T> {
+    network: T,
+    store: OfflineStore,
+}
+
+impl<T> Fallback<T> {
+    /// Returns the store.
+    pub fn store(&self) -> &OfflineStore {
+        &self.store
+    }
+}
+
+impl<T: Transport> Transport for Fallback<T> {
+    fn send(&self, request: Request) -> Result<HttpResponse, Error> {
+        // Requests with a streamed body cannot be copied, nor answered
+        // offline.
+        let copy = request.try_clone();
+        match self.network.send(request) {
+            Err(Error::Network(err)) if err.is_connect() || err.is_timeout() => match copy {
+                Some(copy) => self.store.send(copy),
+                None => Err(Error::Network(err)),
+            },
+            result => result,
+        }
+    }
+}
+
+fn insert(conn: &Connection, product: &Product) -> Result<(), Error> {
+    let json = serde_json::to_string(product).expect("product serialization");
+    conn.execute(
+        "INSERT OR REPLACE INTO products (code, product) VALUES (?1, ?2)",
+        params![product.code, json],
+    )?;
+    conn.execute("DELETE FROM product_tags WHERE code = ?1", [&product.code])?;
+    let mut stmt = conn.prepare_cached(
+        "INSERT OR IGNORE INTO product_tags (field, tag, code) VALUES (?1, ?2, ?3)",
+    )?;
+    for (field, tags) in TAG_FIELDS.iter().zip([
+        &product.brands_tags,
+        &product.categories_tags,
+        &product.countries_tags,
+    ]) {
+        for tag in tags {
+            stmt.execute(params![field, tag, product.code])?;
+        }
+    }
+    Ok(())
+}
+
+// Returns the codes and tag filters of a search, or the name of the first
+// parameter not supported offline.
+fn search_filters(url: &Url) -> Result<(Vec<String>, Vec<TagFilter>), String> {
+    let mut codes = Vec::new();
+    let mut filters = Vec::new();
+    for (name, value) in url.query_pairs() {
+        match name.as_ref() {
+            "code" => codes.extend(value.split(',').map(String::from)),
+            "page" | "page_size" | "fields" | "sort_by" | "cc" | "lc" | "nocache" | "json" => {}
+            name => {
+                let field = match TAG_FIELDS.iter().find(|f| **f == name) {
+                    Some(field) => *field,
+                    None => return Err(name.to_string()),
+                };
+                for tags in value.split(',') {
+                    let (tags, negate) = match tags.strip_prefix('-') {
+                        Some(tags) => (tags, true),
+                        None => (tags, false),
+                    };
+                    filters.push(TagFilter {
+                        field,
+                        tags: tags.split('|').map(String::from).collect(),
+                        negate,
+                    });
+                }
+            }
+        }
+    }
+    Ok((codes, filters))
+}
+
+// Parses a stored product, keeping only the comma-separated `fields` of the
+// request, as the server does.
+fn project(json: &str, fields: Option<&str>) -> Value {
+    let mut product: serde_json::Map<String, Value> =
+        serde_json::from_str(json).unwrap_or_default();
+    if let Some(fields) = fields {
+        let fields: Vec<&str> = fields.split(',').collect();
+        product.retain(|key, _| fields.contains(&key.as_str()));
+    }
+    Value::Object(product)
+}
+
+// Returns the tag field of a facet of OffClient::products_by.
+fn facet_field(what: &str) -> Option<&'static str> {
+    match what {
+        "brand" => Some("brands_tags"),
+        "category" => Some("categories_tags"),
+        "country" => Some("countries_tags"),
+        _ => None,
+    }
+}
+
+fn not_implemented() -> FakeResponse {
+    FakeResponse::status(501).body("not available offline")
+}
+
+fn placeholders(n: usize) -> String {
+    vec!["?"; n].join(", ")
+}
+
+fn query(url: &Url, name: &str) -> Option<String> {
+    url.query_pairs()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.into_owned())
+}
+
+fn query_number(url: &Url, name: &str) -> Option<usize> {
+    query(url, name)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dump::Dump;
+    use crate::{Output, RetryPolicy};
+    use reqwest::StatusCode;
+
+    const FIXTURE: &[u8] = include_bytes!("../tests/fixtures/products.jsonl.gz");
+
+    fn product(json: Value) -> Product {
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn store() -> OfflineStore {
+        let store = OfflineStore::in_memory().unwrap();
+        let products = vec![
+            product(json!({
+                "code": "3017620422003",
+                "product_name": "Nutella",
+                "brands_tags": ["ferrero", "nutella"],
+                "categories_tags": ["en:spreads", "en:sweet-spreads"],
+                "countries_tags": ["en:france", "en:germany"],
+                "custom_field": 1,
+            })),
+            product(json!({
+                "code": "5449000000996",
+                "product_name": "Coca-Cola",
+                "brands_tags": ["coca-cola"],
+                "categories_tags": ["en:sodas"],
+                "countries_tags": ["en:france"],
+            })),
+            product(json!({
+                "code": "5449000131805",
+                "product_name": "Coca-Cola Zero",
+                "brands_tags": ["coca-cola"],
+                "categories_tags": ["en:sodas", "en:diet-sodas"],
+                "countries_tags": ["en:germany"],
+            })),
+        ];
+        assert_eq!(store.import(products.into_iter().map(Ok)).unwrap(), 3);
+        store
+    }
+
+    fn codes(products: &[Product]) -> Vec<&str> {
+        products.iter().map(|p| p.code.as_str()).collect()
+    }
+
+    #[test]
+    fn lookup() {
+        let store = store();
+        assert_eq!(store.len().unwrap(), 3);
+        let nutella = store.product("3017620422003").unwrap().unwrap();
+        assert_eq!(nutella.product_name.as_deref(), Some("Nutella"));
+        assert_eq!(nutella.extra["custom_field"], 1);
+        assert_eq!(store.product("123").unwrap(), None);
+
+        assert_eq!(
+            codes(&store.by_brand("coca-cola").unwrap()),
+            ["5449000000996", "5449000131805"]
+        );
+        assert_eq!(
+            codes(&store.by_category("en:spreads").unwrap()),
+            ["3017620422003"]
+        );
+        assert_eq!(
+            codes(&store.by_country("en:germany").unwrap()),
+            ["3017620422003", "5449000131805"]
+        );
+
+        // Replacing a product replaces its tags.
+        store
+            .insert(&product(json!({
+                "code": "5449000131805",
+                "categories_tags": ["en:waters"],
+            })))
+            .unwrap();
+        assert_eq!(store.len().unwrap(), 3);
+        assert_eq!(
+            codes(&store.by_category("en:sodas").unwrap()),
+            ["5449000000996"]
+        );
+        assert_eq!(store.by_country("en:germany").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn import_dump() {
+        let store = OfflineStore::in_memory().unwrap();
+        assert!(store.is_empty().unwrap());
+        assert_eq!(store.import(Dump::gzip(FIXTURE).products()).unwrap(), 4);
+
+        // A failed import keeps nothing.
+        let store = OfflineStore::in_memory().unwrap();
+        let mut truncated = Dump::gzip(&FIXTURE[..FIXTURE.len() / 2]);
+        assert!(store.import(truncated.products()).is_err());
+        assert!(store.is_empty().unwrap());
+    }
+
+    #[test]
+    fn product_store() {
+        let mut store = OfflineStore::in_memory().unwrap();
+        store.upsert(product(json!({"code": "1"}))).unwrap();
+        store.upsert(product(json!({"code": "2"}))).unwrap();
+        store.flush().unwrap();
+        assert_eq!(store.len().unwrap(), 2);
+        assert!(store.conn.get_mut().unwrap().is_autocommit());
+
+        // A rolled back upsert is discarded, and the next inserts are not in
+        // its transaction.
+        store.upsert(product(json!({"code": "3"}))).unwrap();
+        store.rollback().unwrap();
+        store.insert(&product(json!({"code": "4"}))).unwrap();
+        assert!(store.conn.get_mut().unwrap().is_autocommit());
+        assert_eq!(store.product("3").unwrap(), None);
+        assert!(store.product("4").unwrap().is_some());
+    }
+
+    #[test]
+    fn no_rate_limit() {
+        // The OFF quota allows 10 searches a minute.
+        let client = crate::v2().build_with(store());
+        let start = std::time::Instant::now();
+        for _ in 0..12 {
+            let query = client.query().criteria("brands", "coca-cola", None);
+            client.search(query, None).unwrap();
+        }
+        assert!(start.elapsed() < std::time::Duration::from_secs(3));
+    }
+
+    #[test]
+    fn client_product() {
+        let client = crate::v2().build_with(store());
+        let response = client.product_typed("5449000000996", None).unwrap();
+        assert!(response.is_found());
+        assert_eq!(
+            response.product.unwrap().product_name.as_deref(),
+            Some("Coca-Cola")
+        );
+
+        // As on the server, only the requested fields are returned.
+        let output = Output::new().fields("code,brands_tags");
+        let product = client
+            .product_typed("5449000000996", Some(output))
+            .unwrap()
+            .product
+            .unwrap();
+        assert_eq!(product.product_name, None);
+        assert_eq!(product.brands_tags, ["coca-cola"]);
+
+        let response = client.product_typed("123", None).unwrap();
+        assert!(!response.is_found());
+        let client = crate::v0().error_for_status(true).build_with(store());
+        assert!(matches!(
+            client.product_typed("123", None),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn client_search() {
+        let client = crate::v2().build_with(store());
+        let search = |query, page_size| {
+            let output = Output::new().page_size(page_size);
+            let products: Vec<Product> = client
+                .search_iter(query, Some(output))
+                .unwrap()
+                .map(Result::unwrap)
+                .collect();
+            products
+        };
+        let query = client.query().criteria("brands", "coca-cola", None);
+        assert_eq!(codes(&search(query, 1)), ["5449000000996", "5449000131805"]);
+        let query = client
+            .query()
+            .criteria("categories", "en:sodas", None)
+            .criteria("countries", "en:france|en:germany", None);
+        assert_eq!(search(query, 24).len(), 2);
+        let query = client
+            .query()
+            .criteria("categories", "-en:diet-sodas", None);
+        assert_eq!(search(query, 24).len(), 2);
+
+        let products: Vec<Product> = client
+            .products_by_iter("category", "sodas", None)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(products.len(), 2);
+
+        let output = Output::new().fields("code");
+        let response = client
+            .products("5449000000996,3017620422003", Some(output))
+            .unwrap();
+        let response: crate::SearchResponse = crate::response::decode(response).unwrap();
+        assert_eq!(response.count, Some(2));
+        assert_eq!(
+            codes(&response.products),
+            ["3017620422003", "5449000000996"]
+        );
+        assert_eq!(response.products[0].product_name, None);
+
+        let query = client.query().nutrient_100g("sugars", "<", 5);
+        let response = client.search(query, None).unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = client.nutrients(None).unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+    }
+
+    #[test]
+    fn fallback() {
+        // Nothing listens on port 1.
+        let builder = crate::v2()
+            .scheme("http")
+            .domain("127.0.0.1")
+            .port(1)
+            .single_host(true)
+            .retry(RetryPolicy::none());
+        let network = builder.http_client().unwrap();
+        let client = builder.build_with(store().fallback(network));
+        let response = client.product_typed("3017620422003", None).unwrap();
+        assert!(response.is_found());
+        assert_eq!(client.transport().store().len().unwrap(), 3);
+    }
+}
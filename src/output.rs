@@ -16,7 +16,7 @@ use crate::types::Params;
 /// assert_eq!(output.locale.unwrap().cc, String::from("fr"));
 /// assert_eq!(output.page.unwrap(), 1);
 /// ```
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct Output {
     pub locale: Option<Locale>,
     pub page: Option<usize>,
@@ -60,7 +60,8 @@ impl Output {
         self
     }
 
-    /// Sets the nocache field.
+    /// Sets the nocache field. Besides asking the server for a fresh response,
+    /// `nocache(true)` bypasses the client cache (see [crate::OffBuilder::cache]).
     pub fn nocache(mut self, nocache: bool) -> Self {
         self.nocache = Some(nocache);
         self
@@ -0,0 +1,425 @@
+// Iterate over the products of all the pages of a search or `products_by`
+// request.
+//
+// The pages are fetched lazily, one request per page, until the last page,
+// an empty page or one of the caps is reached.
+use crate::client::{Error, RequestMethods};
+use crate::output::Output;
+use crate::product::{Product, SearchResponse};
+use crate::response::decode;
+use crate::types::Params;
+use std::collections::VecDeque;
+use url::Url;
+
+// The request of a page: the URL and the query parameters common to all the
+// pages.
+#[derive(Debug, Clone)]
+struct PageRequest {
+    url: Url,
+    params: Vec<(String, String)>,
+}
+
+impl PageRequest {
+    // The page number given in `params` or in the output is ignored, pages
+    // are added by `params()`.
+    fn new(url: Url, params: Params<'_>, output: Option<&Output>) -> Self {
+        let mut params: Vec<(String, String)> = params
+            .into_iter()
+            .filter(|(name, _)| *name != "page")
+            .map(|(name, value)| (String::from(name), value))
+            .collect();
+        if let Some(o) = output {
+            for (name, value) in o.params(&["page_size", "fields", "nocache"]) {
+                if !params.iter().any(|(n, _)| n == name) {
+                    params.push((String::from(name), value));
+                }
+            }
+        }
+        Self { url, params }
+    }
+
+    // Returns the query parameters of the given page.
+    fn params(&self, page: usize) -> Params<'_> {
+        let mut params: Params = self
+            .params
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.clone()))
+            .collect();
+        params.push(("page", page.to_string()));
+        params
+    }
+}
+
+// The pagination state shared by the blocking and async paginators.
+#[derive(Debug)]
+struct Cursor {
+    // The next page to fetch.
+    page: usize,
+    max_items: Option<usize>,
+    max_pages: Option<usize>,
+    // The number of pages fetched and products returned so far.
+    pages: usize,
+    items: usize,
+    // The products of the last page not returned yet.
+    buffer: VecDeque<Product>,
+    done: bool,
+}
+
+impl Cursor {
+    fn new(output: Option<&Output>) -> Self {
+        Self {
+            page: output.and_then(|o| o.page).unwrap_or(1),
+            max_items: None,
+            max_pages: None,
+            pages: 0,
+            items: 0,
+            buffer: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    fn items_left(&self) -> bool {
+        self.max_items.is_none_or(|max| self.items < max)
+    }
+
+    // Returns the next buffered product.
+    fn pop(&mut self) -> Option<Product> {
+        if !self.items_left() {
+            return None;
+        }
+        let product = self.buffer.pop_front()?;
+        self.items += 1;
+        Some(product)
+    }
+
+    // Returns the page to fetch next, if any.
+    fn next_page(&self) -> Option<usize> {
+        let pages_left = self.max_pages.is_none_or(|max| self.pages < max);
+        if self.done || !pages_left || !self.items_left() {
+            return None;
+        }
+        Some(self.page)
+    }
+
+    fn push(&mut self, response: SearchResponse) {
+        self.done = response.is_last_page(self.page);
+        self.pages += 1;
+        self.page += 1;
+        self.buffer.extend(response.products);
+    }
+
+    // Stops the iteration after an error.
+    fn fail(&mut self) {
+        self.done = true;
+        self.buffer.clear();
+    }
+}
+
+/// An iterator over the products of a paginated request, returned by
+/// `search_iter()` and `products_by_iter()`.
+///
+/// Pages are fetched on demand, starting at [Output::page] (or the first
+/// page) with [Output::page_size] products per page. The iteration stops
+/// after the last page, or when one of the caps set with
+/// [Paginator::max_items] and [Paginator::max_pages] is reached. After a
+/// request error, the error is returned and the iteration stops.
+///
+/// ```no_run
+/// use openfoodfacts::{self as off, Output};
+///
+/// # fn main() -> Result<(), off::Error> {
+/// let client = off::v2().build()?;
+/// let query = client.query().criteria("categories", "cheeses", None);
+/// let products = client
+///     .search_iter(query, Some(Output::new().page_size(100)))?
+///     .max_items(250);
+/// for product in products {
+///     println!("{}", product?.code);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Paginator<'a> {
+    client: &'a dyn RequestMethods,
+    request: PageRequest,
+    cursor: Cursor,
+}
+
+impl<'a> Paginator<'a> {
+    pub(crate) fn new(
+        client: &'a dyn RequestMethods,
+        url: Url,
+        params: Params<'_>,
+        output: Option<Output>,
+    ) -> Self {
+        Self {
+            client,
+            request: PageRequest::new(url, params, output.as_ref()),
+            cursor: Cursor::new(output.as_ref()),
+        }
+    }
+
+    /// Stops the iteration after `max_items` products.
+    pub fn max_items(mut self, max_items: usize) -> Self {
+        self.cursor.max_items = Some(max_items);
+        self
+    }
+
+    /// Stops the iteration after `max_pages` pages.
+    pub fn max_pages(mut self, max_pages: usize) -> Self {
+        self.cursor.max_pages = Some(max_pages);
+        self
+    }
+
+    fn fetch(&self, page: usize) -> Result<SearchResponse, Error> {
+        let params = self.request.params(page);
+        let response = self.client.get(self.request.url.clone(), Some(&params))?;
+        decode::<SearchResponse>(response)
+    }
+}
+
+impl Iterator for Paginator<'_> {
+    type Item = Result<Product, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(product) = self.cursor.pop() {
+                return Some(Ok(product));
+            }
+            let page = self.cursor.next_page()?;
+            match self.fetch(page) {
+                Ok(response) => self.cursor.push(response),
+                Err(err) => {
+                    self.cursor.fail();
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+pub use self::stream::AsyncPaginator;
+
+#[cfg(feature = "async")]
+mod stream {
+    use super::{Cursor, PageRequest};
+    use crate::async_client::AsyncOffClient;
+    use crate::client::Error;
+    use crate::output::Output;
+    use crate::product::{Product, SearchResponse};
+    use crate::response::decode_async;
+    use crate::types::{Params, Version};
+    use futures_core::Stream;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use url::Url;
+
+    type PageFuture<'a> = Pin<Box<dyn Future<Output = Result<SearchResponse, Error>> + Send + 'a>>;
+
+    /// The async version of [crate::Paginator], returned by `search_stream()`
+    /// and `products_by_stream()`. Implements [futures_core::Stream].
+    ///
+    /// ```no_run
+    /// use futures_util::StreamExt;
+    /// use openfoodfacts as off;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), off::Error> {
+    /// let client = off::v2().build_async()?;
+    /// let mut products = client
+    ///     .products_by_stream("category", "cheeses", None)?
+    ///     .max_pages(3);
+    /// while let Some(product) = products.next().await {
+    ///     println!("{}", product?.code);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub struct AsyncPaginator<'a, V> {
+        client: &'a AsyncOffClient<V>,
+        request: PageRequest,
+        cursor: Cursor,
+        // The request of the page being fetched.
+        pending: Option<PageFuture<'a>>,
+    }
+
+    impl<'a, V> AsyncPaginator<'a, V>
+    where
+        V: Version + Copy + Sync,
+    {
+        pub(crate) fn new(
+            client: &'a AsyncOffClient<V>,
+            url: Url,
+            params: Params<'_>,
+            output: Option<Output>,
+        ) -> Self {
+            Self {
+                client,
+                request: PageRequest::new(url, params, output.as_ref()),
+                cursor: Cursor::new(output.as_ref()),
+                pending: None,
+            }
+        }
+
+        /// Stops the stream after `max_items` products.
+        pub fn max_items(mut self, max_items: usize) -> Self {
+            self.cursor.max_items = Some(max_items);
+            self
+        }
+
+        /// Stops the stream after `max_pages` pages.
+        pub fn max_pages(mut self, max_pages: usize) -> Self {
+            self.cursor.max_pages = Some(max_pages);
+            self
+        }
+
+        fn fetch(&self, page: usize) -> PageFuture<'a> {
+            let client = self.client;
+            let request = self.request.clone();
+            Box::pin(async move {
+                let params = request.params(page);
+                let response = client.get(request.url.clone(), Some(&params)).await?;
+                decode_async::<SearchResponse>(response).await
+            })
+        }
+    }
+
+    impl<V> Stream for AsyncPaginator<'_, V>
+    where
+        V: Version + Copy + Sync,
+    {
+        type Item = Result<Product, Error>;
+
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            let this = self.get_mut();
+            loop {
+                if let Some(pending) = this.pending.as_mut() {
+                    let result = match pending.as_mut().poll(cx) {
+                        Poll::Ready(result) => result,
+                        Poll::Pending => return Poll::Pending,
+                    };
+                    this.pending = None;
+                    match result {
+                        Ok(response) => this.cursor.push(response),
+                        Err(err) => {
+                            this.cursor.fail();
+                            return Poll::Ready(Some(Err(err)));
+                        }
+                    }
+                }
+                if let Some(product) = this.cursor.pop() {
+                    return Poll::Ready(Some(Ok(product)));
+                }
+                match this.cursor.next_page() {
+                    Some(page) => this.pending = Some(this.fetch(page)),
+                    None => return Poll::Ready(None),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(codes: &[&str], page_count: usize) -> SearchResponse {
+        SearchResponse {
+            page_count: Some(page_count),
+            products: codes
+                .iter()
+                .map(|code| Product {
+                    code: String::from(*code),
+                    ..Product::default()
+                })
+                .collect(),
+            ..SearchResponse::default()
+        }
+    }
+
+    // Drains the cursor, fetching the pages from `pages`.
+    fn drain(mut cursor: Cursor, pages: &[SearchResponse]) -> (Vec<String>, Vec<usize>) {
+        let mut codes = Vec::new();
+        let mut fetched = Vec::new();
+        loop {
+            if let Some(product) = cursor.pop() {
+                codes.push(product.code);
+                continue;
+            }
+            match cursor.next_page() {
+                Some(n) => {
+                    fetched.push(n);
+                    cursor.push(pages[n - 1].clone());
+                }
+                None => return (codes, fetched),
+            }
+        }
+    }
+
+    #[test]
+    fn page_request() {
+        let url = Url::parse("https://world.openfoodfacts.org/api/v2/search").unwrap();
+        let output = Output::new().pagination(3, 50).fields("code");
+        let params = vec![
+            ("categories_tags", String::from("cheeses")),
+            ("page", String::from("3")),
+            ("page_size", String::from("50")),
+        ];
+        let request = PageRequest::new(url, params, Some(&output));
+        assert_eq!(
+            request.params(4),
+            [
+                ("categories_tags", String::from("cheeses")),
+                ("page_size", String::from("50")),
+                ("fields", String::from("code")),
+                ("page", String::from("4")),
+            ]
+        );
+        assert_eq!(Cursor::new(Some(&output)).page, 3);
+        assert_eq!(Cursor::new(None).page, 1);
+    }
+
+    #[test]
+    fn all_pages() {
+        let pages = [page(&["1", "2"], 3), page(&["3", "4"], 3), page(&["5"], 3)];
+        let (codes, fetched) = drain(Cursor::new(None), &pages);
+        assert_eq!(codes, ["1", "2", "3", "4", "5"]);
+        assert_eq!(fetched, [1, 2, 3]);
+    }
+
+    #[test]
+    fn stops_at_empty_page() {
+        let pages = [page(&["1"], 9), page(&[], 9)];
+        let (codes, fetched) = drain(Cursor::new(None), &pages);
+        assert_eq!(codes, ["1"]);
+        assert_eq!(fetched, [1, 2]);
+    }
+
+    #[test]
+    fn caps() {
+        let pages = [page(&["1", "2"], 3), page(&["3", "4"], 3), page(&["5"], 3)];
+
+        let mut cursor = Cursor::new(None);
+        cursor.max_items = Some(3);
+        let (codes, fetched) = drain(cursor, &pages);
+        assert_eq!(codes, ["1", "2", "3"]);
+        assert_eq!(fetched, [1, 2]);
+
+        let mut cursor = Cursor::new(None);
+        cursor.max_pages = Some(1);
+        let (codes, fetched) = drain(cursor, &pages);
+        assert_eq!(codes, ["1", "2"]);
+        assert_eq!(fetched, [1]);
+    }
+
+    #[test]
+    fn fail_stops() {
+        let mut cursor = Cursor::new(None);
+        cursor.push(page(&["1", "2"], 3));
+        cursor.fail();
+        assert!(cursor.pop().is_none());
+        assert!(cursor.next_page().is_none());
+    }
+}
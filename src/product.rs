@@ -0,0 +1,575 @@
+use crate::ecoscore::EcoScore;
+use crate::image::ImageUrls;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// The response of the product endpoint.
+///
+/// `status` is 1 if the product was found, 0 otherwise. In the latter case
+/// `product` is None and `status_verbose` gives the reason (i.e.
+/// "product not found").
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProductResponse {
+    #[serde(deserialize_with = "de::string")]
+    pub code: String,
+    #[serde(deserialize_with = "de::number")]
+    pub status: i32,
+    pub status_verbose: String,
+    pub product: Option<Product>,
+}
+
+impl ProductResponse {
+    /// Returns true if the server found the product.
+    pub fn is_found(&self) -> bool {
+        self.status == 1 && self.product.is_some()
+    }
+}
+
+/// One page of products, as returned by the search and `products_by`
+/// endpoints.
+///
+/// `page_count` is only returned by the search endpoints. Numbers are
+/// sometimes given as strings by API V0.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchResponse {
+    #[serde(deserialize_with = "de::opt_number")]
+    pub count: Option<usize>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub page: Option<usize>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub page_count: Option<usize>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub page_size: Option<usize>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub skip: Option<usize>,
+    pub products: Vec<Product>,
+}
+
+impl SearchResponse {
+    /// Returns true if this is the last page of results, given the number of
+    /// the page that was requested. Responses without counts are the last
+    /// page only if they are empty.
+    pub fn is_last_page(&self, page: usize) -> bool {
+        if self.products.is_empty() {
+            return true;
+        }
+        let page = self.page.unwrap_or(page);
+        if let Some(page_count) = self.page_count {
+            return page >= page_count;
+        }
+        match (self.count, self.page_size) {
+            (Some(count), Some(page_size)) => page * page_size >= count,
+            _ => false,
+        }
+    }
+}
+
+/// A product as returned by the OFF API.
+///
+/// Only the most commonly used fields are typed. All other fields, including
+/// the localized variants of the text fields (i.e. `product_name_fr`), are
+/// kept in the `extra` map. Fields missing from the response (for instance
+/// when the request restricts the fields with [crate::Output::fields]) take
+/// their default value.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Product {
+    #[serde(deserialize_with = "de::string")]
+    pub code: String,
+    /// The main language of the product.
+    pub lc: Option<String>,
+    pub product_name: Option<String>,
+    pub generic_name: Option<String>,
+    pub brands: Option<String>,
+    pub brands_tags: Vec<String>,
+    pub quantity: Option<String>,
+    pub categories: Option<String>,
+    pub categories_tags: Vec<String>,
+    pub labels_tags: Vec<String>,
+    pub countries_tags: Vec<String>,
+    pub origins_tags: Vec<String>,
+    pub packaging_tags: Vec<String>,
+    pub stores_tags: Vec<String>,
+    pub states_tags: Vec<String>,
+    pub ingredients_text: Option<String>,
+    pub ingredients: Vec<Ingredient>,
+    pub ingredients_analysis_tags: Vec<String>,
+    pub additives_tags: Vec<String>,
+    pub allergens: Option<String>,
+    pub allergens_tags: Vec<String>,
+    pub traces_tags: Vec<String>,
+    pub nutriments: Nutriments,
+    /// One of "100g" or "serving".
+    pub nutrition_data_per: Option<String>,
+    pub nutriscore_grade: Option<String>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub nutriscore_score: Option<i32>,
+    pub ecoscore_grade: Option<String>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub ecoscore_score: Option<f64>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub nova_group: Option<u8>,
+    /// Uploaded ("1", "2", ..) and selected ("front_fr", ..) images, by id.
+    pub images: HashMap<String, Image>,
+    /// The URLs of the selected images, by image type ("front", ..).
+    pub selected_images: HashMap<String, SelectedImage>,
+    pub image_url: Option<String>,
+    pub image_small_url: Option<String>,
+    pub image_front_url: Option<String>,
+    pub image_ingredients_url: Option<String>,
+    pub image_nutrition_url: Option<String>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub created_t: Option<i64>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub last_modified_t: Option<i64>,
+    /// All the fields not listed above.
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl Product {
+    /// Returns the builder of the product image URLs.
+    pub fn image_urls(&self) -> ImageUrls<'_> {
+        ImageUrls::from_product(self)
+    }
+
+    /// Returns the Eco-Score breakdown computed by OFF, from the
+    /// `ecoscore_data` field. None if the field is missing or invalid.
+    pub fn ecoscore_data(&self) -> Option<EcoScore> {
+        let data = self.extra.get("ecoscore_data")?;
+        EcoScore::deserialize(data).ok()
+    }
+
+    /// Returns the product name in the given language (`product_name_{lc}`),
+    /// or the default `product_name` if there is no localized name.
+    pub fn product_name_in(&self, lc: &str) -> Option<&str> {
+        self.localized("product_name", lc)
+            .or(self.product_name.as_deref())
+    }
+
+    /// Returns the ingredients text in the given language
+    /// (`ingredients_text_{lc}`), or the default `ingredients_text` if there
+    /// is no localized text.
+    pub fn ingredients_text_in(&self, lc: &str) -> Option<&str> {
+        self.localized("ingredients_text", lc)
+            .or(self.ingredients_text.as_deref())
+    }
+
+    /// Returns all the localized product names as pairs (lc, name).
+    pub fn product_names(&self) -> Vec<(&str, &str)> {
+        self.localized_all("product_name")
+    }
+
+    /// Returns the localized variant `{field}_{lc}` of a text field. Empty
+    /// values are ignored.
+    pub fn localized(&self, field: &str, lc: &str) -> Option<&str> {
+        self.extra
+            .get(&format!("{}_{}", field, lc))
+            .and_then(Value::as_str)
+            .filter(|s| !s.is_empty())
+    }
+
+    // Returns all the non-empty `{field}_{lc}` values. Language codes are
+    // 2 or 3 letters long, which excludes fields such as `product_name_en_imported`.
+    fn localized_all(&self, field: &str) -> Vec<(&str, &str)> {
+        let prefix = format!("{}_", field);
+        let mut names: Vec<(&str, &str)> = self
+            .extra
+            .iter()
+            .filter_map(|(k, v)| {
+                let lc = k.strip_prefix(&prefix)?;
+                if (2..=3).contains(&lc.len()) && lc.chars().all(|c| c.is_ascii_lowercase()) {
+                    v.as_str().filter(|s| !s.is_empty()).map(|s| (lc, s))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+/// The nutrition facts of a product.
+///
+/// The main values per 100g are typed. All other values (per serving, units,
+/// other nutrients) are kept in the `other` map and can be read with
+/// [Nutriments::per_100g], [Nutriments::per_serving] and [Nutriments::unit].
+/// Nutrient names use the OFF ids, i.e. "saturated-fat" or "energy-kcal".
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Nutriments {
+    #[serde(rename = "energy-kj_100g", deserialize_with = "de::opt_number")]
+    pub energy_kj_100g: Option<f64>,
+    #[serde(rename = "energy-kcal_100g", deserialize_with = "de::opt_number")]
+    pub energy_kcal_100g: Option<f64>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub fat_100g: Option<f64>,
+    #[serde(rename = "saturated-fat_100g", deserialize_with = "de::opt_number")]
+    pub saturated_fat_100g: Option<f64>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub carbohydrates_100g: Option<f64>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub sugars_100g: Option<f64>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub fiber_100g: Option<f64>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub proteins_100g: Option<f64>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub salt_100g: Option<f64>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub sodium_100g: Option<f64>,
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+impl Nutriments {
+    /// Returns the value per 100g of the given nutrient.
+    pub fn per_100g(&self, nutrient: &str) -> Option<f64> {
+        match nutrient {
+            "energy-kj" => self.energy_kj_100g,
+            "energy-kcal" => self.energy_kcal_100g,
+            "fat" => self.fat_100g,
+            "saturated-fat" => self.saturated_fat_100g,
+            "carbohydrates" => self.carbohydrates_100g,
+            "sugars" => self.sugars_100g,
+            "fiber" => self.fiber_100g,
+            "proteins" => self.proteins_100g,
+            "salt" => self.salt_100g,
+            "sodium" => self.sodium_100g,
+            _ => self.number(&format!("{}_100g", nutrient)),
+        }
+    }
+
+    /// Returns the value per serving of the given nutrient.
+    pub fn per_serving(&self, nutrient: &str) -> Option<f64> {
+        self.number(&format!("{}_serving", nutrient))
+    }
+
+    /// Returns the unit of the given nutrient, i.e. "g" or "kcal".
+    pub fn unit(&self, nutrient: &str) -> Option<&str> {
+        self.other
+            .get(&format!("{}_unit", nutrient))
+            .and_then(Value::as_str)
+    }
+
+    // Nutriment values are numbers, but some older products store them as
+    // strings.
+    fn number(&self, key: &str) -> Option<f64> {
+        match self.other.get(key)? {
+            Value::Number(n) => n.as_f64(),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// An ingredient parsed from the ingredients text. Ingredients may contain
+/// sub-ingredients.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Ingredient {
+    /// The taxonomy id, i.e. "en:sugar".
+    pub id: Option<String>,
+    pub text: Option<String>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub percent: Option<f64>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub percent_estimate: Option<f64>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub percent_min: Option<f64>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub percent_max: Option<f64>,
+    /// One of "yes", "no" or "maybe".
+    pub vegan: Option<String>,
+    /// One of "yes", "no" or "maybe".
+    pub vegetarian: Option<String>,
+    pub from_palm_oil: Option<String>,
+    pub ingredients: Vec<Ingredient>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// An uploaded or selected product image.
+///
+/// Uploaded images have an `uploaded_t` timestamp and `uploader`. Selected
+/// images reference an uploaded image through `imgid` and have a revision
+/// number `rev`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Image {
+    #[serde(deserialize_with = "de::opt_string")]
+    pub imgid: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub rev: Option<String>,
+    /// The available sizes ("100", "200", "400", "full").
+    pub sizes: HashMap<String, ImageSize>,
+    #[serde(deserialize_with = "de::opt_number")]
+    pub uploaded_t: Option<i64>,
+    pub uploader: Option<String>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// The dimensions of an image, in pixels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImageSize {
+    #[serde(deserialize_with = "de::number")]
+    pub w: u32,
+    #[serde(deserialize_with = "de::number")]
+    pub h: u32,
+}
+
+/// The URLs of a selected image per size, each one keyed by language code.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SelectedImage {
+    pub display: HashMap<String, String>,
+    pub small: HashMap<String, String>,
+    pub thumb: HashMap<String, String>,
+}
+
+// Lenient deserializers. The OFF database is not strict about types: numbers
+// are sometimes given as strings, strings as numbers and missing values as
+// empty strings or null.
+pub(crate) mod de {
+    use serde::de::{DeserializeOwned, Error};
+    use serde::{Deserialize, Deserializer};
+    use serde_json::Value;
+    use std::str::FromStr;
+
+    /// A number given either as a JSON number or as a string. Null and empty
+    /// strings are None.
+    pub fn opt_number<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: FromStr + DeserializeOwned,
+    {
+        match Option::<Value>::deserialize(deserializer)? {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::String(s)) if s.trim().is_empty() => Ok(None),
+            Some(Value::String(s)) => s
+                .trim()
+                .parse()
+                .map(Some)
+                .map_err(|_| D::Error::custom(format!("invalid number: {}", s))),
+            Some(v) => T::deserialize(v).map(Some).map_err(D::Error::custom),
+        }
+    }
+
+    /// Same as [opt_number] but missing values take the default value.
+    pub fn number<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: FromStr + DeserializeOwned + Default,
+    {
+        opt_number(deserializer).map(Option::unwrap_or_default)
+    }
+
+    /// A string given either as a JSON string or as a number.
+    pub fn opt_string<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match Option::<Value>::deserialize(deserializer)? {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::String(s)) => Ok(Some(s)),
+            Some(Value::Number(n)) => Ok(Some(n.to_string())),
+            Some(v) => Err(D::Error::custom(format!("expected a string, got {}", v))),
+        }
+    }
+
+    /// Same as [opt_string] but missing values are the empty string.
+    pub fn string<'de, D>(deserializer: D) -> Result<String, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        opt_string(deserializer).map(Option::unwrap_or_default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_response() {
+        let json = r#"{
+            "count": "50", "page": 2, "page_count": 3, "page_size": "24",
+            "skip": 24, "products": [{"code": "123"}, {"code": 456}]
+        }"#;
+        let response: SearchResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(response.count, Some(50));
+        assert_eq!(response.page_size, Some(24));
+        assert_eq!(response.products.len(), 2);
+        assert_eq!(response.products[1].code, "456");
+        assert!(!response.is_last_page(2));
+        let last = SearchResponse {
+            page: Some(3),
+            ..response.clone()
+        };
+        assert!(last.is_last_page(3));
+
+        // No page_count: computed from count and page_size.
+        let response = SearchResponse {
+            page_count: None,
+            page: None,
+            ..response
+        };
+        assert!(!response.is_last_page(2));
+        assert!(response.is_last_page(3));
+
+        // No counts at all: only an empty page is the last.
+        let response = SearchResponse {
+            count: None,
+            ..response
+        };
+        assert!(!response.is_last_page(3));
+        assert!(SearchResponse::default().is_last_page(1));
+    }
+
+    const PRODUCT: &str = r#"{
+        "code": "3017620422003",
+        "status": 1,
+        "status_verbose": "product found",
+        "product": {
+            "code": "3017620422003",
+            "lc": "fr",
+            "product_name": "Nutella",
+            "product_name_fr": "Nutella",
+            "product_name_de": "Nutella Nuss-Nougat-Creme",
+            "product_name_en": "",
+            "brands": "Ferrero",
+            "brands_tags": ["ferrero"],
+            "quantity": "400 g",
+            "categories_tags": ["en:spreads", "en:sweet-spreads"],
+            "ingredients_text": "Sucre, huile de palme",
+            "ingredients": [
+                {"id": "en:sugar", "text": "Sucre", "percent_estimate": 56.3, "vegan": "yes"},
+                {"id": "en:palm-oil", "text": "huile de palme", "percent_estimate": "22", "from_palm_oil": "yes"}
+            ],
+            "allergens_tags": ["en:milk", "en:nuts"],
+            "nutriments": {
+                "energy-kcal_100g": 539,
+                "fat_100g": 30.9,
+                "saturated-fat_100g": "10.6",
+                "sugars_100g": 56.3,
+                "salt_100g": 0.107,
+                "salt_serving": 0.0161,
+                "salt_unit": "g",
+                "nova-group_100g": 4
+            },
+            "nutriscore_grade": "e",
+            "nutriscore_score": 26,
+            "ecoscore_grade": "d",
+            "ecoscore_score": 23,
+            "nova_group": "4",
+            "images": {
+                "1": {"uploaded_t": "1457680652", "uploader": "openfoodfacts-contributors",
+                      "sizes": {"full": {"w": 2000, "h": 2666}, "100": {"w": 75, "h": 100}}},
+                "front_fr": {"imgid": 13, "rev": "375", "sizes": {"400": {"w": 400, "h": 400}},
+                             "angle": null}
+            },
+            "selected_images": {
+                "front": {"display": {"fr": "https://images.openfoodfacts.org/images/products/301/762/042/2003/front_fr.375.400.jpg"}}
+            },
+            "unknown_field": {"a": [1, 2]}
+        }
+    }"#;
+
+    #[test]
+    fn deserialize_product_response() {
+        let response: ProductResponse = serde_json::from_str(PRODUCT).unwrap();
+        assert!(response.is_found());
+        assert_eq!(response.code, "3017620422003");
+        assert_eq!(response.status_verbose, "product found");
+
+        let product = response.product.unwrap();
+        assert_eq!(product.code, "3017620422003");
+        assert_eq!(product.brands.as_deref(), Some("Ferrero"));
+        assert_eq!(product.quantity.as_deref(), Some("400 g"));
+        assert_eq!(product.categories_tags, ["en:spreads", "en:sweet-spreads"]);
+        assert_eq!(product.allergens_tags, ["en:milk", "en:nuts"]);
+        assert_eq!(product.nutriscore_grade.as_deref(), Some("e"));
+        assert_eq!(product.nutriscore_score, Some(26));
+        assert_eq!(product.ecoscore_score, Some(23.0));
+        assert_eq!(product.nova_group, Some(4));
+        assert_eq!(product.generic_name, None);
+        assert!(product.labels_tags.is_empty());
+    }
+
+    #[test]
+    fn localized_names() {
+        let response: ProductResponse = serde_json::from_str(PRODUCT).unwrap();
+        let product = response.product.unwrap();
+        assert_eq!(
+            product.product_name_in("de"),
+            Some("Nutella Nuss-Nougat-Creme")
+        );
+        // Empty and missing localized names fall back to product_name.
+        assert_eq!(product.product_name_in("en"), Some("Nutella"));
+        assert_eq!(product.product_name_in("it"), Some("Nutella"));
+        assert_eq!(
+            product.product_names(),
+            [("de", "Nutella Nuss-Nougat-Creme"), ("fr", "Nutella")]
+        );
+    }
+
+    #[test]
+    fn nutriments() {
+        let response: ProductResponse = serde_json::from_str(PRODUCT).unwrap();
+        let nutriments = response.product.unwrap().nutriments;
+        assert_eq!(nutriments.energy_kcal_100g, Some(539.0));
+        assert_eq!(nutriments.saturated_fat_100g, Some(10.6));
+        assert_eq!(nutriments.per_100g("saturated-fat"), Some(10.6));
+        assert_eq!(nutriments.per_100g("nova-group"), Some(4.0));
+        assert_eq!(nutriments.per_100g("fiber"), None);
+        assert_eq!(nutriments.per_serving("salt"), Some(0.0161));
+        assert_eq!(nutriments.unit("salt"), Some("g"));
+    }
+
+    #[test]
+    fn ingredients_and_images() {
+        let response: ProductResponse = serde_json::from_str(PRODUCT).unwrap();
+        let product = response.product.unwrap();
+        assert_eq!(product.ingredients.len(), 2);
+        assert_eq!(product.ingredients[0].id.as_deref(), Some("en:sugar"));
+        assert_eq!(product.ingredients[1].percent_estimate, Some(22.0));
+
+        let raw = &product.images["1"];
+        assert_eq!(raw.uploaded_t, Some(1457680652));
+        assert_eq!(raw.sizes["full"], ImageSize { w: 2000, h: 2666 });
+        let front = &product.images["front_fr"];
+        assert_eq!(front.imgid.as_deref(), Some("13"));
+        assert_eq!(front.rev.as_deref(), Some("375"));
+        assert!(product.selected_images["front"].display.contains_key("fr"));
+    }
+
+    #[test]
+    fn unknown_fields_are_kept() {
+        let response: ProductResponse = serde_json::from_str(PRODUCT).unwrap();
+        let product = response.product.unwrap();
+        assert_eq!(
+            product.extra.get("unknown_field"),
+            Some(&serde_json::json!({"a": [1, 2]}))
+        );
+        // Round trip.
+        let json = serde_json::to_string(&product).unwrap();
+        let again: Product = serde_json::from_str(&json).unwrap();
+        assert_eq!(again, product);
+    }
+
+    #[test]
+    fn product_not_found() {
+        let response: ProductResponse = serde_json::from_str(
+            r#"{"code": "0000", "status": 0, "status_verbose": "product not found"}"#,
+        )
+        .unwrap();
+        assert!(!response.is_found());
+        assert_eq!(response.product, None);
+    }
+}
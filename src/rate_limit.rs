@@ -0,0 +1,249 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// The classes of endpoints rate limited by the OFF servers.
+///
+/// # Variants:
+///
+/// * Product - Product reads: `GET /api/{version}/product/{barcode}`.
+/// * Search - Search queries: `GET /cgi/search.pl` and `GET /api/v2/search`.
+/// * Facet - Facet and category pages: `GET /{facet}.json` and
+///   `GET /{facet}/{id}.json`.
+///
+/// All other requests (taxonomies, writes, ...) are not rate limited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndpointClass {
+    Product,
+    Search,
+    Facet,
+}
+
+impl EndpointClass {
+    /// Returns the class of the endpoint of the given URL, if the endpoint is
+    /// rate limited.
+    pub fn of(url: &Url) -> Option<Self> {
+        let segments: Vec<&str> = url
+            .path_segments()
+            .map(|s| s.filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default();
+        match segments.as_slice() {
+            ["cgi", "search.pl"] | ["api", _, "search"] => Some(Self::Search),
+            ["api", _, "product", ..] => Some(Self::Product),
+            ["api", ..] | ["cgi", ..] | ["data", ..] => None,
+            [.., last] if last.ends_with(".json") => Some(Self::Facet),
+            _ => None,
+        }
+    }
+
+    // The index of the class in the per-class arrays.
+    pub(crate) fn index(self) -> usize {
+        match self {
+            Self::Product => 0,
+            Self::Search => 1,
+            Self::Facet => 2,
+        }
+    }
+}
+
+/// A rate limit: at most `requests` requests per `period`. Bursts of up to
+/// `requests` requests are allowed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub period: Duration,
+}
+
+impl RateLimit {
+    /// Creates a limit of `requests` requests per minute.
+    pub fn per_minute(requests: u32) -> Self {
+        Self {
+            requests,
+            period: Duration::from_secs(60),
+        }
+    }
+
+    /// Returns the published OFF quota of the given endpoint class: 100
+    /// requests per minute for products and 10 requests per minute for
+    /// searches and facets.
+    pub fn off_quota(class: EndpointClass) -> Self {
+        match class {
+            EndpointClass::Product => Self::per_minute(100),
+            EndpointClass::Search | EndpointClass::Facet => Self::per_minute(10),
+        }
+    }
+}
+
+// A token bucket. Tokens may go negative: each caller takes a token and waits
+// until its token is refilled, so concurrent callers are served in order.
+#[derive(Debug)]
+struct Bucket {
+    limit: RateLimit,
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: f64::from(limit.requests),
+            last: now,
+        }
+    }
+
+    // Tokens refilled per second.
+    fn rate(&self) -> f64 {
+        f64::from(self.limit.requests) / self.limit.period.as_secs_f64()
+    }
+
+    // Takes a token and returns how long to wait before sending the request.
+    fn take(&mut self, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.last = self.last.max(now);
+        self.tokens = (self.tokens + elapsed * self.rate()).min(f64::from(self.limit.requests));
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate())
+        }
+    }
+}
+
+/// The client-side rate limiter, one token bucket per endpoint class.
+///
+/// Clones share the buckets, so all the threads using the same client share
+/// the quotas.
+#[derive(Debug, Clone)]
+pub(crate) struct RateLimiter {
+    buckets: Arc<[Mutex<Option<Bucket>>; 3]>,
+}
+
+impl Default for RateLimiter {
+    /// Returns a rate limiter with the OFF quotas.
+    fn default() -> Self {
+        Self::new([
+            Some(RateLimit::off_quota(EndpointClass::Product)),
+            Some(RateLimit::off_quota(EndpointClass::Search)),
+            Some(RateLimit::off_quota(EndpointClass::Facet)),
+        ])
+    }
+}
+
+impl RateLimiter {
+    // Creates a limiter with the limits of the product, search and facet
+    // classes. None disables the limit.
+    pub(crate) fn new(limits: [Option<RateLimit>; 3]) -> Self {
+        let now = Instant::now();
+        let buckets = limits.map(|l| {
+            Mutex::new(
+                l.filter(|l| l.requests > 0 && !l.period.is_zero())
+                    .map(|l| Bucket::new(l, now)),
+            )
+        });
+        Self {
+            buckets: Arc::new(buckets),
+        }
+    }
+
+    // Returns the limits of the product, search and facet classes.
+    pub(crate) fn limits(&self) -> [Option<RateLimit>; 3] {
+        [0, 1, 2].map(|i| self.buckets[i].lock().unwrap().as_ref().map(|b| b.limit))
+    }
+
+    /// Returns how long to wait before sending a request to the given URL, and
+    /// counts the request.
+    pub(crate) fn delay(&self, url: &Url) -> Duration {
+        let class = match EndpointClass::of(url) {
+            Some(class) => class,
+            None => return Duration::ZERO,
+        };
+        let mut bucket = self.buckets[class.index()].lock().unwrap();
+        match bucket.as_mut() {
+            Some(bucket) => bucket.take(Instant::now()),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Blocks until a request to the given URL may be sent.
+    pub(crate) fn wait(&self, url: &Url) {
+        let delay = self.delay(url);
+        if !delay.is_zero() {
+            std::thread::sleep(delay);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(url: &str) -> Option<EndpointClass> {
+        EndpointClass::of(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn endpoint_class() {
+        let host = "https://world.openfoodfacts.org";
+        let product = format!("{}/api/v2/product/3017620422003", host);
+        assert_eq!(class(&product), Some(EndpointClass::Product));
+        let search = format!("{}/api/v2/search?code=1,2", host);
+        assert_eq!(class(&search), Some(EndpointClass::Search));
+        let search = format!("{}/cgi/search.pl?json=true", host);
+        assert_eq!(class(&search), Some(EndpointClass::Search));
+        let facet = format!("{}/category/cheeses.json", host);
+        assert_eq!(class(&facet), Some(EndpointClass::Facet));
+        let facet = format!("{}/brands.json", host);
+        assert_eq!(class(&facet), Some(EndpointClass::Facet));
+
+        assert_eq!(
+            class(&format!("{}/data/taxonomies/nova_groups.json", host)),
+            None
+        );
+        assert_eq!(class(&format!("{}/cgi/product_jqm2.pl", host)), None);
+        assert_eq!(class(&format!("{}/cgi/nutrients.pl", host)), None);
+    }
+
+    #[test]
+    fn bucket() {
+        let start = Instant::now();
+        let mut bucket = Bucket::new(RateLimit::per_minute(2), start);
+        // Burst.
+        assert_eq!(bucket.take(start), Duration::ZERO);
+        assert_eq!(bucket.take(start), Duration::ZERO);
+        // One token every 30 seconds.
+        assert_eq!(bucket.take(start), Duration::from_secs(30));
+        assert_eq!(bucket.take(start), Duration::from_secs(60));
+        // Refilled.
+        let later = start + Duration::from_secs(180);
+        assert_eq!(bucket.take(later), Duration::ZERO);
+    }
+
+    #[test]
+    fn limiter() {
+        let limiter = RateLimiter::new([Some(RateLimit::per_minute(1)), None, None]);
+        let product = Url::parse("https://world.openfoodfacts.org/api/v0/product/1").unwrap();
+        let facet = Url::parse("https://world.openfoodfacts.org/brands.json").unwrap();
+        assert_eq!(limiter.delay(&product), Duration::ZERO);
+        assert!(limiter.delay(&product) > Duration::from_secs(59));
+        assert_eq!(limiter.delay(&facet), Duration::ZERO);
+        assert_eq!(limiter.delay(&facet), Duration::ZERO);
+
+        // Clones share the buckets.
+        let clone = limiter.clone();
+        assert!(clone.delay(&product) > Duration::from_secs(119));
+    }
+
+    #[test]
+    fn off_quotas() {
+        assert_eq!(
+            RateLimiter::default().limits(),
+            [
+                Some(RateLimit::per_minute(100)),
+                Some(RateLimit::per_minute(10)),
+                Some(RateLimit::per_minute(10)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,161 @@
+// Turns error responses into errors and decodes the JSON responses.
+use crate::client::{Error, HttpResponse};
+use crate::retry::retry_after;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use std::time::SystemTime;
+use url::Url;
+
+// The length of the body excerpt of Error::ServerError, in characters.
+const EXCERPT_LEN: usize = 200;
+
+// Returns the error of an error response, None if the status is not an
+// error.
+pub(crate) fn status_error(
+    url: &Url,
+    status: StatusCode,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Option<Error> {
+    if !status.is_client_error() && !status.is_server_error() {
+        return None;
+    }
+    let error = match status {
+        StatusCode::NOT_FOUND => Error::NotFound(url.clone()),
+        StatusCode::TOO_MANY_REQUESTS => Error::RateLimited {
+            retry_after: retry_after(headers, SystemTime::now()),
+        },
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Error::Unauthorized { status },
+        _ => Error::ServerError {
+            status,
+            body: String::from_utf8_lossy(body)
+                .chars()
+                .take(EXCERPT_LEN)
+                .collect(),
+        },
+    };
+    Some(error)
+}
+
+// Returns the response, or its error if the status is an error.
+pub(crate) fn check(response: HttpResponse) -> Result<HttpResponse, Error> {
+    let status = response.status();
+    if !status.is_client_error() && !status.is_server_error() {
+        return Ok(response);
+    }
+    let url = response.url().clone();
+    let headers = response.headers().clone();
+    let body = response.bytes()?;
+    Err(status_error(&url, status, &headers, &body).expect("error status"))
+}
+
+/// Async version of [check].
+#[cfg(feature = "async")]
+pub(crate) async fn check_async(response: reqwest::Response) -> Result<reqwest::Response, Error> {
+    let status = response.status();
+    if !status.is_client_error() && !status.is_server_error() {
+        return Ok(response);
+    }
+    let url = response.url().clone();
+    let headers = response.headers().clone();
+    let body = response.bytes().await?;
+    Err(status_error(&url, status, &headers, &body).expect("error status"))
+}
+
+// Deserializes the JSON body. Errors give the path of the invalid value.
+pub(crate) fn from_slice<T: DeserializeOwned>(body: &[u8]) -> Result<T, Error> {
+    let deserializer = &mut serde_json::Deserializer::from_slice(body);
+    serde_path_to_error::deserialize(deserializer).map_err(|err| Error::Decode {
+        path: err.path().to_string(),
+        source: err.into_inner(),
+    })
+}
+
+// Deserializes the JSON body of the response.
+pub(crate) fn decode<T: DeserializeOwned>(response: HttpResponse) -> Result<T, Error> {
+    from_slice(&response.bytes()?)
+}
+
+/// Async version of [decode].
+#[cfg(feature = "async")]
+pub(crate) async fn decode_async<T: DeserializeOwned>(
+    response: reqwest::Response,
+) -> Result<T, Error> {
+    from_slice(&response.bytes().await?)
+}
+
+// Returns Error::ApiStatus if the OFF API status is not 1.
+pub(crate) fn api_status(status: i32, status_verbose: &str) -> Result<(), Error> {
+    if status == 1 {
+        Ok(())
+    } else {
+        Err(Error::ApiStatus {
+            status,
+            status_verbose: String::from(status_verbose),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::product::ProductResponse;
+    use reqwest::header::{HeaderValue, RETRY_AFTER};
+    use std::time::Duration;
+
+    fn error(status: u16, headers: &HeaderMap, body: &str) -> Option<Error> {
+        let url = Url::parse("https://world.openfoodfacts.org/brands.json").unwrap();
+        let status = StatusCode::from_u16(status).unwrap();
+        status_error(&url, status, headers, body.as_bytes())
+    }
+
+    #[test]
+    fn status_errors() {
+        let headers = HeaderMap::new();
+        assert!(error(200, &headers, "").is_none());
+        assert!(error(304, &headers, "").is_none());
+        assert!(
+            matches!(error(404, &headers, ""), Some(Error::NotFound(url)) if url.path() == "/brands.json")
+        );
+        assert!(matches!(
+            error(403, &headers, ""),
+            Some(Error::Unauthorized { status }) if status == StatusCode::FORBIDDEN
+        ));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("30"));
+        assert!(matches!(
+            error(429, &headers, ""),
+            Some(Error::RateLimited { retry_after: Some(d) }) if d == Duration::from_secs(30)
+        ));
+
+        let body = "x".repeat(1000);
+        match error(502, &headers, &body) {
+            Some(Error::ServerError { status, body }) => {
+                assert_eq!(status, StatusCode::BAD_GATEWAY);
+                assert_eq!(body.len(), EXCERPT_LEN);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn decode_path() {
+        let body = br#"{"code": "1", "status": 1, "product": {"code": "1", "nutriments": {"fat_100g": {}}}}"#;
+        match from_slice::<ProductResponse>(body) {
+            Err(Error::Decode { path, .. }) => assert_eq!(path, "product.nutriments.fat_100g"),
+            other => panic!("{:?}", other),
+        }
+        assert!(from_slice::<ProductResponse>(b"{\"status\": 0}").is_ok());
+    }
+
+    #[test]
+    fn api_statuses() {
+        assert!(api_status(1, "product found").is_ok());
+        assert!(matches!(
+            api_status(0, "product not found"),
+            Err(Error::ApiStatus { status: 0, status_verbose }) if status_verbose == "product not found"
+        ));
+    }
+}
@@ -0,0 +1,438 @@
+use crate::cache::{bypass, Cache};
+use crate::client::Error;
+use crate::rate_limit::RateLimiter;
+use crate::response;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use url::Url;
+
+/// The retry policy of GET requests.
+///
+/// Requests failing with a connection error or a timeout, or answered with
+/// a `429 Too Many Requests` or a `5xx` status, are sent again up to
+/// `max_attempts` times in total. The delay before each retry doubles from
+/// `base_delay` up to `max_delay`, with a random jitter of up to half the
+/// delay. A `Retry-After` header sent by the server takes precedence,
+/// unless it asks to wait longer than `max_delay`, in which case the
+/// response is returned as is.
+///
+/// Write requests are never retried: they are not idempotent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    /// Returns the default policy: 3 attempts, delays from 500 ms up to 30 s,
+    /// with jitter.
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the default policy with the given number of attempts.
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            ..Self::default()
+        }
+    }
+
+    /// Returns a policy sending each request only once.
+    pub fn none() -> Self {
+        Self::new(1)
+    }
+
+    /// Sets the delay before the first retry.
+    pub fn base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    /// Sets the maximum delay between two attempts.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Enables or disables the jitter.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    // Returns the backoff delay after the given failed attempt (1-based).
+    // `random` is in [0, 1).
+    fn backoff(&self, attempt: u32, random: f64) -> Duration {
+        let exp = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = self.base_delay.saturating_mul(exp).min(self.max_delay);
+        if self.jitter {
+            delay.mul_f64(1.0 - random / 2.0)
+        } else {
+            delay
+        }
+    }
+
+    // Returns the delay before the next attempt, or None if the outcome of
+    // the given attempt must be returned.
+    fn delay(&self, attempt: u32, outcome: &Outcome<'_>, random: f64) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        match outcome {
+            Outcome::Error(Error::Network(err)) if err.is_connect() || err.is_timeout() => {
+                Some(self.backoff(attempt, random))
+            }
+            Outcome::Error(_) => None,
+            Outcome::Response(status, headers) if is_transient(*status) => {
+                match retry_after(headers, SystemTime::now()) {
+                    Some(delay) if delay > self.max_delay => None,
+                    Some(delay) => Some(delay),
+                    None => Some(self.backoff(attempt, random)),
+                }
+            }
+            Outcome::Response(..) => None,
+        }
+    }
+}
+
+/// A retry attempt, given to the hook set with [crate::OffBuilder::on_retry].
+#[derive(Debug)]
+pub struct RetryEvent<'a> {
+    /// The URL of the request.
+    pub url: &'a Url,
+    /// The number of the attempt that failed, starting at 1.
+    pub attempt: u32,
+    /// The delay before the next attempt.
+    pub delay: Duration,
+    /// The response status, if the server answered.
+    pub status: Option<StatusCode>,
+    /// The request error, if the request failed.
+    pub error: Option<&'a Error>,
+}
+
+/// The hook called before each retry.
+#[derive(Clone)]
+pub(crate) struct RetryHook(pub(crate) Arc<dyn Fn(&RetryEvent) + Send + Sync>);
+
+impl Debug for RetryHook {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("RetryHook")
+    }
+}
+
+// The outcome of an attempt.
+enum Outcome<'a> {
+    Error(&'a Error),
+    Response(StatusCode, &'a HeaderMap),
+}
+
+impl<'a> Outcome<'a> {
+    fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::Error(_) => None,
+            Self::Response(status, _) => Some(*status),
+        }
+    }
+
+    fn error(&self) -> Option<&'a Error> {
+        match self {
+            Self::Error(err) => Some(err),
+            Self::Response(..) => None,
+        }
+    }
+}
+
+// 429 and 5xx responses are worth retrying.
+fn is_transient(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+// Parses the Retry-After header, given either in seconds or as an HTTP date.
+pub(crate) fn retry_after(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+/// The cache, rate limits and retries applied to the GET requests of a
+/// client, and whether error responses are turned into errors.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RequestPolicy {
+    pub(crate) limiter: RateLimiter,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) hook: Option<RetryHook>,
+    pub(crate) cache: Option<Cache>,
+    pub(crate) error_for_status: bool,
+}
+
+impl RequestPolicy {
+    // Returns the delay before the next attempt, if any, and calls the hook.
+    fn next_attempt(&self, url: &Url, attempt: u32, outcome: Outcome<'_>) -> Option<Duration> {
+        let delay = self.retry.delay(attempt, &outcome, fastrand::f64())?;
+        if let Some(RetryHook(ref hook)) = self.hook {
+            hook(&RetryEvent {
+                url,
+                attempt,
+                delay,
+                status: outcome.status(),
+                error: outcome.error(),
+            });
+        }
+        Some(delay)
+    }
+
+    // Returns the cache to use for the given URL.
+    fn cache(&self, url: &Url) -> Option<&Cache> {
+        self.cache.as_ref().filter(|_| !bypass(url))
+    }
+
+    /// Returns the response, or its error if the response has an error status
+    /// and error responses are turned into errors.
+    pub(crate) fn check(
+        &self,
+        response: reqwest::blocking::Response,
+    ) -> Result<reqwest::blocking::Response, Error> {
+        if self.error_for_status {
+            response::check(response)
+        } else {
+            Ok(response)
+        }
+    }
+
+    /// Async version of [RequestPolicy::check].
+    #[cfg(feature = "async")]
+    pub(crate) async fn check_async(
+        &self,
+        response: reqwest::Response,
+    ) -> Result<reqwest::Response, Error> {
+        if self.error_for_status {
+            response::check_async(response).await
+        } else {
+            Ok(response)
+        }
+    }
+
+    /// Sends a GET request to `url` with `send`, which sends the request
+    /// with the given extra headers. The response is taken from the cache if
+    /// possible.
+    pub(crate) fn get(
+        &self,
+        url: &Url,
+        send: impl Fn(HeaderMap) -> Result<reqwest::blocking::Response, Error>,
+    ) -> Result<reqwest::blocking::Response, Error> {
+        let response = match self.cache(url) {
+            Some(cache) => cache.get(url, |headers| self.send(url, || send(headers.clone())))?,
+            None => self.send(url, || send(HeaderMap::new()))?,
+        };
+        self.check(response)
+    }
+
+    /// Async version of [RequestPolicy::get].
+    #[cfg(feature = "async")]
+    pub(crate) async fn get_async<F, Fut>(
+        &self,
+        url: &Url,
+        send: F,
+    ) -> Result<reqwest::Response, Error>
+    where
+        F: Fn(HeaderMap) -> Fut,
+        Fut: std::future::Future<Output = reqwest::Result<reqwest::Response>>,
+    {
+        let send = &send;
+        let response = match self.cache(url) {
+            Some(cache) => {
+                cache
+                    .get_async(url, |headers| {
+                        self.send_async(url, move || send(headers.clone()))
+                    })
+                    .await?
+            }
+            None => self.send_async(url, || send(HeaderMap::new())).await?,
+        };
+        self.check_async(response).await
+    }
+
+    /// Sends a GET request with `send`, waiting for the rate limiter before
+    /// each attempt.
+    pub(crate) fn send(
+        &self,
+        url: &Url,
+        send: impl Fn() -> Result<reqwest::blocking::Response, Error>,
+    ) -> Result<reqwest::blocking::Response, Error> {
+        let mut attempt = 1;
+        loop {
+            self.limiter.wait(url);
+            let result = send();
+            let outcome = match result {
+                Ok(ref response) => Outcome::Response(response.status(), response.headers()),
+                Err(ref err) => Outcome::Error(err),
+            };
+            match self.next_attempt(url, attempt, outcome) {
+                Some(delay) => std::thread::sleep(delay),
+                None => return result,
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Async version of [RequestPolicy::send].
+    #[cfg(feature = "async")]
+    pub(crate) async fn send_async<F, Fut>(
+        &self,
+        url: &Url,
+        send: F,
+    ) -> Result<reqwest::Response, Error>
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = reqwest::Result<reqwest::Response>>,
+    {
+        let mut attempt = 1;
+        loop {
+            let delay = self.limiter.delay(url);
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            let result = send().await.map_err(Error::from);
+            let outcome = match result {
+                Ok(ref response) => Outcome::Response(response.status(), response.headers()),
+                Err(ref err) => Outcome::Error(err),
+            };
+            match self.next_attempt(url, attempt, outcome) {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => return result,
+            }
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::new(4)
+            .base_delay(Duration::from_secs(1))
+            .max_delay(Duration::from_secs(5))
+            .jitter(false)
+    }
+
+    #[test]
+    fn backoff() {
+        let policy = policy();
+        assert_eq!(policy.backoff(1, 0.0), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2, 0.0), Duration::from_secs(2));
+        assert_eq!(policy.backoff(3, 0.0), Duration::from_secs(4));
+        assert_eq!(policy.backoff(4, 0.0), Duration::from_secs(5));
+        assert_eq!(policy.backoff(40, 0.0), Duration::from_secs(5));
+
+        let jitter = policy.jitter(true);
+        assert_eq!(jitter.backoff(2, 0.0), Duration::from_secs(2));
+        assert_eq!(jitter.backoff(2, 0.5), Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn transient_statuses() {
+        let policy = policy();
+        let headers = HeaderMap::new();
+        let delay =
+            |attempt, status| policy.delay(attempt, &Outcome::Response(status, &headers), 0.0);
+        assert_eq!(
+            delay(1, StatusCode::TOO_MANY_REQUESTS),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            delay(2, StatusCode::SERVICE_UNAVAILABLE),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(delay(1, StatusCode::OK), None);
+        assert_eq!(delay(1, StatusCode::NOT_FOUND), None);
+        // Last attempt.
+        assert_eq!(delay(4, StatusCode::BAD_GATEWAY), None);
+        assert_eq!(
+            RetryPolicy::none().delay(
+                1,
+                &Outcome::Response(StatusCode::BAD_GATEWAY, &headers),
+                0.0
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn honors_retry_after() {
+        let policy = policy();
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("3"));
+        let outcome = Outcome::Response(StatusCode::TOO_MANY_REQUESTS, &headers);
+        assert_eq!(policy.delay(1, &outcome, 0.0), Some(Duration::from_secs(3)));
+
+        // Longer than max_delay: give up.
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("3600"));
+        let outcome = Outcome::Response(StatusCode::TOO_MANY_REQUESTS, &headers);
+        assert_eq!(policy.delay(1, &outcome, 0.0), None);
+    }
+
+    #[test]
+    fn retry_after_date() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:10 GMT"),
+        );
+        assert_eq!(retry_after(&headers, now), Some(Duration::from_secs(10)));
+
+        // In the past.
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:27:00 GMT"),
+        );
+        assert_eq!(retry_after(&headers, now), Some(Duration::ZERO));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(retry_after(&headers, now), None);
+    }
+
+    #[test]
+    fn retries_connection_errors() {
+        use std::sync::Mutex;
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let hook_events = Arc::clone(&events);
+        let policy = RequestPolicy {
+            limiter: RateLimiter::new([None; 3]),
+            retry: RetryPolicy::new(3).base_delay(Duration::from_millis(1)),
+            cache: None,
+            hook: Some(RetryHook(Arc::new(move |event: &RetryEvent| {
+                assert!(event.error.is_some());
+                assert_eq!(event.status, None);
+                hook_events.lock().unwrap().push(event.attempt);
+            }))),
+            error_for_status: false,
+        };
+        // Nothing listens on port 1.
+        let url = Url::parse("http://127.0.0.1:1/api/v2/product/1").unwrap();
+        let client = reqwest::blocking::Client::new();
+        let result = policy.send(&url, || Ok(client.get(url.clone()).send()?));
+        assert!(matches!(result, Err(Error::Network(_))));
+        assert_eq!(*events.lock().unwrap(), [1, 2]);
+    }
+}
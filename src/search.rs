@@ -1,7 +1,8 @@
-use crate::client::{RequestMethods, Result, SearchUrl};
+use crate::client::{Error, RequestMethods, Result, SearchUrl};
 use crate::output::Output;
 use crate::types::Params;
 use std::fmt::{self, Display, Formatter};
+use url::Url;
 
 /// Sorting criteria.
 ///
@@ -82,7 +83,7 @@ impl From<u32> for Value {
 
 /// Converts a SearchQuery<S> object into a [crate::types::Params] object.
 pub trait QueryParams {
-    fn params(&self) -> Params;
+    fn params(&self) -> Params<'_>;
 }
 
 impl<S> SearchQuery<S> {
@@ -99,12 +100,25 @@ impl<S> SearchQuery<S> {
         client: &(impl SearchUrl + RequestMethods),
         output: Option<Output>,
     ) -> Result {
+        let (url, params) = Self::request(&params, client, output)?;
+        client.get(url, Some(&params))
+    }
+
+    /// Returns the search URL and the query parameters of the search query.
+    /// Shared by the blocking and async clients.
+    pub(crate) fn request<'a>(
+        params: &'a impl QueryParams,
+        client: &impl SearchUrl,
+        output: Option<Output>,
+    ) -> std::result::Result<(Url, Params<'a>), Error> {
         let url = client.search_url(output.as_ref().and_then(|o| o.locale.as_ref()))?;
         let mut params = params.params();
-        if let Some(output_params) = output.map(|o| o.params(&["page", "page_size", "fields"])) {
+        if let Some(output_params) =
+            output.map(|o| o.params(&["page", "page_size", "fields", "nocache"]))
+        {
             params.extend(output_params);
         }
-        client.get(url, Some(&params))
+        Ok((url, params))
     }
 }
 
@@ -213,7 +227,7 @@ impl SearchQueryV0 {
     ///
     /// * nutrient - The nutrient name. See the [`API docs`].
     /// * op - The comparation operation to perform. One of "lt", "lte", "gt", "gte",
-    ///        "eq".
+    ///   "eq".
     /// * value - The value to compare.
     ///
     /// [`API docs`]: https://openfoodfacts.github.io/api-documentation/#5Filtering
@@ -235,10 +249,8 @@ impl SearchQueryV0 {
     }
 
     pub fn terms(mut self, search_terms: &str) -> Self {
-        self.params.push((
-            format!("search_terms"),
-            Value::from(search_terms),
-        ));
+        self.params
+            .push((String::from("search_terms"), Value::from(search_terms)));
         self
     }
 
@@ -248,7 +260,7 @@ impl SearchQueryV0 {
 }
 
 impl QueryParams for SearchQueryV0 {
-    fn params(&self) -> Params {
+    fn params(&self) -> Params<'_> {
         let mut params: Params = Vec::new();
         for (name, value) in &self.params {
             let v = match value {
@@ -294,7 +306,7 @@ impl SearchQueryV2 {
     ///
     /// * criteria - A valid criteria name. See the [`API docs`].
     /// * value - The criteria value. Use comma for AND, colon for OR and tilde for NOT.
-    ///     See the [`Search V2 API docs`].
+    ///   See the [`Search V2 API docs`].
     /// * lc: Optional language code.
     ///
     /// [`openfoodfacts API docs`]: https://openfoodfacts.github.io/api-documentation/#5Filtering
@@ -323,7 +335,7 @@ impl SearchQueryV2 {
     /// * nutrient - The nutrient name. See the [`API docs`].
     /// * unit - One of the "100g" or "serving".
     /// * op - A comparison operator. One of  '=', '<', '>', `<=', '=>`.
-    ///     See the [`Search V2 API docs`].
+    ///   See the [`Search V2 API docs`].
     /// * value - The value to compare.
     ///
     /// TODO: Verify the <= and => operators.
@@ -357,7 +369,7 @@ impl SearchQueryV2 {
 }
 
 impl QueryParams for SearchQueryV2 {
-    fn params(&self) -> Params {
+    fn params(&self) -> Params<'_> {
         let mut params: Params = Vec::new();
         for (name, value) in &self.params {
             let v = match value {
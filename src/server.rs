@@ -0,0 +1,244 @@
+use crate::flavor::Flavor;
+use crate::locale::Locale;
+use crate::types::Params;
+use url::{ParseError, Url};
+
+/// The OFF server a client sends its requests to.
+///
+/// By default the locale is selected with the subdomain, i.e.
+/// `https://fr.openfoodfacts.org/`. In single host mode, all requests go to the
+/// same host and the locale is given with the `cc` and `lc` query parameters,
+/// i.e. `http://localhost:8080/?cc=fr`.
+///
+/// The domain is given by the flavor, on the production (.org) or staging
+/// (.net) server, unless a custom domain is set.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Server {
+    pub(crate) scheme: String,
+    pub(crate) flavor: Flavor,
+    pub(crate) staging: bool,
+    // A custom domain. Overrides the flavor and staging domain.
+    pub(crate) domain: Option<String>,
+    pub(crate) port: Option<u16>,
+    pub(crate) single_host: bool,
+}
+
+impl Default for Server {
+    /// Returns the production server `https://{locale}.openfoodfacts.org/`.
+    fn default() -> Self {
+        Self {
+            scheme: String::from("https"),
+            flavor: Flavor::default(),
+            staging: false,
+            domain: None,
+            port: None,
+            single_host: false,
+        }
+    }
+}
+
+impl Server {
+    /// Returns the same server for another flavor. Servers with a custom
+    /// domain serve a single flavor and are returned unchanged.
+    pub(crate) fn with_flavor(&self, flavor: Flavor) -> Self {
+        let mut server = self.clone();
+        if server.domain.is_none() {
+            server.flavor = flavor;
+        }
+        server
+    }
+
+    /// Returns the server domain.
+    pub(crate) fn domain(&self) -> String {
+        self.domain
+            .clone()
+            .unwrap_or_else(|| self.flavor.domain(self.staging))
+    }
+
+    /// Returns the base URL of the given locale.
+    pub(crate) fn host_url(&self, locale: &Locale) -> Result<Url, ParseError> {
+        let domain = self.domain();
+        let port = self.port.map_or(String::new(), |p| format!(":{}", p));
+        if !self.single_host {
+            let url = format!("{}://{}.{}{}/", self.scheme, locale, domain, port);
+            return Url::parse(&url);
+        }
+        let mut url = Url::parse(&format!("{}://{}{}/", self.scheme, domain, port))?;
+        // "world" is the server default.
+        if *locale != Locale::default() {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("cc", &locale.cc);
+            if let Some(ref lc) = locale.lc {
+                query.append_pair("lc", lc);
+            }
+        }
+        Ok(url)
+    }
+
+    /// Returns the URL of the static files, i.e. the database exports on
+    /// `https://static.openfoodfacts.org/`. In single host mode, static files
+    /// are served by the same host.
+    pub(crate) fn static_url(&self) -> Result<Url, ParseError> {
+        let domain = self.domain();
+        let port = self.port.map_or(String::new(), |p| format!(":{}", p));
+        if self.single_host {
+            return Url::parse(&format!("{}://{}{}/", self.scheme, domain, port));
+        }
+        Url::parse(&format!("{}://static.{}{}/", self.scheme, domain, port))
+    }
+}
+
+/// Joins `path` to `base`, keeping the query of `base`. In single host mode
+/// the query carries the locale.
+pub(crate) fn join(base: &Url, path: &str) -> Result<Url, ParseError> {
+    let mut url = base.join(path)?;
+    url.set_query(base.query());
+    Ok(url)
+}
+
+/// Appends the query parameters to the URL.
+pub(crate) fn with_params(mut url: Url, params: Option<&Params>) -> Url {
+    if let Some(params) = params.filter(|p| !p.is_empty()) {
+        url.query_pairs_mut().extend_pairs(params);
+    }
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default() {
+        let server = Server::default();
+        assert_eq!(
+            server.host_url(&Locale::default()).unwrap().as_str(),
+            "https://world.openfoodfacts.org/"
+        );
+        assert_eq!(
+            server
+                .host_url(&Locale::new("fr", Some("ca")))
+                .unwrap()
+                .as_str(),
+            "https://fr-ca.openfoodfacts.org/"
+        );
+    }
+
+    #[test]
+    fn flavor() {
+        let server = Server::default().with_flavor(Flavor::Beauty);
+        assert_eq!(
+            server.host_url(&Locale::new("fr", None)).unwrap().as_str(),
+            "https://fr.openbeautyfacts.org/"
+        );
+        let staging = Server {
+            staging: true,
+            ..server
+        };
+        assert_eq!(
+            staging.host_url(&Locale::default()).unwrap().as_str(),
+            "https://world.openbeautyfacts.net/"
+        );
+    }
+
+    #[test]
+    fn custom_domain_ignores_flavor() {
+        let server = Server {
+            domain: Some(String::from("localhost")),
+            ..Server::default()
+        };
+        assert_eq!(server.with_flavor(Flavor::Beauty), server);
+    }
+
+    #[test]
+    fn custom_domain_and_port() {
+        let server = Server {
+            scheme: String::from("http"),
+            domain: Some(String::from("openfoodfacts.localhost")),
+            port: Some(8080),
+            ..Server::default()
+        };
+        assert_eq!(
+            server.host_url(&Locale::new("fr", None)).unwrap().as_str(),
+            "http://fr.openfoodfacts.localhost:8080/"
+        );
+    }
+
+    #[test]
+    fn single_host() {
+        let server = Server {
+            scheme: String::from("http"),
+            domain: Some(String::from("localhost")),
+            port: Some(8080),
+            single_host: true,
+            ..Server::default()
+        };
+        assert_eq!(
+            server.host_url(&Locale::default()).unwrap().as_str(),
+            "http://localhost:8080/"
+        );
+        assert_eq!(
+            server.host_url(&Locale::new("fr", None)).unwrap().as_str(),
+            "http://localhost:8080/?cc=fr"
+        );
+        assert_eq!(
+            server
+                .host_url(&Locale::new("be", Some("nl")))
+                .unwrap()
+                .as_str(),
+            "http://localhost:8080/?cc=be&lc=nl"
+        );
+    }
+
+    #[test]
+    fn static_url() {
+        let server = Server::default().with_flavor(Flavor::Beauty);
+        assert_eq!(
+            server.static_url().unwrap().as_str(),
+            "https://static.openbeautyfacts.org/"
+        );
+        let server = Server {
+            scheme: String::from("http"),
+            domain: Some(String::from("localhost")),
+            port: Some(8080),
+            single_host: true,
+            ..Server::default()
+        };
+        assert_eq!(
+            server.static_url().unwrap().as_str(),
+            "http://localhost:8080/"
+        );
+    }
+
+    #[test]
+    fn join_keeps_query() {
+        let base = Url::parse("http://localhost:8080/?cc=fr").unwrap();
+        let api = join(&base, "api/v2/").unwrap();
+        assert_eq!(api.as_str(), "http://localhost:8080/api/v2/?cc=fr");
+        assert_eq!(
+            join(&api, "product/123").unwrap().as_str(),
+            "http://localhost:8080/api/v2/product/123?cc=fr"
+        );
+
+        let base = Url::parse("https://fr.openfoodfacts.org/").unwrap();
+        assert_eq!(
+            join(&base, "cgi/").unwrap().as_str(),
+            "https://fr.openfoodfacts.org/cgi/"
+        );
+    }
+
+    #[test]
+    fn params() {
+        let base = Url::parse("http://localhost:8080/api/v2/search?cc=fr").unwrap();
+        let params = vec![
+            ("brands_tags", String::from("Nestlé")),
+            ("page", String::from("2")),
+        ];
+        assert_eq!(
+            with_params(base.clone(), Some(&params)).as_str(),
+            "http://localhost:8080/api/v2/search?cc=fr&brands_tags=Nestl%C3%A9&page=2"
+        );
+        assert_eq!(with_params(base.clone(), Some(&Vec::new())), base);
+        assert_eq!(with_params(base.clone(), None), base);
+    }
+}
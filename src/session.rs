@@ -0,0 +1,67 @@
+use reqwest::cookie::{CookieStore, Jar};
+use std::sync::Arc;
+use url::Url;
+
+// The name of the Product Opener session cookie.
+const SESSION_COOKIE: &str = "session";
+
+/// The cookie session of a client built with [crate::OffBuilder::session].
+///
+/// The session cookie set by `cgi/session.pl` is kept in a cookie jar shared
+/// with the underlying HTTP client, so it is sent with every request.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Session {
+    jar: Arc<Jar>,
+}
+
+impl Session {
+    /// Returns the cookie jar to install in the HTTP client.
+    pub(crate) fn jar(&self) -> Arc<Jar> {
+        Arc::clone(&self.jar)
+    }
+
+    /// Returns true if the jar holds an unexpired session cookie for the
+    /// given URL.
+    pub(crate) fn is_active(&self, url: &Url) -> bool {
+        self.jar
+            .cookies(url)
+            .and_then(|header| {
+                header.to_str().ok().map(|cookies| {
+                    cookies
+                        .split(';')
+                        .filter_map(|c| c.trim().split_once('='))
+                        .any(|(name, value)| name == SESSION_COOKIE && !value.is_empty())
+                })
+            })
+            .unwrap_or(false)
+    }
+
+    /// Expires the session cookie of the given URL.
+    pub(crate) fn expire(&self, url: &Url) {
+        let expired = format!("{}=; Max-Age=0; Path=/", SESSION_COOKIE);
+        self.jar.add_cookie_str(&expired, url);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn active_session() {
+        let url = Url::parse("https://world.openfoodfacts.org/cgi/").unwrap();
+        let session = Session::default();
+        assert!(!session.is_active(&url));
+
+        session.jar().add_cookie_str("lang=fr; Path=/", &url);
+        assert!(!session.is_active(&url));
+
+        session
+            .jar()
+            .add_cookie_str("session=user_id&user&user_session&abc; Path=/", &url);
+        assert!(session.is_active(&url));
+
+        session.expire(&url);
+        assert!(!session.is_active(&url));
+    }
+}
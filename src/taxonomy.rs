@@ -0,0 +1,352 @@
+//! Typed taxonomies.
+//!
+//! A [Taxonomy] is parsed from the `data/taxonomies/{taxonomy}.json` files
+//! returned by [crate::OffClient::taxonomy_typed]. Tags are resolved to their
+//! ancestors, descendants, localized names and inherited properties offline.
+//!
+//! ```
+//! use openfoodfacts::taxonomy::Taxonomy;
+//!
+//! let json = r#"{
+//!     "en:dairies": {"name": {"en": "Dairies", "fr": "Produits laitiers"}},
+//!     "en:cheeses": {"name": {"en": "Cheeses"}, "parents": ["en:dairies"], "vegan": {"en": "no"}},
+//!     "en:blue-cheeses": {"name": {"en": "Blue cheeses"}, "parents": ["en:cheeses"]}
+//! }"#;
+//! let taxonomy = Taxonomy::from_slice(json.as_bytes()).unwrap();
+//! assert_eq!(taxonomy.ancestors("en:blue-cheeses"), ["en:cheeses", "en:dairies"]);
+//! assert_eq!(taxonomy.name("en:dairies", "fr"), Some("Produits laitiers"));
+//! assert_eq!(taxonomy.inherited_property("en:blue-cheeses", "vegan", "en"), Some("no"));
+//! ```
+use crate::client::Error;
+use crate::response::from_slice;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// The language-less entries of a taxonomy, i.e. the names of brands or the
+/// properties shared by all languages.
+pub const ANY_LANGUAGE: &str = "xx";
+
+/// A taxonomy entry.
+///
+/// `name` and `synonyms` are keyed by language code. All other fields, i.e.
+/// `wikidata` or `vegan`, are properties keyed by language code as well, and
+/// are kept in the `properties` map.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TaxonomyNode {
+    pub name: HashMap<String, String>,
+    pub synonyms: HashMap<String, Vec<String>>,
+    pub parents: Vec<String>,
+    pub children: Vec<String>,
+    #[serde(flatten)]
+    pub properties: HashMap<String, Value>,
+}
+
+impl TaxonomyNode {
+    /// Returns the value of the given property in the given language, or
+    /// the language-less value.
+    pub fn property(&self, name: &str, lc: &str) -> Option<&str> {
+        let values = self.properties.get(name)?;
+        values
+            .get(lc)
+            .or_else(|| values.get(ANY_LANGUAGE))
+            .and_then(Value::as_str)
+    }
+}
+
+/// A parsed taxonomy: a graph of tags, from the most generic parents to the
+/// most specific children.
+///
+/// Children missing from the JSON file are deduced from the parents.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Taxonomy {
+    nodes: HashMap<String, TaxonomyNode>,
+    // Normalized (lc, name or synonym) -> tag.
+    names: HashMap<(String, String), String>,
+}
+
+impl<'de> Deserialize<'de> for Taxonomy {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        HashMap::<String, TaxonomyNode>::deserialize(deserializer).map(Self::from)
+    }
+}
+
+impl From<HashMap<String, TaxonomyNode>> for Taxonomy {
+    fn from(mut nodes: HashMap<String, TaxonomyNode>) -> Self {
+        let links: Vec<(String, String)> = nodes
+            .iter()
+            .flat_map(|(tag, node)| node.parents.iter().map(move |p| (p.clone(), tag.clone())))
+            .collect();
+        for (parent, child) in links {
+            if let Some(node) = nodes.get_mut(&parent) {
+                if !node.children.contains(&child) {
+                    node.children.push(child);
+                }
+            }
+        }
+        // The first tag in alphabetical order wins when tags share a name.
+        let mut tags: Vec<(&String, &TaxonomyNode)> = nodes.iter().collect();
+        tags.sort_unstable_by_key(|(tag, _)| *tag);
+        let mut names = HashMap::new();
+        for (tag, node) in tags {
+            let synonyms = node
+                .synonyms
+                .iter()
+                .flat_map(|(lc, synonyms)| synonyms.iter().map(move |s| (lc, s)));
+            for (lc, name) in node.name.iter().chain(synonyms) {
+                names
+                    .entry((lc.clone(), normalize(name)))
+                    .or_insert_with(|| tag.clone());
+            }
+        }
+        Self { nodes, names }
+    }
+}
+
+impl Taxonomy {
+    /// Parses a taxonomy JSON file.
+    pub fn from_slice(json: &[u8]) -> Result<Self, Error> {
+        from_slice(json)
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns true if the taxonomy has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns the entry of the given tag.
+    pub fn get(&self, tag: &str) -> Option<&TaxonomyNode> {
+        self.nodes.get(tag)
+    }
+
+    /// Returns true if the taxonomy has the given tag.
+    pub fn contains(&self, tag: &str) -> bool {
+        self.nodes.contains_key(tag)
+    }
+
+    /// Returns all the tags, in no particular order.
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.nodes.keys().map(String::as_str)
+    }
+
+    /// Returns the tags without parents.
+    pub fn roots(&self) -> Vec<&str> {
+        let mut roots: Vec<&str> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.parents.is_empty())
+            .map(|(tag, _)| tag.as_str())
+            .collect();
+        roots.sort_unstable();
+        roots
+    }
+
+    /// Returns the direct parents of the given tag.
+    pub fn parents(&self, tag: &str) -> &[String] {
+        self.nodes.get(tag).map_or(&[], |node| &node.parents)
+    }
+
+    /// Returns the direct children of the given tag.
+    pub fn children(&self, tag: &str) -> &[String] {
+        self.nodes.get(tag).map_or(&[], |node| &node.children)
+    }
+
+    /// Returns all the ancestors of the given tag, closest first (breadth
+    /// first), without duplicates.
+    pub fn ancestors(&self, tag: &str) -> Vec<&str> {
+        self.walk(tag, |node| &node.parents)
+    }
+
+    /// Returns all the descendants of the given tag, closest first (breadth
+    /// first), without duplicates.
+    pub fn descendants(&self, tag: &str) -> Vec<&str> {
+        self.walk(tag, |node| &node.children)
+    }
+
+    /// Returns true if `tag` is `ancestor` or one of its descendants.
+    pub fn is_a(&self, tag: &str, ancestor: &str) -> bool {
+        tag == ancestor || self.ancestors(tag).contains(&ancestor)
+    }
+
+    /// Returns the lowest common ancestor of the given tags: the closest
+    /// ancestor of `a` (or `a` itself) that is also `b` or one of its
+    /// ancestors. None if the tags have no common ancestor.
+    pub fn lowest_common_ancestor<'a>(&'a self, a: &'a str, b: &str) -> Option<&'a str> {
+        let b: HashSet<&str> = self.ancestors(b).into_iter().chain([b]).collect();
+        std::iter::once(a)
+            .chain(self.ancestors(a))
+            .find(|tag| b.contains(tag))
+    }
+
+    /// Returns the name of the given tag in the given language, or its
+    /// language-less name.
+    pub fn name(&self, tag: &str, lc: &str) -> Option<&str> {
+        let names = &self.nodes.get(tag)?.name;
+        names
+            .get(lc)
+            .or_else(|| names.get(ANY_LANGUAGE))
+            .map(String::as_str)
+    }
+
+    /// Returns the tag of the given name or synonym in the given language.
+    /// Case and surrounding whitespace are ignored.
+    pub fn lookup(&self, lc: &str, name: &str) -> Option<&str> {
+        let key = (lc.to_string(), normalize(name));
+        let any = (ANY_LANGUAGE.to_string(), key.1.clone());
+        self.names
+            .get(&key)
+            .or_else(|| self.names.get(&any))
+            .map(String::as_str)
+    }
+
+    /// Returns the value of the given property of the tag, or of its closest
+    /// ancestor having the property.
+    pub fn inherited_property(&self, tag: &str, name: &str, lc: &str) -> Option<&str> {
+        std::iter::once(tag)
+            .chain(self.ancestors(tag))
+            .find_map(|tag| self.nodes.get(tag)?.property(name, lc))
+    }
+
+    // Walks the graph breadth first from the given tag, following `next`.
+    fn walk<'a>(
+        &'a self,
+        tag: &str,
+        next: impl Fn(&'a TaxonomyNode) -> &'a [String],
+    ) -> Vec<&'a str> {
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut tags = Vec::new();
+        let mut queue: VecDeque<&TaxonomyNode> = self.nodes.get(tag).into_iter().collect();
+        while let Some(node) = queue.pop_front() {
+            for other in next(node) {
+                if other != tag && seen.insert(other) {
+                    tags.push(other.as_str());
+                    queue.extend(self.nodes.get(other));
+                }
+            }
+        }
+        tags
+    }
+}
+
+// Names are looked up in lower case, without surrounding whitespace.
+fn normalize(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn taxonomy() -> Taxonomy {
+        Taxonomy::from_slice(include_bytes!("../tests/fixtures/taxonomy.json")).unwrap()
+    }
+
+    #[test]
+    fn graph() {
+        let taxonomy = taxonomy();
+        assert_eq!(taxonomy.len(), 9);
+        assert_eq!(
+            taxonomy.roots(),
+            ["en:beverages", "en:dairies", "en:plant-based-foods"]
+        );
+        assert_eq!(taxonomy.parents("en:cow-cheeses"), ["en:cheeses"]);
+        // Children deduced from the parents.
+        let mut children = taxonomy.children("en:cheeses").to_vec();
+        children.sort();
+        assert_eq!(children, ["en:blue-cheeses", "en:cow-cheeses"]);
+        assert!(taxonomy.children("en:unknown").is_empty());
+    }
+
+    #[test]
+    fn ancestors_and_descendants() {
+        let taxonomy = taxonomy();
+        assert_eq!(
+            taxonomy.ancestors("en:roqueforts"),
+            ["en:blue-cheeses", "en:cheeses", "en:dairies"]
+        );
+        assert_eq!(
+            taxonomy.ancestors("en:plant-based-milks"),
+            [
+                "en:milk-substitutes",
+                "en:beverages",
+                "en:plant-based-foods"
+            ]
+        );
+        assert!(taxonomy.ancestors("en:dairies").is_empty());
+        assert!(taxonomy.ancestors("en:unknown").is_empty());
+
+        let mut descendants = taxonomy.descendants("en:dairies");
+        descendants.sort_unstable();
+        assert_eq!(
+            descendants,
+            [
+                "en:blue-cheeses",
+                "en:cheeses",
+                "en:cow-cheeses",
+                "en:roqueforts"
+            ]
+        );
+        assert!(taxonomy.is_a("en:roqueforts", "en:dairies"));
+        assert!(taxonomy.is_a("en:dairies", "en:dairies"));
+        assert!(!taxonomy.is_a("en:dairies", "en:roqueforts"));
+    }
+
+    #[test]
+    fn common_ancestors() {
+        let taxonomy = taxonomy();
+        let lca = |a, b| taxonomy.lowest_common_ancestor(a, b);
+        assert_eq!(lca("en:roqueforts", "en:cow-cheeses"), Some("en:cheeses"));
+        assert_eq!(lca("en:roqueforts", "en:cheeses"), Some("en:cheeses"));
+        assert_eq!(lca("en:cheeses", "en:roqueforts"), Some("en:cheeses"));
+        assert_eq!(
+            lca("en:plant-based-milks", "en:beverages"),
+            Some("en:beverages")
+        );
+        assert_eq!(lca("en:roqueforts", "en:plant-based-milks"), None);
+    }
+
+    #[test]
+    fn names() {
+        let taxonomy = taxonomy();
+        assert_eq!(taxonomy.name("en:cheeses", "fr"), Some("Fromages"));
+        assert_eq!(taxonomy.name("en:cheeses", "de"), None);
+        assert_eq!(taxonomy.name("en:roqueforts", "de"), Some("Roquefort"));
+        assert_eq!(taxonomy.lookup("fr", " fromage "), Some("en:cheeses"));
+        assert_eq!(taxonomy.lookup("en", "CHEESES"), Some("en:cheeses"));
+        assert_eq!(taxonomy.lookup("de", "roquefort"), Some("en:roqueforts"));
+        assert_eq!(taxonomy.lookup("de", "Käse"), None);
+    }
+
+    #[test]
+    fn properties() {
+        let taxonomy = taxonomy();
+        let cheeses = taxonomy.get("en:cheeses").unwrap();
+        assert_eq!(cheeses.property("wikidata", "en"), Some("Q10943"));
+        assert_eq!(cheeses.property("wikidata", "fr"), None);
+        assert_eq!(
+            taxonomy
+                .get("en:roqueforts")
+                .unwrap()
+                .property("vegan", "en"),
+            None
+        );
+        assert_eq!(
+            taxonomy.inherited_property("en:roqueforts", "vegan", "en"),
+            Some("no")
+        );
+        assert_eq!(
+            taxonomy.inherited_property("en:plant-based-milks", "vegan", "en"),
+            Some("yes")
+        );
+        assert_eq!(
+            taxonomy.inherited_property("en:beverages", "vegan", "en"),
+            None
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use crate::client::{Error, HttpClient, HttpResponse};
+use reqwest::blocking::Request;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Method, ResponseBuilderExt, StatusCode};
+use std::sync::Mutex;
+use url::Url;
+
+/// Sends the requests of an [crate::OffClient].
+///
+/// The client builds the requests and hands them over to its transport. The
+/// default transport is the [reqwest::blocking::Client] built by
+/// [crate::OffBuilder::build]. Other transports are given to
+/// [crate::OffBuilder::build_with], i.e. a [FakeTransport] in tests.
+pub trait Transport: Send + Sync {
+    /// Sends the request and returns the response.
+    fn send(&self, request: Request) -> Result<HttpResponse, Error>;
+
+    /// Returns true if the transport answers without network access, i.e.
+    /// from a local database. The clients built on a local transport skip
+    /// the rate limits of the OFF servers.
+    fn is_local(&self) -> bool {
+        false
+    }
+}
+
+impl Transport for HttpClient {
+    fn send(&self, request: Request) -> Result<HttpResponse, Error> {
+        Ok(self.execute(request)?)
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn send(&self, request: Request) -> Result<HttpResponse, Error> {
+        (**self).send(request)
+    }
+
+    fn is_local(&self) -> bool {
+        (**self).is_local()
+    }
+}
+
+/// A canned response of a [FakeTransport].
+#[derive(Debug, Clone)]
+pub struct FakeResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl FakeResponse {
+    /// Creates an empty response with the given status.
+    pub fn status(status: u16) -> Self {
+        Self {
+            status: StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Creates a `200 OK` JSON response.
+    pub fn json(body: &str) -> Self {
+        Self::status(200)
+            .header("content-type", "application/json")
+            .body(body)
+    }
+
+    /// Adds a header. Invalid headers are ignored.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            self.headers.append(name, value);
+        }
+        self
+    }
+
+    /// Sets the body.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    pub(crate) fn to_response(&self, url: &Url) -> HttpResponse {
+        let mut response = http::Response::builder()
+            .status(self.status)
+            .url(url.clone())
+            .body(self.body.clone())
+            .unwrap();
+        *response.headers_mut() = self.headers.clone();
+        response.into()
+    }
+}
+
+/// A request received by a [FakeTransport].
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub url: Url,
+    pub headers: HeaderMap,
+    /// The body, if any. Streamed bodies are not recorded.
+    pub body: Option<Vec<u8>>,
+}
+
+impl RecordedRequest {
+    /// Returns the value of the given query parameter.
+    pub fn query(&self, name: &str) -> Option<String> {
+        self.url
+            .query_pairs()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.into_owned())
+    }
+}
+
+/// An in-memory transport returning canned responses, for tests.
+///
+/// Responses are routed by URL pattern: the full request URL must match the
+/// pattern, where `*` matches any sequence of characters. The first route
+/// matching the request wins. Requests matching no route get a `404 Not
+/// Found`. All the requests are recorded.
+///
+/// ```
+/// use openfoodfacts::{self as off, FakeResponse, FakeTransport};
+///
+/// # fn main() -> Result<(), off::Error> {
+/// let fake = FakeTransport::new().route(
+///     "*/api/v2/product/3017620422003*",
+///     FakeResponse::json(r#"{"code": "3017620422003", "status": 1, "product": {}}"#),
+/// );
+/// let client = off::v2().build_with(fake);
+/// let response = client.product_typed("3017620422003", None)?;
+/// assert!(response.is_found());
+/// assert_eq!(client.transport().requests().len(), 1);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct FakeTransport {
+    routes: Vec<(String, FakeResponse)>,
+    requests: Mutex<Vec<RecordedRequest>>,
+}
+
+impl FakeTransport {
+    /// Creates a fake transport without routes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a route returning `response` for the URLs matching `pattern`.
+    pub fn route(mut self, pattern: &str, response: FakeResponse) -> Self {
+        self.routes.push((pattern.to_string(), response));
+        self
+    }
+
+    /// Returns the requests received so far.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Transport for FakeTransport {
+    fn send(&self, request: Request) -> Result<HttpResponse, Error> {
+        let url = request.url().clone();
+        self.requests.lock().unwrap().push(RecordedRequest {
+            method: request.method().clone(),
+            url: url.clone(),
+            headers: request.headers().clone(),
+            body: request
+                .body()
+                .and_then(|b| b.as_bytes())
+                .map(<[u8]>::to_vec),
+        });
+        let response = self
+            .routes
+            .iter()
+            .find(|(pattern, _)| matches(pattern, url.as_str()))
+            .map_or_else(|| FakeResponse::status(404), |(_, r)| r.clone());
+        Ok(response.to_response(&url))
+    }
+}
+
+// Matches `text` against a pattern where `*` matches any sequence.
+pub(crate) fn matches(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // The pattern has at least one part.
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match text.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let parts: Vec<&str> = parts.collect();
+    match parts.split_last() {
+        // No '*'.
+        None => rest.is_empty(),
+        Some((last, middle)) => {
+            for part in middle {
+                match rest.find(part) {
+                    Some(i) => rest = &rest[i + part.len()..],
+                    None => return false,
+                }
+            }
+            rest.len() >= last.len() && rest.ends_with(last)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns() {
+        assert!(matches("https://a/b", "https://a/b"));
+        assert!(!matches("https://a/b", "https://a/bc"));
+        assert!(matches("*", "https://a/b"));
+        assert!(matches("*/b", "https://a/b"));
+        assert!(!matches("*/b", "https://a/bc"));
+        assert!(matches(
+            "https://*/product/*",
+            "https://fr.off.org/product/123"
+        ));
+        assert!(matches("*product*123*", "https://x/product/123?fields=a"));
+        assert!(!matches("*product*124*", "https://x/product/123?fields=a"));
+        assert!(matches("a*a", "aa"));
+        assert!(!matches("ab*ba", "aba"));
+    }
+
+    #[test]
+    fn fake_transport() {
+        let fake = FakeTransport::new()
+            .route("*/brands.json", FakeResponse::json("{}"))
+            .route("*", FakeResponse::status(503).header("retry-after", "1"));
+        let url = Url::parse("https://world.openfoodfacts.org/brands.json").unwrap();
+        let response = fake.send(Request::new(Method::GET, url.clone())).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.url(), &url);
+        assert_eq!(response.headers()["content-type"], "application/json");
+
+        let other = Url::parse("https://world.openfoodfacts.org/labels.json").unwrap();
+        let response = fake.send(Request::new(Method::POST, other)).unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()["retry-after"], "1");
+
+        let requests = fake.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].url, url);
+        assert_eq!(requests[1].method, Method::POST);
+    }
+
+    #[test]
+    fn not_found() {
+        let url = Url::parse("https://world.openfoodfacts.org/?cc=fr").unwrap();
+        let fake = FakeTransport::new();
+        let response = fake.send(Request::new(Method::GET, url)).unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(fake.requests()[0].query("cc"), Some(String::from("fr")));
+    }
+}
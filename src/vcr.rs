@@ -0,0 +1,565 @@
+// Record/replay (VCR-style) transports.
+//
+// A Recorder forwards the requests to another transport and writes each
+// request/response pair to a JSON cassette. A Player answers the requests from
+// a cassette, without network access.
+use crate::client::{Error, HttpResponse};
+use crate::response::from_slice;
+use crate::transport::{FakeResponse, Transport};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use reqwest::blocking::Request;
+use reqwest::header::{HeaderMap, AUTHORIZATION};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use url::{form_urlencoded, Url};
+
+// The value replacing the redacted headers and parameters.
+const REDACTED: &str = "[REDACTED]";
+
+/// A list of recorded request/response pairs, stored as a JSON file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    /// Loads the cassette from the given file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        from_slice(&fs::read(path)?)
+    }
+
+    /// Saves the cassette to the given file. Creates the parent directories if
+    /// needed.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = fs::File::create(path)?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self).map_err(std::io::Error::from)?;
+        Ok(())
+    }
+}
+
+/// A recorded request and its response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: CassetteRequest,
+    pub response: CassetteResponse,
+}
+
+/// A recorded request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CassetteRequest {
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<Body>,
+}
+
+/// A recorded response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CassetteResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    pub body: Body,
+}
+
+/// A recorded body: UTF-8 text as is, binary data base64 encoded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Body {
+    Text(String),
+    Base64 { base64: String },
+}
+
+impl Body {
+    fn new(bytes: Vec<u8>) -> Self {
+        match String::from_utf8(bytes) {
+            Ok(text) => Self::Text(text),
+            Err(err) => Self::Base64 {
+                base64: BASE64.encode(err.into_bytes()),
+            },
+        }
+    }
+
+    /// Returns the body bytes. Invalid base64 data yields an empty body.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::Text(text) => text.clone().into_bytes(),
+            Self::Base64 { base64 } => BASE64.decode(base64).unwrap_or_default(),
+        }
+    }
+}
+
+/// The request attributes compared when replaying a cassette.
+///
+/// * method - The HTTP method.
+/// * path - The URL without the query: scheme, host and path.
+/// * query - The query parameters, in any order. Parameters given to
+///   [MatchOn::ignore_param] are not compared.
+///
+/// All the attributes are compared by default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchOn {
+    method: bool,
+    path: bool,
+    query: bool,
+    ignored_params: Vec<String>,
+}
+
+impl Default for MatchOn {
+    fn default() -> Self {
+        Self {
+            method: true,
+            path: true,
+            query: true,
+            ignored_params: Vec::new(),
+        }
+    }
+}
+
+impl MatchOn {
+    /// Compares the HTTP methods.
+    pub fn method(mut self, value: bool) -> Self {
+        self.method = value;
+        self
+    }
+
+    /// Compares the URLs without their query.
+    pub fn path(mut self, value: bool) -> Self {
+        self.path = value;
+        self
+    }
+
+    /// Compares the query parameters.
+    pub fn query(mut self, value: bool) -> Self {
+        self.query = value;
+        self
+    }
+
+    /// Ignores the given query parameter when comparing the queries.
+    pub fn ignore_param(mut self, name: &str) -> Self {
+        self.ignored_params.push(String::from(name));
+        self
+    }
+
+    fn matches(&self, request: &Request, recorded: &CassetteRequest) -> bool {
+        let url = match Url::parse(&recorded.url) {
+            Ok(url) => url,
+            Err(_) => return false,
+        };
+        (!self.method || request.method().as_str() == recorded.method)
+            && (!self.path || without_query(request.url()) == without_query(&url))
+            && (!self.query || self.query_pairs(request.url()) == self.query_pairs(&url))
+    }
+
+    // The sorted query pairs, without the ignored parameters.
+    fn query_pairs(&self, url: &Url) -> Vec<(String, String)> {
+        let mut pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(name, _)| !self.ignored_params.iter().any(|n| n == name))
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        pairs.sort();
+        pairs
+    }
+}
+
+fn without_query(url: &Url) -> &str {
+    &url[..url::Position::AfterPath]
+}
+
+/// The headers and parameters replaced by `[REDACTED]` in the cassettes.
+///
+/// The `Authorization` header and the `password` parameter are redacted by
+/// default. Parameters are redacted in the URL query and in the
+/// `application/x-www-form-urlencoded` bodies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redact {
+    headers: Vec<String>,
+    params: Vec<String>,
+}
+
+impl Default for Redact {
+    fn default() -> Self {
+        Self {
+            headers: vec![String::from(AUTHORIZATION.as_str())],
+            params: vec![String::from("password")],
+        }
+    }
+}
+
+impl Redact {
+    /// Redacts the given header.
+    pub fn header(mut self, name: &str) -> Self {
+        self.headers.push(name.to_ascii_lowercase());
+        self
+    }
+
+    /// Redacts the given query or form parameter.
+    pub fn param(mut self, name: &str) -> Self {
+        self.params.push(String::from(name));
+        self
+    }
+
+    fn headers(&self, headers: &HeaderMap) -> Vec<(String, String)> {
+        headers
+            .iter()
+            .map(|(name, value)| {
+                let value = if self.headers.iter().any(|h| h == name.as_str()) {
+                    String::from(REDACTED)
+                } else {
+                    String::from_utf8_lossy(value.as_bytes()).into_owned()
+                };
+                (String::from(name.as_str()), value)
+            })
+            .collect()
+    }
+
+    fn form<'a>(&self, pairs: form_urlencoded::Parse<'a>) -> String {
+        let mut serializer = form_urlencoded::Serializer::new(String::new());
+        for (name, value) in pairs {
+            if self.params.iter().any(|p| *p == name) {
+                serializer.append_pair(&name, REDACTED);
+            } else {
+                serializer.append_pair(&name, &value);
+            }
+        }
+        serializer.finish()
+    }
+
+    fn url(&self, url: &Url) -> String {
+        let mut url = url.clone();
+        if url.query().is_some() {
+            let query = self.form(url.query_pairs());
+            url.set_query(Some(&query));
+        }
+        url.into()
+    }
+
+    fn request(&self, request: &Request) -> CassetteRequest {
+        let is_form = request
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .is_some_and(|v| v == "application/x-www-form-urlencoded");
+        let body = request.body().and_then(|b| b.as_bytes()).map(|bytes| {
+            if is_form {
+                Body::Text(self.form(form_urlencoded::parse(bytes)))
+            } else {
+                Body::new(bytes.to_vec())
+            }
+        });
+        CassetteRequest {
+            method: String::from(request.method().as_str()),
+            url: self.url(request.url()),
+            headers: self.headers(request.headers()),
+            body,
+        }
+    }
+}
+
+/// A transport recording the requests sent through another transport, and
+/// their responses, to a cassette file.
+///
+/// The cassette is written after each request, replacing any previous file.
+/// See [Redact] for the data kept out of the cassette.
+///
+/// ```no_run
+/// use openfoodfacts::{self as off, Recorder};
+///
+/// # fn main() -> Result<(), off::Error> {
+/// let builder = off::v2();
+/// let recorder = Recorder::new("tests/cassettes/product.json", builder.http_client()?);
+/// let client = builder.build_with(recorder);
+/// client.product("3017620422003", None)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Recorder<T> {
+    path: PathBuf,
+    inner: T,
+    redact: Redact,
+    cassette: Mutex<Cassette>,
+}
+
+impl<T: Transport> Recorder<T> {
+    /// Creates a recorder writing to the given cassette file.
+    pub fn new(path: impl Into<PathBuf>, inner: T) -> Self {
+        Self {
+            path: path.into(),
+            inner,
+            redact: Redact::default(),
+            cassette: Mutex::new(Cassette::default()),
+        }
+    }
+
+    /// Sets the redacted headers and parameters.
+    pub fn redact(mut self, redact: Redact) -> Self {
+        self.redact = redact;
+        self
+    }
+
+    /// Returns the interactions recorded so far.
+    pub fn cassette(&self) -> Cassette {
+        self.cassette.lock().unwrap().clone()
+    }
+}
+
+impl<T: Transport> Transport for Recorder<T> {
+    fn send(&self, request: Request) -> Result<HttpResponse, Error> {
+        let recorded = self.redact.request(&request);
+        let response = self.inner.send(request)?;
+        let url = response.url().clone();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes()?.to_vec();
+
+        let mut cassette = self.cassette.lock().unwrap();
+        cassette.interactions.push(Interaction {
+            request: recorded,
+            response: CassetteResponse {
+                status: status.as_u16(),
+                headers: self.redact.headers(&headers),
+                body: Body::new(body.clone()),
+            },
+        });
+        cassette.save(&self.path)?;
+
+        let response = FakeResponse {
+            status,
+            headers,
+            body,
+        };
+        Ok(response.to_response(&url))
+    }
+}
+
+/// A transport answering the requests from a cassette recorded by a
+/// [Recorder], without network access.
+///
+/// Each request gets the response of the first recorded request it matches
+/// (see [MatchOn]) that was not replayed yet. Once all the matching
+/// interactions have been replayed, the last one is replayed again. Requests
+/// matching no recorded request fail with [Error::NotRecorded].
+///
+/// ```no_run
+/// use openfoodfacts::{self as off, Player};
+///
+/// # fn main() -> Result<(), off::Error> {
+/// let player = Player::open("tests/cassettes/product.json")?;
+/// let client = off::v2().no_rate_limit().build_with(player);
+/// client.product("3017620422003", None)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Player {
+    cassette: Cassette,
+    match_on: MatchOn,
+    // Whether each interaction has been replayed.
+    replayed: Mutex<Vec<bool>>,
+}
+
+impl Player {
+    /// Creates a player replaying the given cassette.
+    pub fn new(cassette: Cassette) -> Self {
+        let replayed = vec![false; cassette.interactions.len()];
+        Self {
+            cassette,
+            match_on: MatchOn::default(),
+            replayed: Mutex::new(replayed),
+        }
+    }
+
+    /// Creates a player replaying the given cassette file.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(Cassette::load(path)?))
+    }
+
+    /// Sets the request attributes to compare.
+    pub fn match_on(mut self, match_on: MatchOn) -> Self {
+        self.match_on = match_on;
+        self
+    }
+
+    fn find(&self, request: &Request) -> Option<&Interaction> {
+        let matching: Vec<usize> = self
+            .cassette
+            .interactions
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| self.match_on.matches(request, &i.request))
+            .map(|(index, _)| index)
+            .collect();
+        let mut replayed = self.replayed.lock().unwrap();
+        let index = matching
+            .iter()
+            .copied()
+            .find(|&index| !replayed[index])
+            .or_else(|| matching.last().copied())?;
+        replayed[index] = true;
+        Some(&self.cassette.interactions[index])
+    }
+}
+
+impl Transport for Player {
+    fn send(&self, request: Request) -> Result<HttpResponse, Error> {
+        let interaction = self
+            .find(&request)
+            .ok_or_else(|| Error::NotRecorded(format!("{} {}", request.method(), request.url())))?;
+        let recorded = &interaction.response;
+        let mut response = FakeResponse::status(recorded.status).body(recorded.body.to_bytes());
+        for (name, value) in &recorded.headers {
+            response = response.header(name, value);
+        }
+        Ok(response.to_response(request.url()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::FakeTransport;
+    use reqwest::header::{HeaderValue, CONTENT_TYPE};
+    use reqwest::Method;
+
+    fn get(url: &str) -> Request {
+        Request::new(Method::GET, Url::parse(url).unwrap())
+    }
+
+    fn interaction(url: &str, body: &str) -> Interaction {
+        Interaction {
+            request: CassetteRequest {
+                method: String::from("GET"),
+                url: String::from(url),
+                headers: Vec::new(),
+                body: None,
+            },
+            response: CassetteResponse {
+                status: 200,
+                headers: vec![(String::from("etag"), String::from("\"1\""))],
+                body: Body::Text(String::from(body)),
+            },
+        }
+    }
+
+    #[test]
+    fn body() {
+        assert_eq!(Body::new(b"abc".to_vec()), Body::Text(String::from("abc")));
+        let binary = Body::new(vec![0xff, 0xd8]);
+        assert!(matches!(binary, Body::Base64 { .. }));
+        assert_eq!(binary.to_bytes(), [0xff, 0xd8]);
+    }
+
+    #[test]
+    fn match_on() {
+        let recorded = interaction("https://a/b?x=1&y=2", "").request;
+        let match_on = MatchOn::default();
+        assert!(match_on.matches(&get("https://a/b?y=2&x=1"), &recorded));
+        assert!(!match_on.matches(&get("https://a/b?x=1"), &recorded));
+        assert!(!match_on.matches(&get("https://a/c?x=1&y=2"), &recorded));
+        let post = Request::new(Method::POST, Url::parse("https://a/b?x=1&y=2").unwrap());
+        assert!(!match_on.matches(&post, &recorded));
+
+        assert!(MatchOn::default()
+            .ignore_param("y")
+            .matches(&get("https://a/b?x=1&y=3"), &recorded));
+        assert!(MatchOn::default()
+            .query(false)
+            .matches(&get("https://a/b"), &recorded));
+        assert!(MatchOn::default()
+            .path(false)
+            .matches(&get("https://c/d?x=1&y=2"), &recorded));
+        assert!(MatchOn::default().method(false).matches(&post, &recorded));
+    }
+
+    #[test]
+    fn redact() {
+        let url = Url::parse("https://a/cgi/session.pl?user_id=u&password=p").unwrap();
+        let mut request = Request::new(Method::POST, url);
+        let headers = request.headers_mut();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Basic dTpw"));
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/x-www-form-urlencoded"),
+        );
+        headers.insert("x-token", HeaderValue::from_static("t"));
+        *request.body_mut() = Some("code=1&password=p".into());
+
+        let recorded = Redact::default().header("X-Token").request(&request);
+        assert_eq!(
+            recorded.url,
+            "https://a/cgi/session.pl?user_id=u&password=%5BREDACTED%5D"
+        );
+        assert_eq!(
+            recorded.headers,
+            [
+                (String::from("authorization"), String::from(REDACTED)),
+                (
+                    String::from("content-type"),
+                    String::from("application/x-www-form-urlencoded")
+                ),
+                (String::from("x-token"), String::from(REDACTED)),
+            ]
+        );
+        assert_eq!(
+            recorded.body,
+            Some(Body::Text(String::from("code=1&password=%5BREDACTED%5D")))
+        );
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let path = std::env::temp_dir()
+            .join(format!("off-vcr-test-{}", std::process::id()))
+            .join("cassette.json");
+        let fake = FakeTransport::new()
+            .route("*/brands.json", FakeResponse::json("{\"count\": 1}"))
+            .route("*", FakeResponse::status(404));
+        let recorder = Recorder::new(&path, fake);
+        let response = recorder.send(get("https://a/brands.json")).unwrap();
+        assert_eq!(response.text().unwrap(), "{\"count\": 1}");
+        recorder.send(get("https://a/labels.json")).unwrap();
+        assert_eq!(recorder.cassette().interactions.len(), 2);
+
+        let player = Player::open(&path).unwrap();
+        let response = player.send(get("https://a/brands.json")).unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.url().as_str(), "https://a/brands.json");
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        assert_eq!(response.text().unwrap(), "{\"count\": 1}");
+        let response = player.send(get("https://a/labels.json")).unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        assert!(matches!(
+            player.send(get("https://a/stores.json")),
+            Err(Error::NotRecorded(request)) if request == "GET https://a/stores.json"
+        ));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn replay_in_order() {
+        let player = Player::new(Cassette {
+            interactions: vec![
+                interaction("https://a/b", "1"),
+                interaction("https://a/c", "2"),
+                interaction("https://a/b", "3"),
+            ],
+        });
+        let body = |url| player.send(get(url)).unwrap().text().unwrap();
+        assert_eq!(body("https://a/b"), "1");
+        assert_eq!(body("https://a/b"), "3");
+        // The last matching interaction is replayed again.
+        assert_eq!(body("https://a/b"), "3");
+        assert_eq!(body("https://a/c"), "2");
+    }
+}
@@ -0,0 +1,237 @@
+use crate::product::de;
+use crate::types::Params;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+
+/// The basis of the nutrition facts of a product edit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NutritionDataPer {
+    Hundred,
+    Serving,
+}
+
+impl Display for NutritionDataPer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let per = match self {
+            Self::Hundred => "100g",
+            Self::Serving => "serving",
+        };
+        write!(f, "{}", per)
+    }
+}
+
+/// Builds the fields of a product write request. If the product does not
+/// exist, it is created.
+///
+/// Only the fields that are set are sent and updated. Setting the same field
+/// twice keeps the last value.
+///
+/// ```
+/// use openfoodfacts::write::{NutritionDataPer, ProductEdit};
+///
+/// let edit = ProductEdit::new("0000000000000")
+///     .product_name("Chocolate spread")
+///     .brands("Brand A,Brand B")
+///     .quantity("400 g")
+///     .nutrition_data_per(NutritionDataPer::Hundred)
+///     .nutriment("sugars", 56.3, "g")
+///     .ingredients_text("en", "Sugar, palm oil, hazelnuts");
+/// ```
+#[derive(Debug, Clone)]
+pub struct ProductEdit {
+    code: String,
+    fields: Vec<(String, String)>,
+}
+
+impl ProductEdit {
+    /// Creates a new, empty, edit of the product with the given barcode.
+    pub fn new(code: &str) -> Self {
+        Self {
+            code: String::from(code),
+            fields: Vec::new(),
+        }
+    }
+
+    /// Returns the barcode of the edited product.
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// Sets the main language of the product.
+    pub fn lang(self, lc: &str) -> Self {
+        self.set("lang", lc)
+    }
+
+    /// Sets the product name in the main language.
+    pub fn product_name(self, name: &str) -> Self {
+        self.set("product_name", name)
+    }
+
+    /// Sets the product name in the given language (`product_name_{lc}`).
+    pub fn product_name_in(self, lc: &str, name: &str) -> Self {
+        self.set(format!("product_name_{}", lc), name)
+    }
+
+    /// Sets the brands. Multiple brands are comma-separated.
+    pub fn brands(self, brands: &str) -> Self {
+        self.set("brands", brands)
+    }
+
+    /// Sets the quantity, i.e. "400 g".
+    pub fn quantity(self, quantity: &str) -> Self {
+        self.set("quantity", quantity)
+    }
+
+    /// Sets the categories. Multiple categories are comma-separated.
+    pub fn categories(self, categories: &str) -> Self {
+        self.set("categories", categories)
+    }
+
+    /// Sets the serving size, i.e. "15 g".
+    pub fn serving_size(self, serving_size: &str) -> Self {
+        self.set("serving_size", serving_size)
+    }
+
+    /// Sets whether the nutriment values are given per 100g or per serving.
+    pub fn nutrition_data_per(self, per: NutritionDataPer) -> Self {
+        self.set("nutrition_data_per", per.to_string())
+    }
+
+    /// Sets the value of a nutriment, producing the pairs
+    ///
+    /// ```code
+    /// nutriment_<name>=<value>
+    /// nutriment_<name>_unit=<unit>
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * name - The nutriment id, i.e. "energy-kcal", "fat" or "saturated-fat".
+    /// * value - The nutriment value, per 100g or per serving according to
+    ///   [ProductEdit::nutrition_data_per].
+    /// * unit - The unit of the value, i.e. "g", "mg" or "kcal".
+    pub fn nutriment(self, name: &str, value: f64, unit: &str) -> Self {
+        self.set(format!("nutriment_{}", name), value.to_string())
+            .set(format!("nutriment_{}_unit", name), unit)
+    }
+
+    /// Sets the ingredients text in the given language (`ingredients_text_{lc}`).
+    pub fn ingredients_text(self, lc: &str, text: &str) -> Self {
+        self.set(format!("ingredients_text_{}", lc), text)
+    }
+
+    /// Sets the comment attached to the edit in the product history.
+    pub fn comment(self, comment: &str) -> Self {
+        self.set("comment", comment)
+    }
+
+    /// Returns the form parameters of the edit, starting with the code.
+    pub fn params(&self) -> Params<'_> {
+        let mut params: Params = vec![("code", self.code.clone())];
+        for (name, value) in &self.fields {
+            params.push((name, value.clone()));
+        }
+        params
+    }
+
+    // Sets or replaces a field.
+    fn set(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let name = name.into();
+        let value = value.into();
+        match self.fields.iter_mut().find(|(n, _)| *n == name) {
+            Some(field) => field.1 = value,
+            None => self.fields.push((name, value)),
+        }
+        self
+    }
+}
+
+/// The response of a write request.
+///
+/// `status` is 1 on success. Otherwise `status_verbose` gives the reason,
+/// i.e. "no code or invalid code".
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WriteResponse {
+    #[serde(deserialize_with = "de::number")]
+    pub status: i32,
+    pub status_verbose: String,
+}
+
+impl WriteResponse {
+    /// Returns true if the fields were saved.
+    pub fn is_ok(&self) -> bool {
+        self.status == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn params() {
+        let edit = ProductEdit::new("0000000000000")
+            .lang("fr")
+            .product_name("Pâte à tartiner")
+            .product_name_in("en", "Chocolate spread")
+            .brands("Brand A,Brand B")
+            .quantity("400 g")
+            .categories("Spreads")
+            .nutrition_data_per(NutritionDataPer::Serving)
+            .serving_size("15 g")
+            .nutriment("energy-kcal", 81.0, "kcal")
+            .nutriment("sugars", 8.5, "g")
+            .ingredients_text("fr", "Sucre, huile de palme")
+            .comment("test");
+
+        assert_eq!(edit.code(), "0000000000000");
+        assert_eq!(
+            &edit.params(),
+            &[
+                ("code", String::from("0000000000000")),
+                ("lang", String::from("fr")),
+                ("product_name", String::from("Pâte à tartiner")),
+                ("product_name_en", String::from("Chocolate spread")),
+                ("brands", String::from("Brand A,Brand B")),
+                ("quantity", String::from("400 g")),
+                ("categories", String::from("Spreads")),
+                ("nutrition_data_per", String::from("serving")),
+                ("serving_size", String::from("15 g")),
+                ("nutriment_energy-kcal", String::from("81")),
+                ("nutriment_energy-kcal_unit", String::from("kcal")),
+                ("nutriment_sugars", String::from("8.5")),
+                ("nutriment_sugars_unit", String::from("g")),
+                ("ingredients_text_fr", String::from("Sucre, huile de palme")),
+                ("comment", String::from("test")),
+            ]
+        );
+    }
+
+    #[test]
+    fn last_value_wins() {
+        let edit = ProductEdit::new("0000000000000")
+            .quantity("400 g")
+            .quantity("750 g");
+        assert_eq!(
+            &edit.params(),
+            &[
+                ("code", String::from("0000000000000")),
+                ("quantity", String::from("750 g")),
+            ]
+        );
+    }
+
+    #[test]
+    fn write_response() {
+        let ok: WriteResponse =
+            serde_json::from_str(r#"{"status": 1, "status_verbose": "fields saved"}"#).unwrap();
+        assert!(ok.is_ok());
+
+        let invalid: WriteResponse =
+            serde_json::from_str(r#"{"status": 0, "status_verbose": "no code or invalid code"}"#)
+                .unwrap();
+        assert!(!invalid.is_ok());
+        assert_eq!(invalid.status_verbose, "no code or invalid code");
+    }
+}
@@ -0,0 +1,171 @@
+// Integration tests for the async client. Run with `--features async`.
+//
+// The async client sends its requests with reqwest, so the tests run against
+// a local server standing in for the OFF servers instead of going online.
+#![cfg(feature = "async")]
+use futures_util::StreamExt;
+use openfoodfacts::{self as off, Locale, Output};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+// A local server answering each request with the JSON returned by `respond`
+// for the request target, i.e. `/brands.json?page=22`. Returns its port and
+// the targets of the requests received.
+fn serve(respond: fn(&str) -> String) -> (u16, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let targets = Arc::new(Mutex::new(Vec::new()));
+    let received = Arc::clone(&targets);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut lines = BufReader::new(&stream).lines();
+            let request_line = lines.next().unwrap().unwrap();
+            for line in lines {
+                if line.unwrap().is_empty() {
+                    break;
+                }
+            }
+            let target = request_line.split(' ').nth(1).unwrap().to_string();
+            let body = respond(&target);
+            received.lock().unwrap().push(target);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\n\
+                 content-length: {}\r\nconnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+    (port, targets)
+}
+
+// The builder of a client sending its requests to the local server on `port`.
+macro_rules! local {
+    ($builder:expr, $port:expr) => {
+        $builder
+            .scheme("http")
+            .domain("127.0.0.1")
+            .port($port)
+            .single_host(true)
+            .no_rate_limit()
+    };
+}
+
+#[tokio::test]
+async fn taxonomy() {
+    let (port, targets) = serve(|_| String::from(r#"{"en:1": {}}"#));
+    let client = local!(off::v0(), port).build_async().unwrap();
+    let response = client.taxonomy("nova_groups").await.unwrap();
+    assert_eq!(
+        response.url().as_str(),
+        format!("http://127.0.0.1:{}/data/taxonomies/nova_groups.json", port)
+    );
+    assert!(response.status().is_success());
+    assert_eq!(
+        *targets.lock().unwrap(),
+        ["/data/taxonomies/nova_groups.json"]
+    );
+}
+
+#[tokio::test]
+async fn facet_params() {
+    let (port, targets) = serve(|_| String::from(r#"{"tags": []}"#));
+    let client = local!(off::v0(), port).build_async().unwrap();
+    let output = Output::new()
+        .locale(Locale::new("fr", None))
+        .page(22)
+        .fields("url")
+        .nocache(true);
+    let response = client.facet("brands", Some(output)).await.unwrap();
+    assert!(response.status().is_success());
+    assert_eq!(
+        *targets.lock().unwrap(),
+        ["/brands.json?cc=fr&page=22&fields=url&nocache=true"]
+    );
+}
+
+#[tokio::test]
+async fn product_typed() {
+    let (port, targets) = serve(|_| {
+        String::from(
+            r#"{"code": "069000019832", "status": 1, "product": {"code": "069000019832"}}"#,
+        )
+    });
+    let client = local!(off::v2(), port).build_async().unwrap();
+    let response = client.product_typed("069000019832", None).await.unwrap(); // Diet Pepsi
+    assert!(response.is_found());
+    assert_eq!(*targets.lock().unwrap(), ["/api/v2/product/069000019832"]);
+}
+
+#[tokio::test]
+async fn products() {
+    let (port, targets) = serve(|_| String::from(r#"{"count": 0, "products": []}"#));
+    let client = local!(off::v2(), port).build_async().unwrap();
+    let output = Output::new().fields("code");
+    let response = client
+        .products("069000019832,3017620422003", Some(output))
+        .await
+        .unwrap();
+    assert_eq!(response.url().path(), "/api/v2/search");
+    assert!(response.status().is_success());
+    assert!(targets.lock().unwrap()[0].contains("code=069000019832%2C3017620422003"));
+}
+
+#[tokio::test]
+async fn search_v0() {
+    let (port, targets) = serve(|_| String::from(r#"{"count": 0, "products": []}"#));
+    let client = local!(off::v0(), port).build_async().unwrap();
+    let query = client
+        .query()
+        .criteria("brands", "contains", "Nestlé")
+        .nutrient("fiber", "lt", 500);
+    let response = client.search(query, None).await.unwrap();
+    assert_eq!(response.url().path(), "/cgi/search.pl");
+    assert!(response.status().is_success());
+    assert!(targets.lock().unwrap()[0].contains("tag_1=Nestl%C3%A9"));
+}
+
+#[tokio::test]
+async fn search_v2() {
+    let (port, targets) = serve(|_| String::from(r#"{"count": 0, "products": []}"#));
+    let client = local!(off::v2(), port).build_async().unwrap();
+    let query = client
+        .query()
+        .criteria("brands", "Nestlé", Some("fr"))
+        .nutrient_100g("fiber", "<", 500);
+    let response = client.search(query, None).await.unwrap();
+    assert_eq!(response.url().path(), "/api/v2/search");
+    assert!(response.status().is_success());
+    assert!(targets.lock().unwrap()[0].contains("brands_tags_fr=Nestl%C3%A9"));
+}
+
+#[tokio::test]
+async fn search_stream() {
+    // Pages of 5 products, out of 3 pages.
+    let (port, targets) = serve(|target| {
+        let page = if target.contains("page=2") { 2 } else { 1 };
+        let products: Vec<String> = (0..5)
+            .map(|i| format!(r#"{{"code": "{}{}"}}"#, page, i))
+            .collect();
+        format!(
+            r#"{{"count": 15, "page": {}, "page_count": 3, "page_size": 5, "products": [{}]}}"#,
+            page,
+            products.join(",")
+        )
+    });
+    let client = local!(off::v2(), port).build_async().unwrap();
+    let query = client.query().criteria("categories", "cheeses", None);
+    let output = Output::new().page_size(5).fields("code");
+    let products: Vec<_> = client
+        .search_stream(query, Some(output))
+        .unwrap()
+        .max_pages(2)
+        .collect()
+        .await;
+    assert_eq!(products.len(), 10);
+    assert!(products.iter().all(Result::is_ok));
+    assert_eq!(targets.lock().unwrap().len(), 2);
+}
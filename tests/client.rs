@@ -1,10 +1,44 @@
 // Integration tests using API v1.
-use openfoodfacts::{self as off, Locale, Output};
+use openfoodfacts::{self as off, Locale, Output, Player, Recorder, Transport};
 use reqwest::StatusCode;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+
+// The client of the test `name`, built with `builder` on the cassette
+// `tests/cassettes/{name}.json`. With `OFF_VCR=record`, the requests are sent
+// to the OFF servers through the HTTP client of `builder` and recorded.
+// Otherwise the cassette is replayed, and a missing cassette fails the test
+// instead of going online.
+//
+// The committed cassettes hold minimal responses written for these tests, in
+// place of recorded OFF responses. Record them again with
+// `OFF_VCR=record cargo test --test client` to replay the real responses.
+macro_rules! client {
+    ($builder:expr, $name:expr) => {{
+        let builder = $builder;
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/cassettes")
+            .join(format!("{}.json", $name));
+        let transport: Box<dyn Transport> = if std::env::var("OFF_VCR").as_deref() == Ok("record") {
+            Box::new(Recorder::new(path, builder.http_client().unwrap()))
+        } else {
+            let player = Player::open(&path).unwrap_or_else(|err| {
+                panic!(
+                    "cannot replay {}: {}. Record it with OFF_VCR=record.",
+                    path.display(),
+                    err
+                )
+            });
+            Box::new(player)
+        };
+        builder.build_with(transport)
+    }};
+}
 
 #[test]
 fn taxonomy() {
-    let client = off::v0().build().unwrap();
+    let client = client!(off::v0(), "taxonomy");
     let response = client.taxonomy("nova_groups").unwrap();
     assert_eq!(
         response.url().as_str(),
@@ -13,9 +47,17 @@ fn taxonomy() {
     assert!(response.status().is_success());
 }
 
+#[test]
+fn taxonomy_typed() {
+    let client = client!(off::v0(), "taxonomy_typed");
+    let taxonomy = client.taxonomy_typed("nova_groups").unwrap();
+    assert!(!taxonomy.is_empty());
+    assert!(taxonomy.contains("en:4-ultra-processed-food-and-drink-products"));
+}
+
 #[test]
 fn taxonomy_not_found() {
-    let client = off::v0().build().unwrap();
+    let client = client!(off::v0(), "taxonomy_not_found");
     let response = client.taxonomy("not_found").unwrap();
     assert_eq!(
         response.url().as_str(),
@@ -26,7 +68,7 @@ fn taxonomy_not_found() {
 
 #[test]
 fn facet() {
-    let client = off::v0().build().unwrap();
+    let client = client!(off::v0(), "facet");
     let response = client.facet("brands", None).unwrap();
     assert_eq!(
         response.url().as_str(),
@@ -37,7 +79,7 @@ fn facet() {
 
 #[test]
 fn facet_params() {
-    let client = off::v0().build().unwrap();
+    let client = client!(off::v0(), "facet_params");
     let output = Output::new()
         .locale(Locale::new("fr", None))
         .page(22)
@@ -53,7 +95,7 @@ fn facet_params() {
 
 #[test]
 fn categories() {
-    let client = off::v0().build().unwrap();
+    let client = client!(off::v0(), "categories");
     let response = client.categories(None).unwrap();
     assert_eq!(
         response.url().as_str(),
@@ -64,7 +106,7 @@ fn categories() {
 
 #[test]
 fn categories_params() {
-    let client = off::v0().build().unwrap();
+    let client = client!(off::v0(), "categories_params");
     // Accepts only the locale parameter.
     let output = Output::new().locale(Locale::new("fr", None)).page(22);
     let response = client.categories(Some(output)).unwrap();
@@ -77,7 +119,7 @@ fn categories_params() {
 
 #[test]
 fn nutrients() {
-    let client = off::v0().build().unwrap();
+    let client = client!(off::v0(), "nutrients");
     let response = client.nutrients(None).unwrap();
     assert_eq!(
         response.url().as_str(),
@@ -88,7 +130,7 @@ fn nutrients() {
 
 #[test]
 fn nutrients_params() {
-    let client = off::v0().build().unwrap();
+    let client = client!(off::v0(), "nutrients_params");
     // Accepts only the locale parameter.
     let output = Output::new().locale(Locale::new("fr", None)).page(22);
     let response = client.nutrients(Some(output)).unwrap();
@@ -101,7 +143,7 @@ fn nutrients_params() {
 
 #[test]
 fn products_by_facet() {
-    let client = off::v0().build().unwrap();
+    let client = client!(off::v0(), "products_by_facet");
     let response = client
         .products_by("additive", "e322-lecithins", None)
         .unwrap();
@@ -114,7 +156,7 @@ fn products_by_facet() {
 
 #[test]
 fn products_by_facet_params() {
-    let client = off::v0().build().unwrap();
+    let client = client!(off::v0(), "products_by_facet_params");
     let output = Output::new()
         .locale(Locale::new("fr", None))
         .pagination(22, 20)
@@ -129,9 +171,22 @@ fn products_by_facet_params() {
     assert!(response.status().is_success());
 }
 
+#[test]
+fn products_by_iter() {
+    let client = client!(off::v0(), "products_by_iter");
+    let output = Output::new().page_size(5).fields("code");
+    let products: Vec<_> = client
+        .products_by_iter("category", "cheeses", Some(output))
+        .unwrap()
+        .max_items(12)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(products.len(), 12);
+}
+
 #[test]
 fn products_by_category() {
-    let client = off::v0().build().unwrap();
+    let client = client!(off::v0(), "products_by_category");
     let response = client.products_by("category", "cheeses", None).unwrap();
     assert_eq!(
         response.url().as_str(),
@@ -142,7 +197,7 @@ fn products_by_category() {
 
 #[test]
 fn products_by_category_params() {
-    let client = off::v0().build().unwrap();
+    let client = client!(off::v0(), "products_by_category_params");
     let output = Output::new()
         .locale(Locale::new("fr", None))
         .pagination(22, 20)
@@ -160,7 +215,7 @@ fn products_by_category_params() {
 
 #[test]
 fn product() {
-    let client = off::v0().build().unwrap();
+    let client = client!(off::v0(), "product");
     let response = client.product("069000019832", None).unwrap(); // Diet Pepsi
     assert_eq!(
         response.url().as_str(),
@@ -171,7 +226,7 @@ fn product() {
 
 #[test]
 fn product_params() {
-    let client = off::v0().build().unwrap();
+    let client = client!(off::v0(), "product_params");
     // Accepts only the locale and fields parameters.
     let output = Output::new()
         .locale(Locale::new("fr", None))
@@ -185,9 +240,26 @@ fn product_params() {
     assert!(response.status().is_success());
 }
 
+#[test]
+fn product_typed() {
+    let client = client!(off::v0(), "product_typed");
+    let response = client.product_typed("069000019832", None).unwrap(); // Diet Pepsi
+    assert!(response.is_found());
+    let product = response.product.unwrap();
+    assert_eq!(product.code, "069000019832");
+}
+
+#[test]
+fn product_typed_not_found() {
+    let client = client!(off::v0(), "product_typed_not_found");
+    let response = client.product_typed("0000000000000", None).unwrap();
+    assert_eq!(response.status, 0);
+    assert!(response.product.is_none());
+}
+
 #[test]
 fn search_v0() {
-    let client = off::v0().build().unwrap();
+    let client = client!(off::v0(), "search_v0");
     let query = client
         .query()
         .criteria("brands", "contains", "Nestlé")
@@ -205,7 +277,7 @@ fn search_v0() {
 
 #[test]
 fn search_v2() {
-    let client = off::v2().build().unwrap();
+    let client = client!(off::v2(), "search_v2");
     let query = client
         .query()
         .criteria("brands", "Nestlé", Some("fr"))
@@ -217,3 +289,66 @@ fn search_v2() {
     assert_eq!(response.url().path(), "/api/v2/search");
     assert!(response.status().is_success());
 }
+
+#[test]
+fn record_redacts_credentials() {
+    // A server answering the requests, and returning their head and body.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = std::thread::spawn(move || {
+        let mut received = Vec::new();
+        for stream in listener.incoming().take(2) {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().to_lowercase();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some(value) = line.strip_prefix("content-length: ") {
+                    length = value.parse().unwrap();
+                }
+                received.push(line);
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            received.push(String::from_utf8(body).unwrap());
+            let response = "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\n\
+                            content-length: 13\r\nconnection: close\r\n\r\n{\"status\": 1}";
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+        received
+    });
+
+    let path = std::env::temp_dir()
+        .join(format!("off-record-test-{}", std::process::id()))
+        .join("cassette.json");
+    let builder = off::v2()
+        .staging()
+        .auth("user", "secret")
+        .scheme("http")
+        .domain("127.0.0.1")
+        .port(port)
+        .single_host(true)
+        .no_rate_limit();
+    let recorder = Recorder::new(&path, builder.http_client().unwrap());
+    let client = builder.build_with(recorder);
+    client.taxonomy("nova_groups").unwrap();
+    let edit = client.product_edit("0000000000000").quantity("400 g");
+    client.save_product(edit).unwrap();
+
+    // The staging Basic auth is sent, and the password in the form.
+    let received = server.join().unwrap();
+    assert!(received.contains(&String::from("authorization: basic b2zmom9mzg==")));
+    assert!(received.iter().any(|line| line.contains("password=secret")));
+
+    // Neither is in the cassette.
+    let cassette = std::fs::read_to_string(&path).unwrap();
+    assert!(!cassette.to_lowercase().contains("authorization"));
+    assert!(!cassette.contains("secret"));
+    assert!(cassette.contains("password=%5BREDACTED%5D"));
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}